@@ -0,0 +1,281 @@
+//! Detects the format of a book from its content rather than its extension, so that files
+//! the reader cannot open are rejected with a reason before they reach the frontend.
+//!
+//! ZIP based formats are told apart by the EPUB mimetype and container, an FB2 entry or comic
+//! images, Mobipocket files by their PDB header and FB2 by the root element of the XML.
+
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use serde::{ser::Serializer, Serialize};
+
+type Result<T> = std::result::Result<T, Error>;
+
+const SAMPLE_SIZE: usize = 8192;
+const EPUB_MIMETYPE: &str = "application/epub+zip";
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp", "avif"];
+
+// The formats of the frontend's Book type, plus plain text that the frontend converts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum BookFormat {
+    Epub,
+    Pdf,
+    Mobi, // Also AZW and AZW3
+    Cbz,
+    Fb2,
+    Fbz,
+    Txt,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("damaged ZIP archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("the file is empty")]
+    Empty,
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+    #[error("the ZIP archive contains neither an EPUB, an FB2 book nor comic images")]
+    UnknownArchive,
+    #[error("the XML document is {0}, not an FB2 book")]
+    UnknownXml(String),
+    #[error("the file is an HTML page, not a book")]
+    Html,
+    #[error("the file is not in a supported book format")]
+    Unknown,
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_ref())
+    }
+}
+
+fn is_image(name: &str) -> bool {
+    name.rsplit_once('.')
+        .is_some_and(|(_, ext)| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn detect_zip<R: Read + Seek>(reader: R) -> Result<BookFormat> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    // The OCF container stores the mimetype first, but not every EPUB follows the spec
+    if let Ok(mut mimetype) = archive.by_name("mimetype") {
+        let mut content = String::new();
+        mimetype.read_to_string(&mut content)?;
+        if content.trim() == EPUB_MIMETYPE {
+            return Ok(BookFormat::Epub);
+        }
+    }
+    let names = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .collect::<Vec<_>>();
+    if names.contains(&"META-INF/container.xml") {
+        return Ok(BookFormat::Epub);
+    }
+    if names
+        .iter()
+        .any(|name| name.to_lowercase().ends_with(".fb2"))
+    {
+        return Ok(BookFormat::Fbz);
+    }
+    // Comic archives may also contain ComicInfo.xml and the like next to the pages
+    if names.iter().any(|name| is_image(name)) {
+        return Ok(BookFormat::Cbz);
+    }
+    Err(Error::UnknownArchive)
+}
+
+// Finds the root element of an XML document in the first bytes of the file.
+fn xml_root(sample: &[u8]) -> Option<String> {
+    let mut reader = Reader::from_reader(sample);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e) | Event::Empty(e)) => {
+                return Some(String::from_utf8_lossy(e.local_name().as_ref()).into_owned())
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            Ok(_) => buf.clear(),
+        }
+    }
+}
+
+fn detect_text(sample: &[u8], path: &Path) -> Result<BookFormat> {
+    let text = sample.strip_prefix(b"\xef\xbb\xbf").unwrap_or(sample);
+    let start = text
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(text.len());
+    let text = &text[start..];
+    let head = String::from_utf8_lossy(&text[..text.len().min(256)]).to_lowercase();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return Err(Error::Html);
+    }
+    if text.starts_with(b"<") {
+        return match xml_root(text) {
+            Some(root) if root == "FictionBook" => Ok(BookFormat::Fb2),
+            Some(root) if root.eq_ignore_ascii_case("html") => Err(Error::Html),
+            Some(root) => Err(Error::UnknownXml(format!("<{root}>"))),
+            None => Err(Error::Unknown),
+        };
+    }
+    // Plain text has no signature, so it is only accepted as such when named like it
+    let is_txt = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("txt"));
+    if is_txt && !sample.contains(&0) {
+        return Ok(BookFormat::Txt);
+    }
+    Err(Error::Unknown)
+}
+
+pub fn detect_book_format(path: &Path) -> Result<BookFormat> {
+    detect_format(File::open(path)?, path)
+}
+
+// Detects the format of the book in `reader`, which is named `path`. The name only matters
+// for plain text, which has no signature.
+pub fn detect_format<R: Read + Seek>(mut reader: R, path: &Path) -> Result<BookFormat> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    reader
+        .by_ref()
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    if sample.is_empty() {
+        return Err(Error::Empty);
+    }
+
+    if sample.starts_with(b"PK\x03\x04") || sample.starts_with(b"PK\x05\x06") {
+        reader.rewind()?;
+        return detect_zip(reader);
+    }
+    if sample.starts_with(b"Rar!\x1a\x07") {
+        return Err(Error::Unsupported("a RAR archive (CBR)"));
+    }
+    if sample.starts_with(b"7z\xbc\xaf\x27\x1c") {
+        return Err(Error::Unsupported("a 7-Zip archive (CB7)"));
+    }
+    // PDF readers accept the header anywhere in the first kilobyte
+    if sample[..sample.len().min(1024)]
+        .windows(5)
+        .any(|window| window == b"%PDF-")
+    {
+        return Ok(BookFormat::Pdf);
+    }
+    // Palm database header with the type and creator at offset 60
+    if sample.len() >= 68 && matches!(&sample[60..68], b"BOOKMOBI" | b"TEXtREAd") {
+        return Ok(BookFormat::Mobi);
+    }
+    if sample.starts_with(b"TPZ") {
+        return Err(Error::Unsupported("a Topaz book (AZW1)"));
+    }
+    if sample.starts_with(b"\xea\x44\x52\x4d") {
+        return Err(Error::Unsupported("a DRM protected KFX book"));
+    }
+    detect_text(&sample, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn detect(data: &[u8], name: &str) -> Result<BookFormat> {
+        detect_format(Cursor::new(data), Path::new(name))
+    }
+
+    #[test]
+    fn detects_zip_based_formats() {
+        let epub = zip(&[("mimetype", EPUB_MIMETYPE.as_bytes())]);
+        assert_eq!(detect(&epub, "book.zip").unwrap(), BookFormat::Epub);
+        let epub = zip(&[("META-INF/container.xml", b"<container/>")]);
+        assert_eq!(detect(&epub, "book.epub").unwrap(), BookFormat::Epub);
+        let fbz = zip(&[("book.FB2", b"<FictionBook/>")]);
+        assert_eq!(detect(&fbz, "book.fbz").unwrap(), BookFormat::Fbz);
+        let cbz = zip(&[("ComicInfo.xml", b"<ComicInfo/>"), ("01.JPG", b"")]);
+        assert_eq!(detect(&cbz, "comic.cbz").unwrap(), BookFormat::Cbz);
+        let other = zip(&[("notes.txt", b"hello")]);
+        assert!(matches!(
+            detect(&other, "book.epub"),
+            Err(Error::UnknownArchive)
+        ));
+    }
+
+    #[test]
+    fn detects_signatures() {
+        assert_eq!(detect(b"%PDF-1.7\n", "a.pdf").unwrap(), BookFormat::Pdf);
+        assert_eq!(detect(b"\n\n%PDF-1.4", "a.pdf").unwrap(), BookFormat::Pdf);
+        let mut mobi = vec![0; 68];
+        mobi[60..68].copy_from_slice(b"BOOKMOBI");
+        assert_eq!(detect(&mobi, "a.azw3").unwrap(), BookFormat::Mobi);
+        assert!(matches!(
+            detect(b"Rar!\x1a\x07\x00", "a.cbr"),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(matches!(detect(b"", "a.epub"), Err(Error::Empty)));
+    }
+
+    #[test]
+    fn detects_fb2_and_rejects_other_xml() {
+        let fb2 = "\u{feff}<?xml version=\"1.0\"?>\n<FictionBook xmlns=\"http://www.gribuser.ru/xml/fictionbook/2.0\">";
+        assert_eq!(
+            detect_text(fb2.as_bytes(), Path::new("a.fb2")).unwrap(),
+            BookFormat::Fb2
+        );
+        let svg = b"<?xml version=\"1.0\"?><svg/>";
+        assert!(matches!(
+            detect_text(svg, Path::new("a.fb2")),
+            Err(Error::UnknownXml(root)) if root == "<svg>"
+        ));
+        assert!(matches!(
+            detect_text(b"  <!DOCTYPE html><html>", Path::new("a.txt")),
+            Err(Error::Html)
+        ));
+        assert!(matches!(
+            detect_text(b"<?xml version=\"1.0\"?><html/>", Path::new("a.xhtml")),
+            Err(Error::Html)
+        ));
+    }
+
+    #[test]
+    fn accepts_plain_text_only_by_name() {
+        let text = b"Chapter 1\n\nIt was a dark and stormy night.";
+        assert_eq!(
+            detect_text(text, Path::new("a.TXT")).unwrap(),
+            BookFormat::Txt
+        );
+        assert!(matches!(
+            detect_text(text, Path::new("a.epub")),
+            Err(Error::Unknown)
+        ));
+        assert!(matches!(
+            detect_text(b"text\0binary", Path::new("a.txt")),
+            Err(Error::Unknown)
+        ));
+    }
+}
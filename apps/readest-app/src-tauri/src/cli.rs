@@ -0,0 +1,662 @@
+//! Command line subcommands of the desktop app.
+//!
+//! `list` and `export-notes` only read the library and run without starting the app, so they
+//! work in scripts and on machines without a display. `import` and `open` need the frontend
+//! and are handed to the running instance, or to a new one, as a `cli-request`. Without a
+//! subcommand the arguments are files to open, as before.
+//!
+//! The arguments are only parsed here, and USAGE is the one description of them.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::event_queue::EventQueue;
+use crate::open_with::{self, BookPaths};
+use crate::timestamp::{now_millis, rfc3339};
+
+const USAGE: &str = "\
+Usage: readest [--custom-oauth[=<bool>] | --no-custom-oauth] [--] [<file>...]
+       readest <command> [<args>]
+
+Commands:
+  import <path>... [--recursive]     Add books or folders of books to the library
+  list [--json]                      List the books in the library
+  open <book|path> [--cfi <cfi> | --page <page>]
+                                     Open a book from the library or a file
+  export-notes <book> [--format md|json] [--output <file>]
+                                     Export the highlights and notes of a book
+  help                               Show this message
+
+<book> is the ID of a book as shown by `readest list`, or its title. Files named like a
+command are opened after `--`, as in `readest -- list`.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotesFormat {
+    Markdown,
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Help,
+    Import {
+        paths: Vec<PathBuf>,
+        recursive: bool,
+    },
+    List {
+        json: bool,
+    },
+    Open {
+        target: String,
+        cfi: Option<String>,
+        page: Option<u32>,
+    },
+    ExportNotes {
+        book: String,
+        format: NotesFormat,
+        output: Option<PathBuf>,
+    },
+}
+
+impl Command {
+    // Whether the command runs without the app.
+    pub fn is_headless(&self) -> bool {
+        matches!(
+            self,
+            Command::Help | Command::List { .. } | Command::ExportNotes { .. }
+        )
+    }
+}
+
+// An option of a subcommand: long name, short name and whether it takes a value.
+type OptionSpec = (&'static str, Option<char>, bool);
+
+// Splits the arguments of a subcommand into positional arguments and option values, where
+// options without a value are recorded with an empty value.
+fn split_args(
+    command: &str,
+    args: &[String],
+    options: &[OptionSpec],
+) -> Result<(Vec<String>, HashMap<&'static str, String>), String> {
+    let mut positional = Vec::new();
+    let mut values = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref().cloned());
+            break;
+        }
+        let (spec, inline) = if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            (options.iter().find(|(long, _, _)| *long == name), inline)
+        } else if arg.len() == 2 && arg.starts_with('-') {
+            let short = arg.chars().nth(1);
+            (options.iter().find(|(_, s, _)| *s == short), None)
+        } else {
+            positional.push(arg.clone());
+            continue;
+        };
+        let Some(&(name, _, takes_value)) = spec else {
+            return Err(format!("unknown option {arg} for {command}"));
+        };
+        let value = match (takes_value, inline) {
+            (true, Some(value)) => value.to_string(),
+            (true, None) => args
+                .next()
+                .cloned()
+                .ok_or_else(|| format!("--{name} needs a value"))?,
+            (false, Some(_)) => return Err(format!("--{name} does not take a value")),
+            (false, None) => String::new(),
+        };
+        values.insert(name, value);
+    }
+    Ok((positional, values))
+}
+
+fn single_positional(
+    command: &str,
+    what: &str,
+    mut positional: Vec<String>,
+) -> Result<String, String> {
+    match positional.len() {
+        0 => Err(format!("{command} needs a {what}")),
+        1 => Ok(positional.remove(0)),
+        _ => Err(format!("{command} takes a single {what}")),
+    }
+}
+
+// Parses the subcommand in `argv`, which starts with the program. Returns None when there is
+// no subcommand, in which case the arguments are files to open.
+pub fn parse(argv: &[String]) -> Result<Option<Command>, String> {
+    // Flags before the subcommand are for the app, such as --custom-oauth, and only files
+    // follow `--`
+    let mut args = argv
+        .iter()
+        .skip(1)
+        .skip_while(|arg| arg.starts_with('-') && !matches!(arg.as_str(), "--" | "--help" | "-h"));
+    let Some(name) = args.next().filter(|name| *name != "--") else {
+        return Ok(None);
+    };
+    let rest = args.cloned().collect::<Vec<_>>();
+    let command = match name.as_str() {
+        "help" | "--help" | "-h" => Command::Help,
+        "import" => {
+            let (paths, options) = split_args(name, &rest, &[("recursive", Some('r'), false)])?;
+            if paths.is_empty() {
+                return Err("import needs at least one file or folder".into());
+            }
+            Command::Import {
+                paths: paths.into_iter().map(PathBuf::from).collect(),
+                recursive: options.contains_key("recursive"),
+            }
+        }
+        "list" => {
+            let (positional, options) = split_args(name, &rest, &[("json", None, false)])?;
+            if let Some(arg) = positional.first() {
+                return Err(format!("unexpected argument {arg} for list"));
+            }
+            Command::List {
+                json: options.contains_key("json"),
+            }
+        }
+        "open" => {
+            let (positional, mut options) =
+                split_args(name, &rest, &[("cfi", None, true), ("page", None, true)])?;
+            let page = match options.remove("page") {
+                Some(page) => match page.parse::<u32>() {
+                    Ok(page) if page > 0 => Some(page),
+                    _ => return Err(format!("invalid page {page}, expected a number from 1")),
+                },
+                None => None,
+            };
+            Command::Open {
+                target: single_positional(name, "book or file", positional)?,
+                cfi: options.remove("cfi").filter(|cfi| !cfi.is_empty()),
+                page,
+            }
+        }
+        "export-notes" => {
+            let (positional, mut options) = split_args(
+                name,
+                &rest,
+                &[("format", Some('f'), true), ("output", Some('o'), true)],
+            )?;
+            let format = match options.remove("format").as_deref() {
+                None | Some("md" | "markdown") => NotesFormat::Markdown,
+                Some("json") => NotesFormat::Json,
+                Some(format) => return Err(format!("unsupported format {format}, use md or json")),
+            };
+            Command::ExportNotes {
+                book: single_positional(name, "book", positional)?,
+                format,
+                output: options.remove("output").map(PathBuf::from),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(command))
+}
+
+// The subset of the frontend's Book type needed by the commands.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LibraryBook {
+    hash: String,
+    format: String,
+    title: String,
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    created_at: f64,
+    #[serde(default)]
+    updated_at: f64,
+    deleted_at: Option<f64>,
+    progress: Option<(f64, f64)>, // Current and total page
+}
+
+// Where the frontend keeps the books, the same as `$APPDATA/Readest/Books`.
+fn books_dir(identifier: &str) -> Result<PathBuf, String> {
+    dirs::data_dir()
+        .map(|dir| dir.join(identifier).join("Readest").join("Books"))
+        .ok_or_else(|| "cannot find the data directory".into())
+}
+
+fn load_library(books_dir: &Path) -> Result<Vec<LibraryBook>, String> {
+    let path = books_dir.join("library.json");
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("cannot read {}: {e}", path.display())),
+    };
+    let books = serde_json::from_slice::<Vec<Value>>(&data)
+        .map_err(|e| format!("cannot parse {}: {e}", path.display()))?;
+    Ok(books
+        .into_iter()
+        .filter_map(|book| serde_json::from_value::<LibraryBook>(book).ok())
+        .filter(|book| book.deleted_at.is_none())
+        .collect())
+}
+
+// The file of a book is named after its original title, so it is found by its extension.
+fn book_file(books_dir: &Path, book: &LibraryBook) -> Option<PathBuf> {
+    let ext = book.format.to_lowercase();
+    std::fs::read_dir(books_dir.join(&book.hash))
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| e.eq_ignore_ascii_case(&ext))
+        })
+}
+
+// Finds a book by its ID, its title or, if only one book matches, part of its title.
+fn find_book<'a>(library: &'a [LibraryBook], query: &str) -> Result<&'a LibraryBook, String> {
+    if let Some(book) = library.iter().find(|book| book.hash == query) {
+        return Ok(book);
+    }
+    let query_lower = query.to_lowercase();
+    let mut matches = library
+        .iter()
+        .filter(|book| book.title.to_lowercase() == query_lower)
+        .collect::<Vec<_>>();
+    if matches.is_empty() {
+        matches = library
+            .iter()
+            .filter(|book| book.title.to_lowercase().contains(&query_lower))
+            .collect();
+    }
+    match matches.as_slice() {
+        [] => Err(format!("no book in the library matches {query}")),
+        [book] => Ok(book),
+        books => {
+            let candidates = books
+                .iter()
+                .map(|book| format!("  {}  {}", book.hash, book.title))
+                .collect::<Vec<_>>()
+                .join("\n");
+            Err(format!(
+                "{query} matches several books, use one of their IDs:\n{candidates}"
+            ))
+        }
+    }
+}
+
+fn list(books_dir: &Path, json: bool) -> Result<String, String> {
+    let mut library = load_library(books_dir)?;
+    library.sort_by(|a, b| b.updated_at.total_cmp(&a.updated_at));
+    if json {
+        let books = library
+            .iter()
+            .map(|book| {
+                json!({
+                    "id": book.hash,
+                    "title": book.title,
+                    "author": book.author,
+                    "format": book.format,
+                    "progress": book.progress.map(|(current, total)| json!([current, total])),
+                    "createdAt": rfc3339(book.created_at as u64),
+                    "updatedAt": rfc3339(book.updated_at as u64),
+                    "path": book_file(books_dir, book),
+                })
+            })
+            .collect::<Vec<_>>();
+        return serde_json::to_string_pretty(&books).map_err(|e| e.to_string());
+    }
+    // One tab-separated line per book, so that the output is easy to process
+    Ok(library
+        .iter()
+        .map(|book| {
+            let progress = match book.progress {
+                Some((current, total)) if total > 0.0 => format!("{:.0}%", current * 100.0 / total),
+                _ => "-".into(),
+            };
+            format!(
+                "{}\t{}\t{progress}\t{}\t{}",
+                book.hash, book.format, book.title, book.author
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+// Orders CFIs by their steps. This is exact for the steps and offsets of the CFIs that the
+// reader creates, which is all that the export needs without parsing the book.
+fn compare_cfi(a: &str, b: &str) -> Ordering {
+    let steps = |cfi: &str| {
+        cfi.split(|c: char| !c.is_ascii_digit())
+            .filter(|step| !step.is_empty())
+            .map(|step| step.parse::<u64>().unwrap_or(u64::MAX))
+            .collect::<Vec<_>>()
+    };
+    steps(a).cmp(&steps(b))
+}
+
+fn export_notes(books_dir: &Path, query: &str, format: NotesFormat) -> Result<String, String> {
+    let library = load_library(books_dir)?;
+    let book = find_book(&library, query)?;
+    let path = books_dir.join(&book.hash).join("config.json");
+    let config = match std::fs::read(&path) {
+        Ok(data) => serde_json::from_slice::<Value>(&data)
+            .map_err(|e| format!("cannot parse {}: {e}", path.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Value::Null,
+        Err(e) => return Err(format!("cannot read {}: {e}", path.display())),
+    };
+    let mut notes = config
+        .get("booknotes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|note| note.get("deletedAt").map_or(true, Value::is_null))
+        .collect::<Vec<_>>();
+    let field = |note: &Value, key: &str| {
+        note.get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    notes.sort_by(|a, b| compare_cfi(&field(a, "cfi"), &field(b, "cfi")));
+
+    if format == NotesFormat::Json {
+        let export = json!({
+            "id": book.hash,
+            "title": book.title,
+            "author": book.author,
+            "notes": notes,
+        });
+        return serde_json::to_string_pretty(&export).map_err(|e| e.to_string());
+    }
+    // The same layout as the export from the reader, without the chapters of the notes
+    let mut lines = vec![
+        format!("# {}", book.title),
+        format!("**Author**: {}", book.author),
+        String::new(),
+        format!(
+            "**Exported from Readest**: {}",
+            &rfc3339(now_millis())[..10]
+        ),
+        String::new(),
+        "---".into(),
+        String::new(),
+        "## Highlights & Annotations".into(),
+        String::new(),
+    ];
+    for note in notes {
+        let text = field(note, "text");
+        let comment = field(note, "note");
+        if text.is_empty() && comment.is_empty() {
+            continue;
+        }
+        if !text.is_empty() {
+            lines.push(format!("> \"{text}\""));
+        }
+        if !comment.is_empty() {
+            lines.push(format!("**Note**:: {comment}"));
+        }
+        lines.push(String::new());
+    }
+    Ok(lines.join("\n"))
+}
+
+// Lets a release build on Windows, which has no console of its own, print to the terminal
+// that it was started from.
+#[cfg(target_os = "windows")]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+// Runs a headless command and returns the exit code of the process.
+pub fn run_headless(command: &Command, identifier: &str) -> i32 {
+    #[cfg(target_os = "windows")]
+    attach_console();
+
+    let result = match command {
+        Command::Help => Ok(USAGE.to_string()),
+        Command::List { json } => books_dir(identifier).and_then(|dir| list(&dir, *json)),
+        Command::ExportNotes {
+            book,
+            format,
+            output,
+        } => books_dir(identifier)
+            .and_then(|dir| export_notes(&dir, book, *format))
+            .and_then(|notes| match output {
+                Some(output) => std::fs::write(output, notes)
+                    .map(|_| String::new())
+                    .map_err(|e| format!("cannot write {}: {e}", output.display())),
+                None => Ok(notes),
+            }),
+        Command::Import { .. } | Command::Open { .. } => {
+            Err("this command needs the app to be running".into())
+        }
+    };
+    match result {
+        Ok(output) => {
+            if !output.is_empty() {
+                let mut stdout = std::io::stdout().lock();
+                let _ = writeln!(stdout, "{output}");
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("readest: {e}");
+            1
+        }
+    }
+}
+
+// Reports an invalid command line and returns the exit code of the process.
+pub fn usage_error(error: &str) -> i32 {
+    #[cfg(target_os = "windows")]
+    attach_console();
+
+    eprintln!("readest: {error}\n\n{USAGE}");
+    2
+}
+
+// An import or open request for the frontend. Files to open are handed over as pending opens.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CliRequest {
+    pub command: &'static str,   // "import" or "open"
+    pub files: Vec<PathBuf>,     // For import, with absolute paths
+    pub book_id: Option<String>, // For open, a book in the library
+    pub cfi: Option<String>,
+    pub page: Option<u32>,
+}
+
+impl CliRequest {
+    pub fn import(files: Vec<PathBuf>) -> Self {
+        CliRequest {
+            command: "import",
+            files,
+            book_id: None,
+            cfi: None,
+            page: None,
+        }
+    }
+}
+
+pub struct PendingCliRequests(EventQueue<CliRequest>);
+
+impl Default for PendingCliRequests {
+    fn default() -> Self {
+        PendingCliRequests(EventQueue::new("cli-requests"))
+    }
+}
+
+impl PendingCliRequests {
+    pub fn reset(&self) {
+        self.0.reset();
+    }
+}
+
+pub fn dispatch(app: &AppHandle, request: CliRequest) {
+    app.state::<PendingCliRequests>().0.push(app, vec![request]);
+}
+
+// Hands an import or open command to the frontend. Paths are resolved against `cwd`, the
+// working directory of the process that received the command, and the book to open against
+// the library.
+pub fn handle(app: &AppHandle, command: Command, cwd: &Path) {
+    match command {
+        Command::Import { paths, recursive } => {
+            let args = paths.iter().filter_map(|path| path.to_str());
+            let paths = BookPaths::resolve(app, args, cwd, recursive);
+            let mut files = paths.found;
+            files.extend(paths.opened.into_iter().map(|file| file.path));
+            if !files.is_empty() {
+                dispatch(app, CliRequest::import(files));
+            }
+            open_with::report_rejected(app, paths.rejected);
+        }
+        Command::Open { target, cfi, page } => {
+            if cwd.join(&target).is_file() {
+                let mut paths = BookPaths::resolve(app, [target.as_str()], cwd, false);
+                for file in &mut paths.opened {
+                    file.cfi = cfi.clone();
+                    file.page = page;
+                }
+                open_with::open_books(app, paths);
+                return;
+            }
+            let Ok(books_dir) = app.path().app_data_dir() else {
+                return;
+            };
+            let books_dir = books_dir.join("Readest").join("Books");
+            match load_library(&books_dir)
+                .and_then(|library| find_book(&library, &target).map(|book| book.hash.clone()))
+            {
+                Ok(hash) => dispatch(
+                    app,
+                    CliRequest {
+                        command: "open",
+                        files: Vec::new(),
+                        book_id: Some(hash),
+                        cfi,
+                        page,
+                    },
+                ),
+                Err(e) => log::warn!("Cannot open {target}: {e}"),
+            }
+        }
+        _ => {}
+    }
+}
+
+// Returns the pending requests. Later requests are emitted as `cli-requests` events.
+#[command]
+pub fn take_cli_requests(pending: State<'_, PendingCliRequests>) -> Vec<CliRequest> {
+    pending.0.take()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Option<Command>, String> {
+        let argv = std::iter::once("readest")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect::<Vec<_>>();
+        parse(&argv)
+    }
+
+    #[test]
+    fn treats_plain_arguments_as_files() {
+        assert!(parse_args(&[]).unwrap().is_none());
+        assert!(parse_args(&["book.epub"]).unwrap().is_none());
+        assert!(parse_args(&["--custom-oauth", "book.epub"])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn treats_arguments_after_double_dash_as_files() {
+        assert!(parse_args(&["--", "list"]).unwrap().is_none());
+        assert!(parse_args(&["--no-custom-oauth", "--", "open", "help"])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn parses_subcommands_after_app_flags() {
+        assert!(matches!(
+            parse_args(&["--custom-oauth=false", "list", "--json"]),
+            Ok(Some(Command::List { json: true }))
+        ));
+        assert!(matches!(parse_args(&["-h"]), Ok(Some(Command::Help))));
+        assert!(matches!(parse_args(&["help"]), Ok(Some(Command::Help))));
+    }
+
+    #[test]
+    fn parses_import() {
+        let Ok(Some(Command::Import { paths, recursive })) =
+            parse_args(&["import", "-r", "a.epub", "--", "-b.epub"])
+        else {
+            panic!("expected import");
+        };
+        assert!(recursive);
+        assert_eq!(paths, [PathBuf::from("a.epub"), PathBuf::from("-b.epub")]);
+        assert!(parse_args(&["import"]).is_err());
+    }
+
+    #[test]
+    fn parses_open() {
+        let Ok(Some(Command::Open { target, cfi, page })) =
+            parse_args(&["open", "Dune", "--page=12"])
+        else {
+            panic!("expected open");
+        };
+        assert_eq!(target, "Dune");
+        assert_eq!(cfi, None);
+        assert_eq!(page, Some(12));
+
+        let Ok(Some(Command::Open { cfi, .. })) =
+            parse_args(&["open", "Dune", "--cfi", "epubcfi(/6/4)"])
+        else {
+            panic!("expected open");
+        };
+        assert_eq!(cfi.as_deref(), Some("epubcfi(/6/4)"));
+
+        assert!(parse_args(&["open", "Dune", "--page", "0"]).is_err());
+        assert!(parse_args(&["open", "Dune", "Emma"]).is_err());
+        assert!(parse_args(&["open", "--page"]).is_err());
+    }
+
+    #[test]
+    fn parses_export_notes() {
+        let Ok(Some(Command::ExportNotes {
+            book,
+            format,
+            output,
+        })) = parse_args(&["export-notes", "Dune", "-f", "json", "-o", "notes.json"])
+        else {
+            panic!("expected export-notes");
+        };
+        assert_eq!(book, "Dune");
+        assert_eq!(format, NotesFormat::Json);
+        assert_eq!(output, Some(PathBuf::from("notes.json")));
+
+        assert!(parse_args(&["export-notes", "Dune", "--format", "pdf"]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert!(parse_args(&["list", "--all"]).is_err());
+        assert!(parse_args(&["list", "--json=yes"]).is_err());
+        assert!(parse_args(&["list", "extra"]).is_err());
+    }
+}
@@ -0,0 +1,376 @@
+//! Routes `readest://` links, and the same paths on web.readest.com on mobile, to the frontend.
+//!
+//! `open?book=<hash>&cfi=<cfi>` opens a book of the library, `import?url=<url>` imports a book
+//! from the web and `annotation/<id>` opens the book of a highlight or note at its location.
+//! Links are validated here and queued until the frontend takes them with take_deep_links,
+//! after which they arrive as `deep-links` events.
+
+use serde::{ser::Serializer, Serialize};
+use tauri::{command, AppHandle, Manager, State, Url};
+
+use crate::event_queue::EventQueue;
+
+type Result<T> = std::result::Result<T, Error>;
+
+const SCHEME: &str = "readest";
+const WEB_HOST: &str = "web.readest.com";
+const MAX_CFI_LENGTH: usize = 4096;
+const MAX_ANNOTATION_ID_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum DeepLink {
+    #[serde(rename_all = "camelCase")]
+    Open {
+        book_id: String,
+        cfi: Option<String>,
+    },
+    Import {
+        url: String, // An http(s) URL of a book
+    },
+    #[serde(rename_all = "camelCase")]
+    Annotation {
+        id: String,
+        book_id: Option<String>, // Narrows down the search, since ids are only unique per book
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unknown link {0}")]
+    UnknownRoute(String),
+    #[error("missing parameter {0}")]
+    MissingParameter(&'static str),
+    #[error("invalid book hash {0}")]
+    InvalidBookHash(String),
+    #[error("invalid CFI {0}")]
+    InvalidCfi(String),
+    #[error("invalid URL {0}")]
+    InvalidUrl(String),
+    #[error("invalid annotation id {0}")]
+    InvalidAnnotationId(String),
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_ref())
+    }
+}
+
+pub struct PendingDeepLinks(EventQueue<DeepLink>);
+
+impl Default for PendingDeepLinks {
+    fn default() -> Self {
+        PendingDeepLinks(EventQueue::new("deep-links"))
+    }
+}
+
+impl PendingDeepLinks {
+    pub fn reset(&self) {
+        self.0.reset();
+    }
+}
+
+// Book hashes are the hex MD5 digests computed by the frontend when importing.
+pub(crate) fn is_book_hash(value: &str) -> bool {
+    value.len() == 32 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn book_hash(value: &str) -> Result<String> {
+    if is_book_hash(value) {
+        Ok(value.to_lowercase())
+    } else {
+        Err(Error::InvalidBookHash(value.to_string()))
+    }
+}
+
+fn cfi(value: &str) -> Result<String> {
+    if value.len() <= MAX_CFI_LENGTH && value.starts_with("epubcfi(/") && value.ends_with(')') {
+        Ok(value.to_string())
+    } else {
+        Err(Error::InvalidCfi(value.to_string()))
+    }
+}
+
+fn import_url(value: &str) -> Result<String> {
+    match Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.host().is_some() => {
+            Ok(url.to_string())
+        }
+        _ => Err(Error::InvalidUrl(value.to_string())),
+    }
+}
+
+fn annotation_id(value: &str) -> Result<String> {
+    let valid = !value.is_empty()
+        && value.len() <= MAX_ANNOTATION_ID_LENGTH
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err(Error::InvalidAnnotationId(value.to_string()))
+    }
+}
+
+// Returns the route of a link, e.g. ["annotation", "<id>"], or None if it is not for the app.
+fn route(url: &Url) -> Option<Vec<&str>> {
+    let mut segments = Vec::new();
+    match url.scheme() {
+        SCHEME => segments.extend(url.host_str()),
+        "https" if url.host_str() == Some(WEB_HOST) => {}
+        _ => return None,
+    }
+    // Links without a path, like readest://open?book=<hash>, have no path segments at all
+    let path = url.path_segments().into_iter().flatten();
+    segments.extend(path.filter(|segment| !segment.is_empty()));
+    Some(segments)
+}
+
+fn query_param(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn required_param(url: &Url, name: &'static str) -> Result<String> {
+    query_param(url, name).ok_or(Error::MissingParameter(name))
+}
+
+fn parse_route(route: &[&str], url: &Url) -> Result<DeepLink> {
+    match route {
+        ["open"] => Ok(DeepLink::Open {
+            book_id: book_hash(&required_param(url, "book")?)?,
+            cfi: query_param(url, "cfi").as_deref().map(cfi).transpose()?,
+        }),
+        ["import"] => Ok(DeepLink::Import {
+            url: import_url(&required_param(url, "url")?)?,
+        }),
+        ["annotation", id] => Ok(DeepLink::Annotation {
+            id: annotation_id(id)?,
+            book_id: query_param(url, "book")
+                .as_deref()
+                .map(book_hash)
+                .transpose()?,
+        }),
+        _ => Err(Error::UnknownRoute(url.to_string())),
+    }
+}
+
+// Parses a link, returning None for links that are handled elsewhere, such as auth callbacks.
+pub fn parse(url: &Url) -> Option<Result<DeepLink>> {
+    let route = route(url)?;
+    match route.first() {
+        Some(&("open" | "import" | "annotation")) => Some(parse_route(&route, url)),
+        _ => None,
+    }
+}
+
+// Hands the valid links among `urls` to the frontend and logs the invalid ones.
+pub fn handle_urls<'a>(app: &AppHandle, urls: impl IntoIterator<Item = &'a Url>) {
+    let mut links = Vec::new();
+    for url in urls {
+        match parse(url) {
+            Some(Ok(link)) => links.push(link),
+            Some(Err(e)) => log::warn!("Ignoring deep link {url}: {e}"),
+            None => {}
+        }
+    }
+    app.state::<PendingDeepLinks>().0.push(app, links);
+}
+
+// Hands the links among the arguments of a process to the frontend, as on Windows and Linux.
+#[cfg(desktop)]
+pub fn handle_args(app: &AppHandle, args: &[String]) {
+    let urls = args
+        .iter()
+        .skip(1)
+        .filter_map(|arg| Url::parse(arg).ok())
+        .filter(|url| url.scheme() == SCHEME)
+        .collect::<Vec<_>>();
+    handle_urls(app, &urls);
+}
+
+// Returns the links that arrived before the frontend was listening. Later links are emitted
+// as `deep-links` events.
+#[command]
+pub fn take_deep_links(pending: State<'_, PendingDeepLinks>) -> Vec<DeepLink> {
+    pending.0.take()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+    const HASH: &str = "0b229176d4e8db7f6d2b5a4952368d7a";
+
+    fn parse_link(link: &str) -> Option<Result<DeepLink>> {
+        parse(&Url::parse(link).unwrap())
+    }
+
+    fn link(link: &str) -> DeepLink {
+        parse_link(link).unwrap().unwrap()
+    }
+
+    fn error(link: &str) -> Error {
+        parse_link(link).unwrap().unwrap_err()
+    }
+
+    #[test]
+    fn parses_open_links() {
+        let open = DeepLink::Open {
+            book_id: HASH.to_string(),
+            cfi: Some("epubcfi(/6/4)".to_string()),
+        };
+        assert_eq!(
+            link(&format!("readest://open?book={HASH}&cfi=epubcfi(/6/4)")),
+            open
+        );
+        let uppercase = HASH.to_uppercase();
+        assert_eq!(
+            link(&format!(
+                "readest://open?cfi=epubcfi%28%2F6%2F4%29&book={uppercase}"
+            )),
+            open
+        );
+        assert_eq!(
+            link(&format!("readest://open/?book={HASH}")),
+            DeepLink::Open {
+                book_id: HASH.to_string(),
+                cfi: None,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_open_links() {
+        assert!(matches!(
+            error("readest://open"),
+            Error::MissingParameter("book")
+        ));
+        for hash in [
+            "",
+            "0b229176",
+            "0b229176d4e8db7f6d2b5a4952368d7g",
+            "../library",
+        ] {
+            let link = format!("readest://open?book={hash}");
+            assert!(matches!(error(&link), Error::InvalidBookHash(_)), "{link}");
+        }
+        let long_cfi = format!("epubcfi(/{})", "6/4/".repeat(MAX_CFI_LENGTH / 4));
+        for cfi in [
+            "/6/4",
+            "epubcfi(/6/4",
+            "epubcfi(6/4)",
+            "javascript:alert(1)",
+            &long_cfi,
+        ] {
+            let link = format!("readest://open?book={HASH}&cfi={cfi}");
+            assert!(matches!(error(&link), Error::InvalidCfi(_)), "{cfi}");
+        }
+        assert!(matches!(
+            error(&format!("readest://open/book?book={HASH}")),
+            Error::UnknownRoute(_)
+        ));
+    }
+
+    #[test]
+    fn parses_import_links() {
+        assert_eq!(
+            link("readest://import?url=https%3A%2F%2Fexample.com%2Fbooks%2Fa%20b.epub%3Fdl%3D1"),
+            DeepLink::Import {
+                url: "https://example.com/books/a%20b.epub?dl=1".to_string(),
+            }
+        );
+        assert!(matches!(
+            error("readest://import"),
+            Error::MissingParameter("url")
+        ));
+        for url in [
+            "file:///etc/passwd",
+            "javascript:alert(1)",
+            "data:application/epub+zip;base64,UEsDBA",
+            "http://",
+            "mailto:reader@example.com",
+            "/books/a.epub",
+        ] {
+            let link = format!(
+                "readest://import?url={}",
+                utf8_percent_encode(url, NON_ALPHANUMERIC)
+            );
+            assert!(matches!(error(&link), Error::InvalidUrl(_)), "{url}");
+        }
+    }
+
+    #[test]
+    fn parses_annotation_links() {
+        assert_eq!(
+            link("readest://annotation/note_1-a"),
+            DeepLink::Annotation {
+                id: "note_1-a".to_string(),
+                book_id: None,
+            }
+        );
+        assert_eq!(
+            link(&format!("readest://annotation/note_1-a?book={HASH}")),
+            DeepLink::Annotation {
+                id: "note_1-a".to_string(),
+                book_id: Some(HASH.to_string()),
+            }
+        );
+        let long_id = "a".repeat(MAX_ANNOTATION_ID_LENGTH + 1);
+        for id in ["note.1", "note%201", &long_id] {
+            let link = format!("readest://annotation/{id}");
+            assert!(
+                matches!(error(&link), Error::InvalidAnnotationId(_)),
+                "{id}"
+            );
+        }
+        assert!(matches!(
+            error("readest://annotation/note?book=abc"),
+            Error::InvalidBookHash(_)
+        ));
+        assert!(matches!(
+            error("readest://annotation"),
+            Error::UnknownRoute(_)
+        ));
+    }
+
+    #[test]
+    fn parses_web_links() {
+        assert_eq!(
+            link(&format!("https://web.readest.com/open?book={HASH}")),
+            DeepLink::Open {
+                book_id: HASH.to_string(),
+                cfi: None,
+            }
+        );
+        assert_eq!(
+            link("https://web.readest.com/annotation/note"),
+            DeepLink::Annotation {
+                id: "note".to_string(),
+                book_id: None,
+            }
+        );
+        assert!(parse_link(&format!("http://web.readest.com/open?book={HASH}")).is_none());
+        assert!(parse_link(&format!("https://example.com/open?book={HASH}")).is_none());
+        assert!(parse_link(&format!("otherapp://open?book={HASH}")).is_none());
+    }
+
+    #[test]
+    fn leaves_other_links_alone() {
+        for link in [
+            "readest://auth-callback#access_token=abc&refresh_token=def",
+            "readest://auth-callback?code=abc",
+            "https://web.readest.com/auth/callback?code=abc",
+            "https://web.readest.com/library",
+        ] {
+            assert!(parse_link(link).is_none(), "{link}");
+        }
+    }
+}
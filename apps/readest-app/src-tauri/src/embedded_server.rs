@@ -0,0 +1,122 @@
+//! Small HTTP servers that the desktop app can run for devices on the local network, or for
+//! the browser on this computer.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use axum::Router;
+use rand::{distributions::Alphanumeric, Rng};
+use tokio_util::sync::CancellationToken;
+
+// How long shutdown() waits for running requests before giving up on them.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A server listening until stop() is called or it is dropped.
+pub struct EmbeddedServer {
+    port: u16,
+    shutdown: CancellationToken,
+    stopped: CancellationToken, // Cancelled once the server has closed its listener
+}
+
+impl EmbeddedServer {
+    // Starts serving `router` on `port`, or on a free port if it is 0. `name` is used in logs.
+    pub async fn start(name: &'static str, router: Router, port: u16) -> std::io::Result<Self> {
+        Self::bind(
+            name,
+            router,
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)),
+        )
+        .await
+    }
+
+    // Like start(), but only reachable from this computer.
+    pub async fn start_loopback(
+        name: &'static str,
+        router: Router,
+        port: u16,
+    ) -> std::io::Result<Self> {
+        Self::bind(name, router, SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await
+    }
+
+    async fn bind(name: &'static str, router: Router, addr: SocketAddr) -> std::io::Result<Self> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let port = listener.local_addr()?.port();
+        let shutdown = CancellationToken::new();
+        let stopped = CancellationToken::new();
+
+        let signal = shutdown.clone();
+        let done = stopped.clone();
+        tauri::async_runtime::spawn(async move {
+            log::info!("{name} server listening on port {port}");
+            let server = axum::serve(listener, router)
+                .with_graceful_shutdown(async move { signal.cancelled().await });
+            if let Err(e) = server.await {
+                log::error!("{name} server failed: {e}");
+            }
+            log::info!("{name} server on port {port} stopped");
+            done.cancel();
+        });
+        Ok(Self {
+            port,
+            shutdown,
+            stopped,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // Stops accepting connections and lets the running requests finish.
+    pub fn stop(&self) {
+        self.shutdown.cancel();
+    }
+
+    // Stops the server and waits until it has stopped, so that its port can be bound again.
+    pub async fn shutdown(self) {
+        let stopped = self.stopped.clone();
+        let port = self.port;
+        drop(self);
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, stopped.cancelled())
+            .await
+            .is_err()
+        {
+            log::warn!("Server on port {port} did not stop in time");
+        }
+    }
+}
+
+impl Drop for EmbeddedServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Random alphanumeric string for access tokens and OAuth states.
+pub fn random_token(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+// Compares secrets in time that does not depend on where they differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Address of the interface that routes to other hosts; connecting a UDP socket sends nothing.
+pub fn local_ip() -> Ipv4Addr {
+    UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .and_then(|socket| {
+            socket.connect(SocketAddr::from((Ipv4Addr::new(192, 168, 0, 1), 9)))?;
+            socket.local_addr()
+        })
+        .ok()
+        .and_then(|addr| match addr.ip() {
+            IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+            _ => None,
+        })
+        .unwrap_or(Ipv4Addr::LOCALHOST)
+}
@@ -0,0 +1,62 @@
+//! Events for the frontend of the main window that must be neither lost nor handled twice.
+//!
+//! Items are queued until the frontend takes them with a command, which also tells the queue
+//! that the frontend is now listening, so later items are emitted right away. When the main
+//! window loads a page again, the queue goes back to queueing until the new page takes them.
+
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+const MAIN_WINDOW: &str = "main";
+
+struct Pending<T> {
+    items: Vec<T>,
+    listening: bool,
+}
+
+pub struct EventQueue<T> {
+    event: &'static str,
+    pending: Mutex<Pending<T>>,
+}
+
+impl<T: Serialize + Clone> EventQueue<T> {
+    pub fn new(event: &'static str) -> Self {
+        EventQueue {
+            event,
+            pending: Mutex::new(Pending {
+                items: Vec::new(),
+                listening: false,
+            }),
+        }
+    }
+
+    // Emits `items` as a single event, or queues them while the frontend is not listening.
+    pub fn push(&self, app: &AppHandle, items: Vec<T>) {
+        if items.is_empty() {
+            return;
+        }
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if !pending.listening {
+                pending.items.extend(items);
+                return;
+            }
+        }
+        if let Err(e) = app.emit_to(MAIN_WINDOW, self.event, items) {
+            log::error!("Failed to emit {}: {e}", self.event);
+        }
+    }
+
+    pub fn take(&self) -> Vec<T> {
+        let mut pending = self.pending.lock().unwrap();
+        pending.listening = true;
+        std::mem::take(&mut pending.items)
+    }
+
+    // Called when the main window starts loading a page, whose frontend is not listening yet.
+    pub fn reset(&self) {
+        self.pending.lock().unwrap().listening = false;
+    }
+}
@@ -0,0 +1,166 @@
+//! The HTTP client shared by all networking done in Rust.
+//!
+//! It is configured from the `httpClient` section of `settings.json` at startup and rebuilt
+//! whenever the frontend changes the settings, so proxies, private CAs and timeouts apply to
+//! every transfer without each command building its own client.
+
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{command, AppHandle, Manager, State};
+
+const SETTINGS_FILE: &str = "settings.json";
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpClientConfig {
+    pub proxy: Option<String>, // http://, https://, socks5:// or socks5h:// URL used for all requests
+    pub no_proxy: Option<String>, // Comma-separated hosts and domains that bypass the proxy
+    pub extra_root_certificates: Vec<String>, // PEM files with additional CAs to trust
+    pub connect_timeout_secs: Option<u64>,
+    pub read_timeout_secs: Option<u64>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Settings {
+    #[serde(default)]
+    http_client: HttpClientConfig,
+}
+
+pub struct HttpClient {
+    default_user_agent: String,
+    config: RwLock<HttpClientConfig>,
+    client: RwLock<reqwest::Client>,
+}
+
+impl HttpClient {
+    // Clients share their connection pool, so handing out clones is cheap.
+    pub fn client(&self) -> reqwest::Client {
+        self.client.read().unwrap().clone()
+    }
+
+    fn build(
+        config: &HttpClientConfig,
+        default_user_agent: &str,
+    ) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(config.user_agent.as_deref().unwrap_or(default_user_agent))
+            .connect_timeout(
+                config
+                    .connect_timeout_secs
+                    .map_or(DEFAULT_CONNECT_TIMEOUT, Duration::from_secs),
+            )
+            .read_timeout(
+                config
+                    .read_timeout_secs
+                    .map_or(DEFAULT_READ_TIMEOUT, Duration::from_secs),
+            );
+
+        if let Some(proxy_url) = config.proxy.as_deref().filter(|url| !url.is_empty()) {
+            let proxy = reqwest::Proxy::all(proxy_url)
+                .map_err(|e| format!("Invalid proxy {proxy_url}: {e}"))?
+                .no_proxy(
+                    config
+                        .no_proxy
+                        .as_deref()
+                        .and_then(reqwest::NoProxy::from_string),
+                );
+            builder = builder.proxy(proxy);
+        }
+
+        for path in &config.extra_root_certificates {
+            let pem = std::fs::read(path)
+                .map_err(|e| format!("Failed to read certificate {path}: {e}"))?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("Invalid certificate {path}: {e}"))?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        builder.build().map_err(|e| e.to_string())
+    }
+
+    fn apply(&self, config: HttpClientConfig) -> Result<(), String> {
+        let client = Self::build(&config, &self.default_user_agent)?;
+        *self.client.write().unwrap() = client;
+        *self.config.write().unwrap() = config;
+        Ok(())
+    }
+}
+
+fn settings_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_config_dir()
+        .ok()
+        .map(|dir| dir.join(SETTINGS_FILE))
+}
+
+// Writes the config into settings.json, keeping the other settings of the frontend, through a
+// temporary file so a crash never leaves the settings half written.
+fn save(app: &AppHandle, config: &HttpClientConfig) -> Result<(), String> {
+    let path = settings_path(app).ok_or("Failed to resolve the settings directory")?;
+    let mut settings = std::fs::read(&path)
+        .ok()
+        .and_then(|data| serde_json::from_slice::<serde_json::Value>(&data).ok())
+        .filter(serde_json::Value::is_object)
+        .unwrap_or_else(|| serde_json::json!({}));
+    settings["httpClient"] = serde_json::to_value(config).map_err(|e| e.to_string())?;
+
+    let data = serde_json::to_vec(&settings).map_err(|e| e.to_string())?;
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, data)
+        .and_then(|_| std::fs::rename(&tmp_path, &path))
+        .map_err(|e| format!("Failed to save settings: {e}"))
+}
+
+// Manages the shared client, configured from the settings saved by the frontend.
+pub fn init(app: &AppHandle) {
+    let default_user_agent = format!("Readest/{}", app.package_info().version);
+    let config = settings_path(app)
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|data| serde_json::from_slice::<Settings>(&data).ok())
+        .unwrap_or_default()
+        .http_client;
+
+    // A broken proxy or certificate must not leave the app without any network access
+    let (config, client) = match HttpClient::build(&config, &default_user_agent) {
+        Ok(client) => (config, client),
+        Err(e) => {
+            log::error!("Failed to configure HTTP client, using defaults: {e}");
+            let config = HttpClientConfig::default();
+            let client = HttpClient::build(&config, &default_user_agent)
+                .expect("failed to build default HTTP client");
+            (config, client)
+        }
+    };
+
+    app.manage(HttpClient {
+        default_user_agent,
+        config: RwLock::new(config),
+        client: RwLock::new(client),
+    });
+}
+
+#[command]
+pub fn get_http_client_config(http: State<'_, HttpClient>) -> HttpClientConfig {
+    http.config.read().unwrap().clone()
+}
+
+// Rebuilds the shared client and saves the config for the next start; transfers already
+// running keep the client they started with.
+#[command]
+pub fn set_http_client_config(
+    app: AppHandle,
+    http: State<'_, HttpClient>,
+    config: HttpClientConfig,
+) -> Result<(), String> {
+    http.apply(config.clone())?;
+    save(&app, &config)
+}
@@ -0,0 +1,521 @@
+//! Embedded server compatible with the KOReader sync server (koreader-sync-server), so that
+//! KOReader devices on the local network can sync their reading progress with the desktop
+//! app without a third-party service.
+//!
+//! Users and progress are kept in `kosync.json` in the app data dir. As with the reference
+//! server, clients authenticate with the `X-Auth-User` and `X-Auth-Key` headers, where the
+//! key is the MD5 hash of the password.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State as AxumState},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::embedded_server::EmbeddedServer;
+
+const STORE_FILE: &str = "kosync.json";
+const DEFAULT_PORT: u16 = 7200;
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Store {
+    users: HashMap<String, User>,
+    progress: HashMap<String, HashMap<String, Progress>>, // Latest progress by user and document
+    // Incremented for every change to save
+    #[serde(skip)]
+    generation: u64,
+}
+
+// A serialized store waiting to be written.
+struct Snapshot {
+    generation: u64,
+    data: Vec<u8>,
+}
+
+impl Store {
+    // Serializes the store while it is locked, so that it can be saved after unlocking it.
+    fn snapshot(&mut self) -> Result<Snapshot, KosyncError> {
+        self.generation += 1;
+        let data = serde_json::to_vec(self).map_err(|e| {
+            log::error!("Failed to serialize the KOReader sync store: {e}");
+            KosyncError::Internal
+        })?;
+        Ok(Snapshot {
+            generation: self.generation,
+            data,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct User {
+    key_hash: String, // SHA-256 of the user name and key, the key itself is never stored
+}
+
+// Field names follow the KOReader sync protocol.
+#[derive(Clone, Serialize, Deserialize)]
+struct Progress {
+    document: String,
+    progress: String,
+    percentage: f64,
+    device: String,
+    device_id: String,
+    timestamp: u64,
+}
+
+#[derive(Deserialize)]
+struct NewUser {
+    username: String,
+    password: String, // Actually the key, i.e. the MD5 hash of the password
+}
+
+#[derive(Deserialize)]
+struct ProgressUpdate {
+    document: Option<String>,
+    progress: String,
+    percentage: f64,
+    #[serde(default)]
+    device: String,
+    #[serde(default)]
+    device_id: String,
+}
+
+struct ServerState {
+    store: Mutex<Store>,
+    path: PathBuf,
+    written: tokio::sync::Mutex<u64>, // Generation of the last save, as saves may race
+    allow_registration: bool,
+}
+
+impl ServerState {
+    fn load(path: PathBuf, allow_registration: bool) -> Self {
+        let store = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self {
+            store: Mutex::new(store),
+            path,
+            written: tokio::sync::Mutex::new(0),
+            allow_registration,
+        }
+    }
+
+    // Writes a snapshot through a temporary file, unless a newer one was written already.
+    async fn save(&self, snapshot: Snapshot) -> Result<(), KosyncError> {
+        let mut written = self.written.lock().await;
+        if *written >= snapshot.generation {
+            return Ok(());
+        }
+        let temp_path = self.path.with_extension("json.tmp");
+        let result = match tokio::fs::write(&temp_path, snapshot.data).await {
+            Ok(()) => tokio::fs::rename(&temp_path, &self.path).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => {
+                *written = snapshot.generation;
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Failed to save {}: {e}", self.path.display());
+                Err(KosyncError::Internal)
+            }
+        }
+    }
+
+    // Returns the name of the user authenticated by the request headers.
+    fn authorize(&self, headers: &HeaderMap) -> Result<String, KosyncError> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let (Some(username), Some(key)) = (header("x-auth-user"), header("x-auth-key")) else {
+            return Err(KosyncError::Unauthorized);
+        };
+        let store = self.store.lock().unwrap();
+        match store.users.get(username) {
+            Some(user) if user.key_hash == key_hash(username, key) => Ok(username.to_string()),
+            _ => Err(KosyncError::Unauthorized),
+        }
+    }
+}
+
+fn key_hash(username: &str, key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(username.as_bytes());
+    hasher.update(b":");
+    hasher.update(key.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+// Errors as reported by koreader-sync-server, which KOReader shows to the user.
+enum KosyncError {
+    Internal,
+    Unauthorized,
+    UserExists,
+    InvalidFields,
+    DocumentMissing,
+    RegistrationDisabled,
+}
+
+impl IntoResponse for KosyncError {
+    fn into_response(self) -> Response {
+        let (status, code, message) = match self {
+            KosyncError::Internal => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                2000,
+                "Unknown server error.",
+            ),
+            KosyncError::Unauthorized => (StatusCode::UNAUTHORIZED, 2001, "Unauthorized"),
+            KosyncError::UserExists => (
+                StatusCode::PAYMENT_REQUIRED,
+                2002,
+                "Username is already registered.",
+            ),
+            KosyncError::InvalidFields => (StatusCode::FORBIDDEN, 2003, "Invalid request"),
+            KosyncError::DocumentMissing => (
+                StatusCode::FORBIDDEN,
+                2004,
+                "Field 'document' not provided.",
+            ),
+            KosyncError::RegistrationDisabled => (
+                StatusCode::FORBIDDEN,
+                2005,
+                "User registration is disabled.",
+            ),
+        };
+        (status, Json(json!({ "code": code, "message": message }))).into_response()
+    }
+}
+
+type SharedState = AxumState<Arc<ServerState>>;
+
+fn router(state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route(
+            "/healthcheck",
+            get(|| async { Json(json!({ "state": "OK" })) }),
+        )
+        .route("/users/create", post(create_user))
+        .route("/users/auth", get(authorize_user))
+        .route("/syncs/progress", put(update_progress))
+        .route("/syncs/progress/:document", get(get_progress))
+        .with_state(state)
+}
+
+async fn create_user(
+    AxumState(state): SharedState,
+    body: Bytes,
+) -> Result<impl IntoResponse, KosyncError> {
+    if !state.allow_registration {
+        return Err(KosyncError::RegistrationDisabled);
+    }
+    let user: NewUser = serde_json::from_slice(&body).map_err(|_| KosyncError::InvalidFields)?;
+    if user.username.is_empty() || user.password.is_empty() || user.username.contains(':') {
+        return Err(KosyncError::InvalidFields);
+    }
+
+    let snapshot = {
+        let mut store = state.store.lock().unwrap();
+        if store.users.contains_key(&user.username) {
+            return Err(KosyncError::UserExists);
+        }
+        store.users.insert(
+            user.username.clone(),
+            User {
+                key_hash: key_hash(&user.username, &user.password),
+            },
+        );
+        store.snapshot()?
+    };
+    state.save(snapshot).await?;
+    log::info!("Registered KOReader sync user {}", user.username);
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "username": user.username })),
+    ))
+}
+
+async fn authorize_user(
+    AxumState(state): SharedState,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, KosyncError> {
+    state.authorize(&headers)?;
+    Ok(Json(json!({ "authorized": "OK" })))
+}
+
+async fn update_progress(
+    AxumState(state): SharedState,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, KosyncError> {
+    let username = state.authorize(&headers)?;
+    let update: ProgressUpdate =
+        serde_json::from_slice(&body).map_err(|_| KosyncError::InvalidFields)?;
+    let document = update
+        .document
+        .filter(|document| !document.is_empty())
+        .ok_or(KosyncError::DocumentMissing)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let snapshot = {
+        let mut store = state.store.lock().unwrap();
+        store.progress.entry(username).or_default().insert(
+            document.clone(),
+            Progress {
+                document: document.clone(),
+                progress: update.progress,
+                percentage: update.percentage,
+                device: update.device,
+                device_id: update.device_id,
+                timestamp,
+            },
+        );
+        store.snapshot()?
+    };
+    state.save(snapshot).await?;
+    Ok(Json(
+        json!({ "document": document, "timestamp": timestamp }),
+    ))
+}
+
+// Responds with an empty object for documents that were never synced, like the reference server.
+async fn get_progress(
+    AxumState(state): SharedState,
+    headers: HeaderMap,
+    Path(document): Path<String>,
+) -> Result<impl IntoResponse, KosyncError> {
+    let username = state.authorize(&headers)?;
+    let store = state.store.lock().unwrap();
+    let progress = store
+        .progress
+        .get(&username)
+        .and_then(|documents| documents.get(&document));
+    Ok(Json(match progress {
+        Some(progress) => json!(progress),
+        None => json!({}),
+    }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KosyncServerOptions {
+    pub port: Option<u16>,                // Defaults to 7200
+    pub allow_registration: Option<bool>, // Whether new devices may register users, defaults to false
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KosyncServerStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+}
+
+#[derive(Default)]
+pub struct KosyncServer(Mutex<Option<EmbeddedServer>>);
+
+impl KosyncServer {
+    fn status(&self) -> KosyncServerStatus {
+        let port = self.0.lock().unwrap().as_ref().map(EmbeddedServer::port);
+        KosyncServerStatus {
+            running: port.is_some(),
+            port,
+        }
+    }
+}
+
+// Starts the server, restarting it if it is already running so that new options take effect.
+// Registration is off unless enabled, since anyone on the network can reach the server.
+#[command]
+pub async fn start_kosync_server(
+    app: AppHandle,
+    server: State<'_, KosyncServer>,
+    options: Option<KosyncServerOptions>,
+) -> Result<KosyncServerStatus, String> {
+    let options = options.unwrap_or_default();
+    let previous = server.0.lock().unwrap().take();
+    if let Some(previous) = previous {
+        previous.shutdown().await;
+    }
+
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let state = Arc::new(ServerState::load(
+        dir.join(STORE_FILE),
+        options.allow_registration.unwrap_or(false),
+    ));
+    let running = EmbeddedServer::start(
+        "KOReader sync",
+        router(state),
+        options.port.unwrap_or(DEFAULT_PORT),
+    )
+    .await
+    .map_err(|e| format!("Failed to start KOReader sync server: {e}"))?;
+
+    *server.0.lock().unwrap() = Some(running);
+    Ok(server.status())
+}
+
+#[command]
+pub fn stop_kosync_server(server: State<'_, KosyncServer>) -> KosyncServerStatus {
+    drop(server.0.lock().unwrap().take());
+    server.status()
+}
+
+#[command]
+pub fn get_kosync_server_status(server: State<'_, KosyncServer>) -> KosyncServerStatus {
+    server.status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    const KEY: &str = "5f4dcc3b5aa765d61d8327deb882cf99"; // MD5 of "password"
+
+    fn state(name: &str, allow_registration: bool) -> Arc<ServerState> {
+        let path =
+            std::env::temp_dir().join(format!("readest-kosync-{name}-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Arc::new(ServerState::load(path, allow_registration))
+    }
+
+    async fn send(
+        state: &Arc<ServerState>,
+        method: Method,
+        uri: &str,
+        auth: Option<(&str, &str)>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some((user, key)) = auth {
+            request = request
+                .header("x-auth-user", user)
+                .header("x-auth-key", key);
+        }
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        let response = router(state.clone())
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    async fn register(state: &Arc<ServerState>, username: &str) -> (StatusCode, Value) {
+        let user = json!({ "username": username, "password": KEY });
+        send(state, Method::POST, "/users/create", None, Some(user)).await
+    }
+
+    #[tokio::test]
+    async fn refuses_registration_unless_allowed() {
+        let state = state("registration", false);
+        let (status, body) = register(&state, "reader").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], 2005);
+        assert!(state.store.lock().unwrap().users.is_empty());
+    }
+
+    #[tokio::test]
+    async fn registers_users_once() {
+        let state = state("users", true);
+        let (status, body) = register(&state, "reader").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["username"], "reader");
+        let (status, body) = register(&state, "reader").await;
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+        assert_eq!(body["code"], 2002);
+        let (status, _) = register(&state, "a:b").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let saved = std::fs::read(&state.path).unwrap();
+        let saved: Store = serde_json::from_slice(&saved).unwrap();
+        assert!(!saved.users["reader"].key_hash.contains(KEY));
+        let _ = std::fs::remove_file(&state.path);
+    }
+
+    #[tokio::test]
+    async fn checks_auth_headers() {
+        let state = state("auth", true);
+        register(&state, "reader").await;
+        for auth in [
+            None,
+            Some(("reader", "wrong")),
+            Some(("someone", KEY)),
+            Some(("", KEY)),
+        ] {
+            let (status, body) = send(&state, Method::GET, "/users/auth", auth, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{auth:?}");
+            assert_eq!(body["code"], 2001);
+        }
+        let auth = Some(("reader", KEY));
+        let (status, body) = send(&state, Method::GET, "/users/auth", auth, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["authorized"], "OK");
+        let (status, _) = send(&state, Method::GET, "/syncs/progress/doc", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let _ = std::fs::remove_file(&state.path);
+    }
+
+    #[tokio::test]
+    async fn syncs_progress_by_document() {
+        let state = state("progress", true);
+        register(&state, "reader").await;
+        register(&state, "other").await;
+        let auth = Some(("reader", KEY));
+
+        let update = json!({
+            "document": "0b229176d4e8db7f6d2b5a4952368d7a",
+            "progress": "/body/DocFragment[20]/body/p[22]/img.0",
+            "percentage": 0.32,
+            "device": "Kobo",
+            "device_id": "kobo-1",
+        });
+        let (status, body) = send(&state, Method::PUT, "/syncs/progress", auth, Some(update)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["document"], "0b229176d4e8db7f6d2b5a4952368d7a");
+        assert!(body["timestamp"].as_u64().unwrap() > 0);
+
+        let uri = "/syncs/progress/0b229176d4e8db7f6d2b5a4952368d7a";
+        let (status, body) = send(&state, Method::GET, uri, auth, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["progress"], "/body/DocFragment[20]/body/p[22]/img.0");
+        assert_eq!(body["percentage"], 0.32);
+        assert_eq!(body["device"], "Kobo");
+        assert_eq!(body["device_id"], "kobo-1");
+
+        // Progress is kept per user and per document
+        let (_, body) = send(&state, Method::GET, uri, Some(("other", KEY)), None).await;
+        assert_eq!(body, json!({}));
+        let (_, body) = send(&state, Method::GET, "/syncs/progress/other", auth, None).await;
+        assert_eq!(body, json!({}));
+
+        let update = json!({ "progress": "/body", "percentage": 0.5 });
+        let (status, body) = send(&state, Method::PUT, "/syncs/progress", auth, Some(update)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], 2004);
+        let _ = std::fs::remove_file(&state.path);
+    }
+}
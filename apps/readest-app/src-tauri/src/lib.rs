@@ -10,81 +10,64 @@ extern crate objc;
 use tauri::TitleBarStyle;
 
 #[cfg(desktop)]
-use std::path::PathBuf;
+use std::path::Path;
 #[cfg(desktop)]
-use tauri::{AppHandle, Listener, Manager, Url};
-#[cfg(desktop)]
-use tauri_plugin_fs::FsExt;
+use tauri::{AppHandle, Listener};
 
+mod book_format;
+#[cfg(desktop)]
+mod cli;
+mod deep_link;
+#[cfg(desktop)]
+mod embedded_server;
+mod event_queue;
+mod http_client;
+#[cfg(desktop)]
+mod kosync_server;
 #[cfg(target_os = "macos")]
 mod macos;
-mod transfer_file;
-use tauri::{command, Emitter, WebviewUrl, WebviewWindowBuilder, Window};
-use tauri_plugin_oauth::start;
-use transfer_file::{download_file, upload_file};
-
 #[cfg(desktop)]
-fn allow_file_in_scopes(app: &AppHandle, files: Vec<PathBuf>) {
-    let fs_scope = app.fs_scope();
-    let asset_protocol_scope = app.asset_protocol_scope();
-    for file in &files {
-        if let Err(e) = fs_scope.allow_file(file) {
-            eprintln!("Failed to allow file in fs_scope: {e}");
-        } else {
-            println!("Allowed file in fs_scope: {file:?}");
-        }
-        if let Err(e) = asset_protocol_scope.allow_file(file) {
-            eprintln!("Failed to allow file in asset_protocol_scope: {e}");
-        } else {
-            println!("Allowed file in asset_protocol_scope: {file:?}");
-        }
-    }
-}
-
+mod menu;
+mod multipart_upload;
 #[cfg(desktop)]
-fn get_files_from_argv(argv: Vec<String>) -> Vec<PathBuf> {
-    let mut files = Vec::new();
-    // NOTICE: `args` may include URL protocol (`your-app-protocol://`)
-    // or arguments (`--`) if your app supports them.
-    // files may also be passed as `file://path/to/file`
-    for (_, maybe_file) in argv.iter().enumerate().skip(1) {
-        // skip flags like -f or --flag
-        if maybe_file.starts_with("-") {
-            continue;
-        }
-        // handle `file://` path urls and skip other urls
-        if let Ok(url) = Url::parse(maybe_file) {
-            if let Ok(path) = url.to_file_path() {
-                files.push(path);
-            } else {
-                files.push(PathBuf::from(maybe_file))
-            }
-        } else {
-            files.push(PathBuf::from(maybe_file))
-        }
-    }
-    files
-}
-
+mod oauth_loopback;
+mod opds;
 #[cfg(desktop)]
-fn set_window_open_with_files(app: &AppHandle, files: Vec<PathBuf>) {
-    let files = files
-        .into_iter()
-        .map(|f| {
-            let file = f
-                .to_string_lossy()
-                .replace("\\", "\\\\")
-                .replace("\"", "\\\"");
-            format!("\"{file}\"",)
-        })
-        .collect::<Vec<_>>()
-        .join(",");
-    let window = app.get_webview_window("main").unwrap();
-    let script = format!("window.OPEN_WITH_FILES = [{files}];");
-    if let Err(e) = window.eval(&script) {
-        eprintln!("Failed to set open files variable: {e}");
-    }
-}
+mod opds_server;
+#[cfg(desktop)]
+mod open_with;
+#[cfg(desktop)]
+mod reader_window;
+mod runtime_config;
+#[cfg(desktop)]
+mod timestamp;
+mod transfer_config;
+mod transfer_file;
+mod transfer_queue;
+mod transfer_registry;
+#[cfg(desktop)]
+mod upload_server;
+mod vault;
+mod webdav;
+use http_client::{get_http_client_config, set_http_client_config};
+use multipart_upload::{abort_multipart_upload, create_multipart_upload, upload_file_multipart};
+use opds::{opds_download, opds_fetch_feed, opds_search};
+use runtime_config::get_runtime_config;
+use tauri::{Emitter, Manager, WebviewUrl, WebviewWindowBuilder};
+use transfer_file::{download_file, upload_file};
+use transfer_queue::{
+    dequeue_transfer, enqueue_transfer, get_transfer_queue, prioritize_transfer,
+    set_transfer_queue_concurrency,
+};
+use transfer_registry::{
+    cancel_transfer, get_transfer_config, pause_transfer, resume_transfer, set_transfer_config,
+    set_transfer_rate_limit, TransferRegistry,
+};
+use vault::{
+    delete_secret, get_secret, get_vault_status, lock_vault, reset_vault, set_secret, unlock_vault,
+    Vault,
+};
+use webdav::{webdav_check_connection, webdav_list, webdav_sync};
 
 #[cfg(desktop)]
 fn set_rounded_window(app: &AppHandle, rounded: bool) {
@@ -95,21 +78,6 @@ fn set_rounded_window(app: &AppHandle, rounded: bool) {
     }
 }
 
-#[command]
-async fn start_server(window: Window) -> Result<u16, String> {
-    start(move |url| {
-        // Because of the unprotected localhost port, you must verify the URL here.
-        // Preferebly send back only the token, or nothing at all if you can handle everything else in Rust.
-        let _ = window.emit("redirect_uri", url);
-    })
-    .map_err(|err| err.to_string())
-}
-
-#[tauri::command]
-fn get_environment_variable(name: &str) -> String {
-    std::env::var(String::from(name)).unwrap_or(String::from(""))
-}
-
 #[derive(Clone, serde::Serialize)]
 #[allow(dead_code)]
 struct Payload {
@@ -119,14 +87,93 @@ struct Payload {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let context = tauri::generate_context!();
+
+    // `list` and `export-notes` exit here, before the app or another instance is involved
+    #[cfg(desktop)]
+    let cli_command = match cli::parse(&std::env::args().collect::<Vec<_>>()) {
+        Ok(Some(command)) if command.is_headless() => {
+            std::process::exit(cli::run_headless(&command, &context.config().identifier))
+        }
+        Ok(command) => command,
+        Err(e) => std::process::exit(cli::usage_error(&e)),
+    };
+
     let builder = tauri::Builder::default()
         .plugin(tauri_plugin_process::init())
-        .plugin(tauri_plugin_oauth::init())
+        .manage(TransferRegistry::default())
+        .manage(Vault::default())
+        .manage(deep_link::PendingDeepLinks::default())
         .invoke_handler(tauri::generate_handler![
-            start_server,
+            deep_link::take_deep_links,
+            #[cfg(desktop)]
+            cli::take_cli_requests,
+            #[cfg(desktop)]
+            open_with::take_pending_opens,
+            #[cfg(desktop)]
+            menu::set_recent_books,
+            #[cfg(desktop)]
+            menu::set_app_menu_visible,
+            #[cfg(desktop)]
+            reader_window::open_reader_window,
+            #[cfg(desktop)]
+            reader_window::close_reader_window,
+            #[cfg(desktop)]
+            reader_window::list_reader_windows,
+            #[cfg(desktop)]
+            oauth_loopback::start_oauth_loopback,
+            #[cfg(desktop)]
+            oauth_loopback::cancel_oauth_loopback,
             download_file,
             upload_file,
-            get_environment_variable,
+            create_multipart_upload,
+            upload_file_multipart,
+            abort_multipart_upload,
+            cancel_transfer,
+            pause_transfer,
+            resume_transfer,
+            get_transfer_config,
+            set_transfer_config,
+            get_http_client_config,
+            set_http_client_config,
+            webdav_check_connection,
+            webdav_list,
+            webdav_sync,
+            opds_fetch_feed,
+            opds_search,
+            opds_download,
+            get_vault_status,
+            unlock_vault,
+            lock_vault,
+            reset_vault,
+            set_secret,
+            get_secret,
+            delete_secret,
+            set_transfer_rate_limit,
+            enqueue_transfer,
+            prioritize_transfer,
+            dequeue_transfer,
+            get_transfer_queue,
+            set_transfer_queue_concurrency,
+            get_runtime_config,
+            #[cfg(desktop)]
+            kosync_server::start_kosync_server,
+            #[cfg(desktop)]
+            kosync_server::stop_kosync_server,
+            #[cfg(desktop)]
+            kosync_server::get_kosync_server_status,
+            #[cfg(desktop)]
+            opds_server::start_opds_server,
+            #[cfg(desktop)]
+            opds_server::stop_opds_server,
+            #[cfg(desktop)]
+            opds_server::get_opds_server_status,
+            #[cfg(desktop)]
+            upload_server::start_upload_server,
+            #[cfg(desktop)]
+            upload_server::stop_upload_server,
+            #[cfg(desktop)]
+            upload_server::get_upload_server_info,
             #[cfg(target_os = "macos")]
             macos::safari_auth::auth_with_safari,
             #[cfg(target_os = "macos")]
@@ -143,20 +190,51 @@ pub fn run() {
         .plugin(tauri_plugin_native_tts::init())
         .plugin(tauri_plugin_fs::init());
 
+    #[cfg(desktop)]
+    let builder = builder
+        .manage(cli::PendingCliRequests::default())
+        .manage(open_with::PendingOpens::default())
+        .manage(menu::AppMenu::default())
+        .manage(kosync_server::KosyncServer::default())
+        .manage(oauth_loopback::OAuthLoopbackServer::default())
+        .manage(opds_server::OpdsServer::default())
+        .manage(upload_server::UploadServer::default());
+
     #[cfg(desktop)]
     let builder = builder.plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
         let _ = app
             .get_webview_window("main")
             .expect("no main window")
             .set_focus();
-        let files = get_files_from_argv(argv.clone());
-        if !files.is_empty() {
-            allow_file_in_scopes(app, files.clone());
+        if let Ok(Some(command)) = cli::parse(&argv) {
+            cli::handle(app, command, Path::new(&cwd));
+            return;
         }
+        deep_link::handle_args(app, &argv);
+        let paths = open_with::BookPaths::from_argv(app, &argv, Path::new(&cwd));
+        open_with::open_books(app, paths);
         app.emit("single-instance", Payload { args: argv, cwd })
             .unwrap();
     }));
 
+    // Files, requests and links are queued again until a reloaded page takes them
+    let builder = builder.on_page_load(|webview, payload| {
+        if !matches!(payload.event(), tauri::webview::PageLoadEvent::Started) {
+            return;
+        }
+        // New windows get the app menu shown, so hide it again if the user chose so
+        #[cfg(desktop)]
+        menu::apply_visibility(&webview.window());
+        if webview.label() == "main" {
+            webview.state::<deep_link::PendingDeepLinks>().reset();
+            #[cfg(desktop)]
+            {
+                webview.state::<open_with::PendingOpens>().reset();
+                webview.state::<cli::PendingCliRequests>().reset();
+            }
+        }
+    });
+
     let builder = builder.plugin(tauri_plugin_deep_link::init());
 
     #[cfg(desktop)]
@@ -178,31 +256,46 @@ pub fn run() {
     let builder = builder.plugin(tauri_plugin_haptics::init());
 
     builder
-        .setup(|#[allow(unused_variables)] app| {
+        .setup(move |#[allow(unused_variables)] app| {
+            // Registered first, so that the initialization below is logged
+            if let Err(e) = app.handle().plugin(
+                tauri_plugin_log::Builder::default()
+                    .level(log::LevelFilter::Info)
+                    .build(),
+            ) {
+                eprintln!("Failed to initialize tauri_plugin_log: {e}");
+            };
+
             #[cfg(desktop)]
             {
-                let files = get_files_from_argv(std::env::args().collect());
-                if !files.is_empty() {
-                    let app_handle = app.handle().clone();
-                    allow_file_in_scopes(&app_handle, files.clone());
-                    app.listen("window-ready", move |_| {
-                        println!("Window is ready, proceeding to handle files.");
-                        set_window_open_with_files(&app_handle, files.clone());
-                    });
+                let cwd = std::env::current_dir().unwrap_or_default();
+                if let Some(command) = cli_command {
+                    cli::handle(app.handle(), command, &cwd);
+                } else {
+                    let argv = std::env::args().collect::<Vec<_>>();
+                    deep_link::handle_args(app.handle(), &argv);
+                    let paths = open_with::BookPaths::from_argv(app.handle(), &argv, &cwd);
+                    open_with::open_books(app.handle(), paths);
                 }
             }
 
-            #[cfg(desktop)]
+            // On mobile the links arrive through the deep link plugin
+            #[cfg(mobile)]
             {
-                app.handle().plugin(tauri_plugin_cli::init())?;
+                use tauri_plugin_deep_link::DeepLinkExt;
+                let app_handle = app.handle().clone();
+                app.deep_link().on_open_url(move |event| {
+                    deep_link::handle_urls(&app_handle, &event.urls());
+                });
+                if let Ok(Some(urls)) = app.deep_link().get_current() {
+                    deep_link::handle_urls(app.handle(), &urls);
+                }
+            }
 
+            #[cfg(desktop)]
+            {
                 let app_handle = app.handle().clone();
                 app.listen("window-ready", move |_| {
-                    let webview = app_handle.get_webview_window("main").unwrap();
-                    webview
-                        .eval("window.__READEST_CLI_ACCESS = true;")
-                        .expect("Failed to set cli access config");
-
                     set_rounded_window(&app_handle, true);
                     #[cfg(target_os = "windows")]
                     if tauri_plugin_os::version()
@@ -219,6 +312,7 @@ pub fn run() {
 
                         let script =
                             format!("window.__READEST_UPDATER_DISABLED = {};", !is_appimage);
+                        let webview = app_handle.get_webview_window("main").unwrap();
                         webview
                             .eval(&script)
                             .expect("Failed to set updater disabled config");
@@ -232,13 +326,8 @@ pub fn run() {
                 let _ = app.deep_link().register_all();
             }
 
-            if let Err(e) = app.handle().plugin(
-                tauri_plugin_log::Builder::default()
-                    .level(log::LevelFilter::Info)
-                    .build(),
-            ) {
-                eprintln!("Failed to initialize tauri_plugin_log: {e}");
-            };
+            http_client::init(app.handle());
+            transfer_queue::init(app.handle())?;
 
             let win_builder = WebviewWindowBuilder::new(app, "main", WebviewUrl::default());
 
@@ -275,31 +364,28 @@ pub fn run() {
             // let win = win_builder.build().unwrap();
             // win.open_devtools();
 
-            #[cfg(target_os = "macos")]
-            macos::menu::setup_macos_menu(app.handle())?;
+            #[cfg(desktop)]
+            menu::setup(app.handle())?;
 
             app.handle().emit("window-ready", ()).unwrap();
 
             Ok(())
         })
-        .build(tauri::generate_context!())
+        .build(context)
         .expect("error while running tauri application")
         .run(
             #[allow(unused_variables)]
             |app_handle, event| {
                 #[cfg(target_os = "macos")]
                 if let tauri::RunEvent::Opened { urls } = event {
-                    let files = urls
-                        .into_iter()
-                        .filter_map(|url| url.to_file_path().ok())
-                        .collect::<Vec<_>>();
-
-                    let app_handler_clone = app_handle.clone();
-                    allow_file_in_scopes(app_handle, files.clone());
-                    app_handle.listen("window-ready", move |_| {
-                        println!("Window is ready, proceeding to handle files.");
-                        set_window_open_with_files(&app_handler_clone, files.clone());
-                    });
+                    deep_link::handle_urls(app_handle, &urls);
+                    let paths = open_with::BookPaths::resolve(
+                        app_handle,
+                        urls.iter().map(|url| url.as_str()),
+                        Path::new("/"),
+                        true,
+                    );
+                    open_with::open_books(app_handle, paths);
                 }
             },
         );
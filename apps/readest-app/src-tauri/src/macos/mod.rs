@@ -1,4 +1,3 @@
 pub mod apple_auth;
-pub mod menu;
 pub mod safari_auth;
 pub mod traffic_light;
@@ -0,0 +1,270 @@
+//! The application menu of the desktop app, with File, View and Help submenus.
+//!
+//! Help items open their pages right away, the others are forwarded to the frontend as typed
+//! `menu-action` events. On macOS the submenus replace the default ones in the global menu bar.
+//! On Linux and Windows every window gets the menu bar, which the user can hide since the
+//! frameless windows draw their own title bar. The accelerators are the same as the frontend's
+//! default shortcuts, so that both lead to the same action.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde::{ser::Serializer, Deserialize, Serialize};
+use tauri::menu::{Menu, MenuEvent, MenuItem, MenuItemBuilder, Submenu, SubmenuBuilder};
+use tauri::{command, AppHandle, Emitter, Manager, State, Window, Wry};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::deep_link::is_book_hash;
+
+type Result<T> = std::result::Result<T, Error>;
+
+const MAIN_WINDOW: &str = "main";
+const MAX_RECENT_BOOKS: usize = 10;
+
+const IMPORT_BOOKS: &str = "import_books";
+const RECENT_PREFIX: &str = "open_recent:";
+const TOGGLE_FULLSCREEN: &str = "toggle_fullscreen";
+const ZOOM_IN: &str = "zoom_in";
+const ZOOM_OUT: &str = "zoom_out";
+const RESET_ZOOM: &str = "reset_zoom";
+const PRIVACY_POLICY: &str = "privacy_policy";
+const REPORT_ISSUE: &str = "report_issue";
+const READEST_HELP: &str = "readest_help";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum MenuAction {
+    ImportBooks,
+    #[serde(rename_all = "camelCase")]
+    OpenRecent {
+        book_id: String,
+    },
+    ToggleFullscreen,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[error("invalid book id {0}")]
+    InvalidBookId(String),
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_ref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentBook {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Default)]
+pub struct AppMenu {
+    recent: Mutex<Option<Submenu<Wry>>>, // Set once the menu is built
+    hidden: AtomicBool,
+}
+
+// An item with the accelerator of its shortcut in the frontend, see shortcuts.ts.
+fn item(app: &AppHandle, id: &str, text: &str, accelerator: &str) -> tauri::Result<MenuItem<Wry>> {
+    MenuItemBuilder::with_id(id, text)
+        .accelerator(accelerator)
+        .build(app)
+}
+
+fn build_submenus(app: &AppHandle) -> tauri::Result<[Submenu<Wry>; 3]> {
+    let recent = SubmenuBuilder::new(app, "Open Recent")
+        .enabled(false)
+        .build()?;
+    app.state::<AppMenu>()
+        .recent
+        .lock()
+        .unwrap()
+        .replace(recent.clone());
+
+    let file = SubmenuBuilder::new(app, "File")
+        .text(IMPORT_BOOKS, "Import Books...")
+        .item(&recent)
+        .separator()
+        .close_window();
+    // The app submenu already has Quit on macOS
+    #[cfg(not(target_os = "macos"))]
+    let file = file.separator().quit();
+
+    let view = SubmenuBuilder::new(app, "View")
+        .item(&item(app, TOGGLE_FULLSCREEN, "Toggle Fullscreen", "F11")?)
+        .separator()
+        .item(&item(app, ZOOM_IN, "Zoom In", "CmdOrCtrl+=")?)
+        .item(&item(app, ZOOM_OUT, "Zoom Out", "CmdOrCtrl+-")?)
+        .item(&item(app, RESET_ZOOM, "Reset Zoom", "CmdOrCtrl+0")?);
+
+    let help = SubmenuBuilder::new(app, "Help")
+        .text(PRIVACY_POLICY, "Privacy Policy")
+        .separator()
+        .text(REPORT_ISSUE, "Report An Issue...")
+        .text(READEST_HELP, "Readest Help");
+
+    Ok([file.build()?, view.build()?, help.build()?])
+}
+
+// Replaces the default submenus with the same titles in place, keeping App, Edit and Window.
+#[cfg(target_os = "macos")]
+fn install(app: &AppHandle, submenus: [Submenu<Wry>; 3]) -> tauri::Result<()> {
+    let menu = match app.menu() {
+        Some(menu) => menu,
+        None => Menu::default(app)?,
+    };
+    for submenu in submenus {
+        let text = submenu.text()?;
+        let position = menu.items()?.iter().position(|item| {
+            item.as_submenu()
+                .is_some_and(|item| item.text().is_ok_and(|t| t == text))
+        });
+        match position {
+            Some(position) => {
+                menu.remove_at(position)?;
+                menu.insert(&submenu, position)?;
+            }
+            None => menu.append(&submenu)?,
+        }
+    }
+    app.set_menu(menu)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn install(app: &AppHandle, submenus: [Submenu<Wry>; 3]) -> tauri::Result<()> {
+    let [file, view, help] = submenus;
+    app.set_menu(Menu::with_items(app, &[&file, &view, &help])?)?;
+    for window in app.windows().values() {
+        apply_visibility(window);
+    }
+    Ok(())
+}
+
+pub fn setup(app: &AppHandle) -> tauri::Result<()> {
+    install(app, build_submenus(app)?)?;
+    app.on_menu_event(|app, event| {
+        handle_menu_event(app, &event);
+    });
+    Ok(())
+}
+
+// Shows or hides the menu bar of a window as the user chose. The macOS menu bar is global.
+pub fn apply_visibility(window: &Window) {
+    #[cfg(not(target_os = "macos"))]
+    {
+        let result = if window.state::<AppMenu>().hidden.load(Ordering::Relaxed) {
+            window.hide_menu()
+        } else {
+            window.show_menu()
+        };
+        if let Err(e) = result {
+            log::warn!(
+                "Failed to set the menu visibility of {}: {e}",
+                window.label()
+            );
+        }
+    }
+    #[cfg(target_os = "macos")]
+    let _ = window;
+}
+
+// Library actions go to the main window, view actions to the window the user is looking at.
+fn emit_action(app: &AppHandle, action: MenuAction) {
+    let label = match action {
+        MenuAction::ImportBooks | MenuAction::OpenRecent { .. } => MAIN_WINDOW.to_string(),
+        _ => app
+            .webview_windows()
+            .into_values()
+            .find(|window| window.is_focused().unwrap_or(false))
+            .map_or(MAIN_WINDOW.to_string(), |window| window.label().to_string()),
+    };
+    if let Some(window) = app.get_webview_window(&label) {
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+    if let Err(e) = app.emit_to(&label, "menu-action", action) {
+        log::error!("Failed to emit menu-action: {e}");
+    }
+}
+
+fn handle_menu_event(app: &AppHandle, event: &MenuEvent) {
+    let opener = app.opener();
+    let id = event.id().as_ref();
+    let action = match id {
+        PRIVACY_POLICY => {
+            let _ = opener.open_url("https://readest.com/privacy-policy", None::<&str>);
+            return;
+        }
+        REPORT_ISSUE => {
+            let _ = opener.open_url("https://github.com/readest/readest/issues", None::<&str>);
+            return;
+        }
+        READEST_HELP => {
+            let _ = opener.open_url("https://readest.com/support", None::<&str>);
+            return;
+        }
+        IMPORT_BOOKS => MenuAction::ImportBooks,
+        TOGGLE_FULLSCREEN => MenuAction::ToggleFullscreen,
+        ZOOM_IN => MenuAction::ZoomIn,
+        ZOOM_OUT => MenuAction::ZoomOut,
+        RESET_ZOOM => MenuAction::ResetZoom,
+        _ => match id.strip_prefix(RECENT_PREFIX) {
+            Some(book_id) => MenuAction::OpenRecent {
+                book_id: book_id.to_string(),
+            },
+            None => return,
+        },
+    };
+    emit_action(app, action);
+}
+
+// Replaces the books in File > Open Recent, most recent first.
+#[command]
+pub fn set_recent_books(
+    app: AppHandle,
+    menu: State<'_, AppMenu>,
+    books: Vec<RecentBook>,
+) -> Result<()> {
+    if let Some(book) = books.iter().find(|book| !is_book_hash(&book.id)) {
+        return Err(Error::InvalidBookId(book.id.clone()));
+    }
+    let Some(recent) = menu.recent.lock().unwrap().clone() else {
+        return Ok(());
+    };
+    for item in recent.items()? {
+        recent.remove(&item)?;
+    }
+    for book in books.iter().take(MAX_RECENT_BOOKS) {
+        let id = format!("{RECENT_PREFIX}{}", book.id.to_lowercase());
+        recent.append(&MenuItem::with_id(
+            &app,
+            id,
+            &book.title,
+            true,
+            None::<&str>,
+        )?)?;
+    }
+    recent.set_enabled(!books.is_empty())?;
+    Ok(())
+}
+
+// Shows or hides the menu bar of every window on Linux and Windows.
+#[command]
+pub fn set_app_menu_visible(app: AppHandle, menu: State<'_, AppMenu>, visible: bool) {
+    menu.hidden.store(!visible, Ordering::Relaxed);
+    for window in app.windows().values() {
+        apply_visibility(window);
+    }
+}
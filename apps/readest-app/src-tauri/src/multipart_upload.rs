@@ -0,0 +1,354 @@
+//! Upload large files to S3-compatible storage (S3, R2, MinIO) with the multipart upload API.
+//!
+//! The server presigns every request (initiate, each part, complete and abort) the same way
+//! it presigns single PUTs today, so the client never needs storage credentials. Parts are
+//! uploaded in parallel and retried individually, so a failure late in a large upload only
+//! repeats the affected part.
+
+use std::cmp::min;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::stream::{self, StreamExt, TryStreamExt};
+use quick_xml::{escape::escape, events::Event, Reader};
+use serde::Deserialize;
+use tauri::{command, ipc::Channel, State};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::codec::{BytesCodec, FramedRead};
+
+use crate::http_client::HttpClient;
+use crate::transfer_config::TransferOverrides;
+use crate::transfer_file::{with_retry, Error, ProgressPayload, Result, TransferStats};
+use crate::transfer_registry::{TransferHandle, TransferRegistry};
+
+// Slice size of a part body, so that pausing and bandwidth limits take effect within a part.
+const BODY_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultipartUploadUrls {
+    pub part_urls: Vec<String>, // Presigned UploadPart URLs, ordered by part number
+    pub complete_url: String,   // Presigned CompleteMultipartUpload URL
+    pub abort_url: Option<String>, // Presigned AbortMultipartUpload URL, used on failure
+}
+
+// Starts a multipart upload with a presigned CreateMultipartUpload URL and returns its upload id.
+#[command]
+pub async fn create_multipart_upload(
+    http: State<'_, HttpClient>,
+    url: &str,
+    headers: HashMap<String, String>,
+) -> Result<String> {
+    let mut request = http.client().post(url);
+    for (key, value) in headers {
+        request = request.header(&key, value);
+    }
+
+    let response = request.send().await?;
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(Error::HttpErrorCode(status.as_u16(), text));
+    }
+    parse_response(&text)?
+        .upload_id
+        .ok_or_else(|| Error::MultipartUpload("response has no UploadId".into()))
+}
+
+#[command]
+pub async fn abort_multipart_upload(http: State<'_, HttpClient>, url: &str) -> Result<()> {
+    abort_upload(&http.client(), url).await
+}
+
+async fn abort_upload(client: &reqwest::Client, url: &str) -> Result<()> {
+    let response = client.delete(url).send().await?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(Error::HttpErrorCode(
+            response.status().as_u16(),
+            response.text().await.unwrap_or_default(),
+        ))
+    }
+}
+
+// Uploads `file_path` in parts of `part_size` bytes (the last one may be shorter) and completes
+// the upload, returning the response of CompleteMultipartUpload. The upload is aborted if it
+// fails or is cancelled, provided an abort URL was given.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_file_multipart(
+    registry: State<'_, TransferRegistry>,
+    http: State<'_, HttpClient>,
+    id: u32,
+    file_path: &str,
+    part_size: u64,
+    urls: MultipartUploadUrls,
+    headers: HashMap<String, String>,
+    config: Option<TransferOverrides>,
+    on_progress: Channel<ProgressPayload>,
+) -> Result<String> {
+    let transfer = registry.register(id, config);
+    let client = http.client();
+    let upload = upload_parts(
+        &transfer,
+        &client,
+        file_path,
+        part_size,
+        &urls,
+        &headers,
+        on_progress,
+    );
+    let result = transfer
+        .until_cancelled(upload)
+        .await
+        .unwrap_or(Err(Error::Cancelled));
+
+    if let (Err(e), Some(abort_url)) = (&result, &urls.abort_url) {
+        log::warn!("Aborting multipart upload of {file_path}: {e}");
+        if let Err(e) = abort_upload(&client, abort_url).await {
+            log::error!("Failed to abort multipart upload: {e}");
+        }
+    }
+    result
+}
+
+async fn upload_parts(
+    transfer: &TransferHandle,
+    client: &reqwest::Client,
+    file_path: &str,
+    part_size: u64,
+    urls: &MultipartUploadUrls,
+    headers: &HashMap<String, String>,
+    on_progress: Channel<ProgressPayload>,
+) -> Result<String> {
+    let file_len = tokio::fs::metadata(file_path).await?.len();
+    if part_size == 0 {
+        return Err(Error::MultipartUpload("part size must not be zero".into()));
+    }
+    let part_count = file_len.div_ceil(part_size).max(1);
+    if part_count != urls.part_urls.len() as u64 {
+        return Err(Error::MultipartUpload(format!(
+            "{part_count} parts of {part_size} bytes need as many URLs, got {}",
+            urls.part_urls.len()
+        )));
+    }
+
+    let stats = Arc::new(TransferStats::new(file_len, 0));
+
+    let mut etags: Vec<(usize, String)> = stream::iter(urls.part_urls.iter().enumerate())
+        .map(|(index, url)| {
+            let start = index as u64 * part_size;
+            let len = min(part_size, file_len - start);
+            let client = client.clone();
+            let stats = Arc::clone(&stats);
+            let on_progress = on_progress.clone();
+            async move {
+                let etag = with_retry(&format!("part {}", index + 1), || {
+                    put_part(
+                        transfer, &stats, &client, url, headers, file_path, start, len,
+                    )
+                })
+                .await?;
+
+                stats.record_completed(len as usize);
+                let _ = on_progress.send(stats.payload());
+                Ok::<_, Error>((index + 1, etag))
+            }
+        })
+        .buffer_unordered(transfer.config().concurrency)
+        .try_collect()
+        .await?;
+    etags.sort_unstable_by_key(|(part_number, _)| *part_number);
+
+    complete_upload(client, &urls.complete_url, &etags).await
+}
+
+// Uploads the `len` bytes at `start` of the file as a single part, reading them as the body is
+// sent, and returns the ETag the server assigned to the part.
+#[allow(clippy::too_many_arguments)]
+async fn put_part(
+    transfer: &TransferHandle,
+    stats: &Arc<TransferStats>,
+    client: &reqwest::Client,
+    url: &str,
+    headers: &HashMap<String, String>,
+    file_path: &str,
+    start: u64,
+    len: u64,
+) -> Result<String> {
+    let mut file = File::open(file_path).await?;
+    file.seek(std::io::SeekFrom::Start(start)).await?;
+    let body_transfer = transfer.clone();
+    let body_stats = Arc::clone(stats);
+    let body = FramedRead::with_capacity(file.take(len), BytesCodec::new(), BODY_CHUNK_SIZE)
+        .map_ok(|chunk| chunk.freeze())
+        .and_then(move |chunk| {
+            let transfer = body_transfer.clone();
+            let stats = Arc::clone(&body_stats);
+            async move {
+                transfer.wait_if_paused().await;
+                transfer.throttle(chunk.len()).await;
+                stats.record_received(chunk.len());
+                Ok(chunk)
+            }
+        });
+
+    let mut request = client
+        .put(url)
+        .header(reqwest::header::CONTENT_LENGTH, len)
+        .body(reqwest::Body::wrap_stream(body));
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpErrorCode(
+            response.status().as_u16(),
+            response.text().await.unwrap_or_default(),
+        ));
+    }
+    response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| Error::MultipartUpload("part response has no ETag".into()))
+}
+
+async fn complete_upload(
+    client: &reqwest::Client,
+    url: &str,
+    etags: &[(usize, String)],
+) -> Result<String> {
+    let parts = etags
+        .iter()
+        .map(|(part_number, etag)| {
+            format!(
+                "<Part><PartNumber>{part_number}</PartNumber><ETag>{}</ETag></Part>",
+                escape(etag.as_str())
+            )
+        })
+        .collect::<String>();
+    let body = format!(
+        "<CompleteMultipartUpload xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">{parts}</CompleteMultipartUpload>"
+    );
+
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/xml")
+        .body(body)
+        .send()
+        .await?;
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(Error::HttpErrorCode(status.as_u16(), text));
+    }
+    // S3 may report a failure of CompleteMultipartUpload in the body of a 200 response
+    if let Some(error) = parse_response(&text)?.error {
+        return Err(Error::MultipartUpload(error));
+    }
+    Ok(text)
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct S3Response {
+    upload_id: Option<String>, // UploadId of an InitiateMultipartUploadResult
+    error: Option<String>,     // Message, or else Code, of an Error
+}
+
+// Reads the fields of S3 responses the multipart upload needs; other elements are ignored.
+fn parse_response(xml: &str) -> Result<S3Response> {
+    let invalid = |e: quick_xml::Error| Error::MultipartUpload(format!("invalid response: {e}"));
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut response = S3Response::default();
+    let mut code = None;
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(start) => path.push(start.local_name().as_ref().to_vec()),
+            Event::End(_) => {
+                path.pop();
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(invalid)?.into_owned();
+                let path = path.iter().map(Vec::as_slice).collect::<Vec<_>>();
+                match path.as_slice() {
+                    [b"InitiateMultipartUploadResult", b"UploadId"] => {
+                        response.upload_id = Some(text)
+                    }
+                    [b"Error", b"Message"] => response.error = Some(text),
+                    [b"Error", b"Code"] => code = Some(text),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if response.error.is_none() {
+        response.error = code;
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_upload_id() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<InitiateMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <Bucket>books</Bucket>
+  <Key>book.epub</Key>
+  <UploadId>VXBsb2FkIElE&amp;1</UploadId>
+</InitiateMultipartUploadResult>"#;
+        let response = parse_response(xml).unwrap();
+        assert_eq!(response.upload_id.as_deref(), Some("VXBsb2FkIElE&1"));
+        assert_eq!(response.error, None);
+    }
+
+    #[test]
+    fn parses_error_message() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+  <Code>InternalError</Code>
+  <Message>We encountered an internal error &lt;retry&gt;.</Message>
+  <UploadId>ignored</UploadId>
+</Error>"#;
+        let response = parse_response(xml).unwrap();
+        assert_eq!(
+            response.error.as_deref(),
+            Some("We encountered an internal error <retry>.")
+        );
+        assert_eq!(response.upload_id, None);
+    }
+
+    #[test]
+    fn falls_back_to_error_code() {
+        let response = parse_response("<Error><Code>SlowDown</Code></Error>").unwrap();
+        assert_eq!(response.error.as_deref(), Some("SlowDown"));
+    }
+
+    #[test]
+    fn ignores_complete_result() {
+        let xml = r#"<CompleteMultipartUploadResult>
+  <Location>https://s3.example.com/books/book.epub</Location>
+  <ETag>"3858f62230ac3c915f300c664312c11f-9"</ETag>
+</CompleteMultipartUploadResult>"#;
+        assert_eq!(parse_response(xml).unwrap(), S3Response::default());
+    }
+
+    #[test]
+    fn rejects_malformed_xml() {
+        assert!(parse_response("<Error><Code>x</Message></Error>").is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Loopback redirect for OAuth sign-in on desktop, for environments where the app cannot
+//! register its URL scheme (development builds, Flatpak, ...).
+//!
+//! Each flow gets a random `state` that is also part of the redirect URI, a PKCE verifier
+//! (RFC 7636) and a timeout. The frontend puts the S256 challenge of the verifier into the
+//! authorization URL. The server only listens on 127.0.0.1, answers a single valid redirect
+//! and then shuts down, emitting the authorization code with the verifier to exchange it as
+//! an `oauth-callback` event to the window that started the flow.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query, State as AxumState},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{command, AppHandle, Emitter, Manager, State, Window};
+use tokio_util::sync::CancellationToken;
+
+use crate::embedded_server::{constant_time_eq, random_token, EmbeddedServer};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const STATE_LENGTH: usize = 32;
+const VERIFIER_BYTES: usize = 32; // Encoded as 43 characters, as recommended by RFC 7636
+
+const DONE_PAGE: &str = r#"<!doctype html>
+<html><head><meta charset="utf-8"><title>Readest</title></head><body>
+<p>You can close this window and return to Readest.</p>
+</body></html>"#;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthLoopbackOptions {
+    pub port: Option<u16>,         // A free port is picked if not given
+    pub timeout_secs: Option<u64>, // Defaults to 5 minutes
+}
+
+// What the frontend needs to build the authorization URL.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthLoopback {
+    pub port: u16,
+    pub redirect_uri: String,
+    pub state: String,
+    pub code_challenge: String,
+    pub code_challenge_method: &'static str,
+}
+
+// Payload of the `oauth-callback` event.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthCallback {
+    pub code: Option<String>,
+    pub code_verifier: Option<String>, // Set together with the code, for the token request
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+struct Flow {
+    app: AppHandle,
+    window: String, // Label of the window that started the flow
+    state: String,
+    code_verifier: String,
+    finished: AtomicBool,
+    done: CancellationToken, // Cancelled once the flow has ended, successfully or not
+}
+
+impl Flow {
+    // Ends the flow and emits its outcome; only the first call has an effect.
+    fn finish(&self, callback: OAuthCallback) -> bool {
+        if self.finished.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        self.done.cancel();
+        if let Err(e) = self.app.emit_to(&self.window, "oauth-callback", callback) {
+            log::error!("Failed to emit the OAuth callback: {e}");
+        }
+        true
+    }
+}
+
+fn code_verifier() -> String {
+    let mut bytes = [0u8; VERIFIER_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+// The state is part of the path, and must also match the state parameter if the provider
+// returns one.
+fn state_matches(expected: &str, path: &str, query: Option<&str>) -> bool {
+    let matches = |state: &str| constant_time_eq(state.as_bytes(), expected.as_bytes());
+    matches(path) && query.map_or(true, matches)
+}
+
+fn router(flow: Arc<Flow>) -> Router {
+    Router::new()
+        .route("/:state", get(redirect))
+        .with_state(flow)
+}
+
+async fn redirect(
+    AxumState(flow): AxumState<Arc<Flow>>,
+    Path(state): Path<String>,
+    Query(mut params): Query<HashMap<String, String>>,
+) -> Response {
+    if flow.done.is_cancelled() {
+        return (StatusCode::GONE, "This sign-in has already ended").into_response();
+    }
+    if !state_matches(&flow.state, &state, params.get("state").map(String::as_str)) {
+        log::warn!("Rejected an OAuth redirect with an invalid state");
+        return (StatusCode::BAD_REQUEST, "Invalid state").into_response();
+    }
+
+    let mut take = |name: &str| params.remove(name).filter(|value| !value.is_empty());
+    let mut callback = OAuthCallback {
+        code: take("code"),
+        error: take("error"),
+        error_description: take("error_description"),
+        ..Default::default()
+    };
+    if callback.code.is_none() && callback.error.is_none() {
+        return (StatusCode::BAD_REQUEST, "Missing authorization code").into_response();
+    }
+    if callback.code.is_some() {
+        callback.code_verifier = Some(flow.code_verifier.clone());
+    }
+    if flow.finish(callback) {
+        Html(DONE_PAGE).into_response()
+    } else {
+        (StatusCode::GONE, "This sign-in has already ended").into_response()
+    }
+}
+
+struct RunningFlow {
+    server: EmbeddedServer,
+    flow: Arc<Flow>,
+}
+
+#[derive(Default)]
+pub struct OAuthLoopbackServer(Mutex<Option<RunningFlow>>);
+
+impl OAuthLoopbackServer {
+    // Stops the server of `flow`, unless a newer flow has replaced it in the meantime.
+    fn stop(&self, flow: &Arc<Flow>) {
+        let mut running = self.0.lock().unwrap();
+        if running
+            .as_ref()
+            .is_some_and(|running| Arc::ptr_eq(&running.flow, flow))
+        {
+            drop(running.take());
+        }
+    }
+}
+
+// Starts a new sign-in flow, ending the previous one if it is still running.
+#[command]
+pub async fn start_oauth_loopback(
+    app: AppHandle,
+    window: Window,
+    server: State<'_, OAuthLoopbackServer>,
+    options: Option<OAuthLoopbackOptions>,
+) -> Result<OAuthLoopback, String> {
+    let options = options.unwrap_or_default();
+    if let Some(running) = server.0.lock().unwrap().take() {
+        running.flow.done.cancel();
+    }
+
+    let flow = Arc::new(Flow {
+        app: app.clone(),
+        window: window.label().to_string(),
+        state: random_token(STATE_LENGTH),
+        code_verifier: code_verifier(),
+        finished: AtomicBool::new(false),
+        done: CancellationToken::new(),
+    });
+    let running = EmbeddedServer::start_loopback(
+        "OAuth loopback",
+        router(Arc::clone(&flow)),
+        options.port.unwrap_or(0),
+    )
+    .await
+    .map_err(|e| format!("Failed to start OAuth loopback server: {e}"))?;
+
+    // A literal address rather than localhost, which may resolve to ::1 (RFC 8252, section 8.3)
+    let port = running.port();
+    let loopback = OAuthLoopback {
+        port,
+        redirect_uri: format!("http://127.0.0.1:{port}/{}", flow.state),
+        state: flow.state.clone(),
+        code_challenge: code_challenge(&flow.code_verifier),
+        code_challenge_method: "S256",
+    };
+    *server.0.lock().unwrap() = Some(RunningFlow {
+        server: running,
+        flow: Arc::clone(&flow),
+    });
+
+    // Shuts the server down after the first valid redirect, or reports a timeout
+    let timeout = options
+        .timeout_secs
+        .map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+    tauri::async_runtime::spawn(async move {
+        tokio::select! {
+            _ = flow.done.cancelled() => {}
+            _ = tokio::time::sleep(timeout) => {
+                flow.finish(OAuthCallback {
+                    error: Some("timeout".into()),
+                    error_description: Some("The sign-in was not completed in time".into()),
+                    ..Default::default()
+                });
+            }
+        }
+        // Let the browser receive the final page before the server goes away
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        flow.app.state::<OAuthLoopbackServer>().stop(&flow);
+    });
+    Ok(loopback)
+}
+
+// Ends the running sign-in flow without emitting an event.
+#[command]
+pub fn cancel_oauth_loopback(server: State<'_, OAuthLoopbackServer>) {
+    if let Some(running) = server.0.lock().unwrap().take() {
+        running.flow.done.cancel();
+        running.server.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_s256_challenges() {
+        // Example of RFC 7636, appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn makes_random_verifiers() {
+        let verifier = code_verifier();
+        assert_eq!(verifier.len(), 43);
+        assert!(verifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_ne!(verifier, code_verifier());
+    }
+
+    #[test]
+    fn checks_states() {
+        let state = "Xq3v9LmZ";
+        assert!(state_matches(state, "Xq3v9LmZ", None));
+        assert!(state_matches(state, "Xq3v9LmZ", Some("Xq3v9LmZ")));
+        assert!(!state_matches(state, "Xq3v9Lm", None));
+        assert!(!state_matches(state, "xq3v9lmz", None));
+        assert!(!state_matches(state, "", None));
+        assert!(!state_matches(state, "Xq3v9LmZ", Some("other")));
+        assert!(!state_matches(state, "Xq3v9LmZ", Some("")));
+        assert!(!state_matches(state, "other", Some("Xq3v9LmZ")));
+    }
+}
@@ -0,0 +1,405 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use md5::Md5;
+use reqwest::header::{ACCEPT, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Method, Response, StatusCode, Url};
+use sha2::{Digest, Sha256};
+
+use super::{Error, OpdsCredentials, Result};
+use crate::transfer_file::Authorize;
+
+pub struct OpdsClient {
+    client: reqwest::Client,
+    credentials: Option<OpdsCredentials>,
+}
+
+impl OpdsClient {
+    pub fn new(client: reqwest::Client, credentials: Option<OpdsCredentials>) -> Self {
+        Self {
+            client,
+            credentials: credentials.filter(|credentials| !credentials.username.is_empty()),
+        }
+    }
+
+    // Sends a GET request and, if the server asks for it, repeats it with Basic or Digest
+    // authentication. Credentials are never sent before the server asked for them.
+    pub async fn get(&self, url: &Url, accept: &str) -> Result<Response> {
+        let response = self
+            .client
+            .get(url.clone())
+            .header(ACCEPT, accept)
+            .send()
+            .await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return error_for_status(response).await;
+        }
+
+        // The challenge is for the URL that answered, which may differ after redirects
+        let url = response.url().clone();
+        let authorization = self.authenticator(&response)?.header(&Method::GET, &url);
+        let response = self
+            .client
+            .get(url)
+            .header(ACCEPT, accept)
+            .header(AUTHORIZATION, authorization)
+            .send()
+            .await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(Error::Unauthorized);
+        }
+        error_for_status(response).await
+    }
+
+    // Returns what authorizes the requests of a download of `url`, if the server requires it,
+    // together with the URL it is valid for, which is where `url` redirects to.
+    pub async fn authorization(&self, url: &Url) -> Result<Option<(Url, Authorize)>> {
+        if self.credentials.is_none() {
+            return Ok(None);
+        }
+        // Only the headers are needed, the body is dropped unread
+        let response = self.client.get(url.clone()).send().await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(None);
+        }
+        let authenticator = self.authenticator(&response)?;
+        let url = response.url().clone();
+        let authorize_url = url.clone();
+        let authorize: Authorize =
+            Arc::new(move |method| authenticator.header(method, &authorize_url));
+        Ok(Some((url, authorize)))
+    }
+
+    // Answers the challenges of a 401 response, preferring Digest since it does not reveal
+    // the password.
+    fn authenticator(&self, response: &Response) -> Result<Authenticator> {
+        let challenges = response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(Challenge::parse)
+            .collect::<Vec<_>>();
+        let realm = challenges
+            .iter()
+            .find_map(|challenge| challenge.param("realm"))
+            .unwrap_or_default()
+            .to_string();
+        let Some(credentials) = &self.credentials else {
+            return Err(Error::AuthenticationRequired(realm));
+        };
+
+        let digest = challenges
+            .iter()
+            .find(|challenge| challenge.scheme.eq_ignore_ascii_case("digest"));
+        if let Some(challenge) = digest {
+            return Ok(Authenticator::Digest {
+                challenge: DigestChallenge::new(challenge)?,
+                credentials: credentials.clone(),
+                nc: AtomicU32::new(0),
+            });
+        }
+        if challenges
+            .iter()
+            .any(|challenge| challenge.scheme.eq_ignore_ascii_case("basic"))
+        {
+            return Ok(Authenticator::Basic(basic_authorization(credentials)));
+        }
+        Err(Error::InvalidResponse(
+            "the server asked for an unsupported authentication scheme".into(),
+        ))
+    }
+}
+
+async fn error_for_status(response: Response) -> Result<Response> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(Error::HttpErrorCode(
+            response.status().as_u16(),
+            response.text().await.unwrap_or_default(),
+        ))
+    }
+}
+
+fn basic_authorization(credentials: &OpdsCredentials) -> String {
+    let token = format!("{}:{}", credentials.username, credentials.password);
+    format!("Basic {}", BASE64.encode(token))
+}
+
+// Builds the Authorization headers for the requests that follow a challenge.
+enum Authenticator {
+    Basic(String),
+    Digest {
+        challenge: DigestChallenge,
+        credentials: OpdsCredentials,
+        nc: AtomicU32, // Number of requests sent with the challenge's nonce
+    },
+}
+
+impl Authenticator {
+    // Digest headers count the requests and have a new client nonce each time, so a fresh one
+    // is needed for every request.
+    fn header(&self, method: &Method, url: &Url) -> String {
+        match self {
+            Authenticator::Basic(header) => header.clone(),
+            Authenticator::Digest {
+                challenge,
+                credentials,
+                nc,
+            } => {
+                let nc = nc.fetch_add(1, Ordering::Relaxed) + 1;
+                let cnonce = challenge.cnonce(nc);
+                let uri = match url.query() {
+                    Some(query) => format!("{}?{query}", url.path()),
+                    None => url.path().to_string(),
+                };
+                challenge.authorization(credentials, method, &uri, nc, &cnonce)
+            }
+        }
+    }
+}
+
+// A WWW-Authenticate challenge, e.g. `Digest realm="calibre", nonce="...", qop="auth"`.
+struct Challenge {
+    scheme: String,
+    params: Vec<(String, String)>,
+}
+
+impl Challenge {
+    // Parses the challenges of a header, which may offer several schemes at once, as in
+    // `Basic realm="x", Digest realm="x", nonce="..."`.
+    fn parse(header: &str) -> Vec<Self> {
+        let mut challenges = Vec::<Challenge>::new();
+        let mut chars = header.chars().peekable();
+        loop {
+            while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+            let token = std::iter::from_fn(|| {
+                chars.next_if(|c| !c.is_whitespace() && *c != '=' && *c != ',')
+            })
+            .collect::<String>();
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.next_if_eq(&'=').is_none() {
+                // A token that is not followed by `=` starts the next challenge
+                match token.is_empty() {
+                    true if chars.peek().is_none() => break,
+                    true => continue,
+                    false => challenges.push(Challenge {
+                        scheme: token,
+                        params: Vec::new(),
+                    }),
+                }
+                continue;
+            }
+
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            let mut value = String::new();
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => value.extend(chars.next()),
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                value.extend(std::iter::from_fn(|| chars.next_if(|c| *c != ',')));
+            }
+            if let Some(challenge) = challenges.last_mut().filter(|_| !token.is_empty()) {
+                challenge
+                    .params
+                    .push((token.to_lowercase(), value.trim().to_string()));
+            }
+        }
+        challenges
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// A Digest challenge that can be answered, see RFC 7616. Only MD5 and SHA-256 are supported,
+// with or without sessions, and only qop=auth.
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: String,
+    hash: fn(&str) -> String,
+    session: bool, // The algorithm is one of the -sess variants
+    qop: bool,     // The server offered qop=auth rather than the legacy RFC 2069 scheme
+}
+
+impl DigestChallenge {
+    fn new(challenge: &Challenge) -> Result<Self> {
+        let invalid =
+            |reason: &str| Error::InvalidResponse(format!("invalid Digest challenge: {reason}"));
+        let nonce = challenge
+            .param("nonce")
+            .ok_or_else(|| invalid("missing nonce"))?;
+        let algorithm = challenge.param("algorithm").unwrap_or("MD5");
+        let upper = algorithm.to_uppercase();
+        let hash: fn(&str) -> String = match upper.trim_end_matches("-SESS") {
+            "MD5" => |data| format!("{:x}", Md5::digest(data.as_bytes())),
+            "SHA-256" => |data| format!("{:x}", Sha256::digest(data.as_bytes())),
+            _ => return Err(invalid(&format!("unsupported algorithm {algorithm}"))),
+        };
+        let qop = challenge.param("qop").map(|qop| {
+            qop.split(',')
+                .map(str::trim)
+                .any(|qop| qop.eq_ignore_ascii_case("auth"))
+        });
+        if qop == Some(false) {
+            return Err(invalid("only qop=auth-int is offered"));
+        }
+        Ok(Self {
+            realm: challenge.param("realm").unwrap_or_default().to_string(),
+            nonce: nonce.to_string(),
+            opaque: challenge.param("opaque").map(str::to_string),
+            algorithm: algorithm.to_string(),
+            hash,
+            session: upper.ends_with("-SESS"),
+            qop: qop.is_some(),
+        })
+    }
+
+    // A client nonce that differs for every request.
+    fn cnonce(&self, nc: u32) -> String {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let mut cnonce = (self.hash)(&format!("{}:{nc}:{nanos}", self.nonce));
+        cnonce.truncate(16);
+        cnonce
+    }
+
+    fn authorization(
+        &self,
+        credentials: &OpdsCredentials,
+        method: &Method,
+        uri: &str,
+        nc: u32,
+        cnonce: &str,
+    ) -> String {
+        let hash = self.hash;
+        let (realm, nonce) = (&self.realm, &self.nonce);
+        let nc = format!("{nc:08x}");
+        let mut ha1 = hash(&format!(
+            "{}:{realm}:{}",
+            credentials.username, credentials.password
+        ));
+        if self.session {
+            ha1 = hash(&format!("{ha1}:{nonce}:{cnonce}"));
+        }
+        let ha2 = hash(&format!("{method}:{uri}"));
+        let response = match self.qop {
+            true => hash(&format!("{ha1}:{nonce}:{nc}:{cnonce}:auth:{ha2}")),
+            false => hash(&format!("{ha1}:{nonce}:{ha2}")),
+        };
+
+        let quote = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut header = format!(
+            r#"Digest username="{}", realm="{}", nonce="{}", uri="{}", algorithm={}, response="{response}""#,
+            quote(&credentials.username),
+            quote(realm),
+            quote(nonce),
+            quote(uri),
+            self.algorithm,
+        );
+        if self.qop {
+            header.push_str(&format!(r#", qop=auth, nc={nc}, cnonce="{cnonce}""#));
+        }
+        if let Some(opaque) = &self.opaque {
+            header.push_str(&format!(r#", opaque="{}""#, quote(opaque)));
+        }
+        header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example of RFC 7616, section 3.9.1
+    const CHALLENGE: &str = r#"Digest realm="http-auth@example.org", qop="auth, auth-int", algorithm=ALGORITHM, nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#;
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+    fn credentials() -> OpdsCredentials {
+        OpdsCredentials {
+            username: "Mufasa".into(),
+            password: "Circle of Life".into(),
+        }
+    }
+
+    fn digest_response(algorithm: &str) -> String {
+        let challenges = Challenge::parse(&CHALLENGE.replace("ALGORITHM", algorithm));
+        let challenge = DigestChallenge::new(&challenges[0]).unwrap();
+        challenge.authorization(&credentials(), &Method::GET, "/dir/index.html", 1, CNONCE)
+    }
+
+    #[test]
+    fn answers_rfc_7616_md5_example() {
+        let header = digest_response("MD5");
+        assert!(header.contains(r#"response="8ca523f5e9506fed4657c9700eebdbec""#));
+        assert!(header.contains(
+            r#"qop=auth, nc=00000001, cnonce="f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ""#
+        ));
+        assert!(header.contains(r#"opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#));
+    }
+
+    #[test]
+    fn answers_rfc_7616_sha256_example() {
+        let header = digest_response("SHA-256");
+        assert!(header.contains(
+            r#"response="753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1""#
+        ));
+    }
+
+    #[test]
+    fn counts_requests_with_fresh_client_nonces() {
+        let challenges = Challenge::parse(&CHALLENGE.replace("ALGORITHM", "MD5"));
+        let authenticator = Authenticator::Digest {
+            challenge: DigestChallenge::new(&challenges[0]).unwrap(),
+            credentials: credentials(),
+            nc: AtomicU32::new(0),
+        };
+        let url = Url::parse("http://example.org/dir/index.html").unwrap();
+        let first = authenticator.header(&Method::GET, &url);
+        let second = authenticator.header(&Method::GET, &url);
+        assert!(first.contains("nc=00000001"));
+        assert!(second.contains("nc=00000002"));
+        let cnonce = |header: &str| header.split("cnonce=").nth(1).unwrap().to_string();
+        assert_ne!(cnonce(&first), cnonce(&second));
+    }
+
+    #[test]
+    fn parses_several_challenges_in_one_header() {
+        let challenges = Challenge::parse(
+            r#"Basic realm="x, y", Digest realm="calibre", nonce=abc , qop="auth""#,
+        );
+        assert_eq!(challenges.len(), 2);
+        assert_eq!(challenges[0].scheme, "Basic");
+        assert_eq!(challenges[0].param("realm"), Some("x, y"));
+        assert_eq!(challenges[1].scheme, "Digest");
+        assert_eq!(challenges[1].param("realm"), Some("calibre"));
+        assert_eq!(challenges[1].param("nonce"), Some("abc"));
+        assert_eq!(challenges[1].param("qop"), Some("auth"));
+    }
+
+    #[test]
+    fn rejects_unsupported_digest_challenges() {
+        let challenges = Challenge::parse(r#"Digest realm="x", nonce="n", algorithm=SHA-512-256"#);
+        assert!(DigestChallenge::new(&challenges[0]).is_err());
+        let challenges = Challenge::parse(r#"Digest realm="x", qop="auth-int", nonce="n""#);
+        assert!(DigestChallenge::new(&challenges[0]).is_err());
+        let challenges = Challenge::parse(r#"Digest realm="x""#);
+        assert!(DigestChallenge::new(&challenges[0]).is_err());
+    }
+}
@@ -0,0 +1,906 @@
+use std::collections::HashSet;
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{Error, Result};
+
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+const FACET_REL: &str = "http://opds-spec.org/facet";
+const IMAGE_RELS: [&str; 2] = ["http://opds-spec.org/image", "http://opds-spec.org/cover"];
+const THUMBNAIL_RELS: [&str; 2] = [
+    "http://opds-spec.org/image/thumbnail",
+    "http://opds-spec.org/thumbnail",
+];
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsLink {
+    pub href: String, // Absolute URL, or an absolute URI template if templated
+    pub rel: Option<String>,
+    #[serde(rename = "type")]
+    pub media_type: Option<String>,
+    pub title: Option<String>,
+    pub templated: bool,
+    pub count: Option<u64>, // Number of items behind the link, if the server tells
+    pub active_facet: bool,
+    pub facet_group: Option<String>,
+    pub indirect_types: Vec<String>, // Media types behind an indirect acquisition, outermost first
+}
+
+impl OpdsLink {
+    fn has_rel(&self, rels: &[&str]) -> bool {
+        self.rel.as_deref().is_some_and(|rel| rels.contains(&rel))
+    }
+
+    fn is_acquisition(&self) -> bool {
+        self.rel
+            .as_deref()
+            .is_some_and(|rel| rel.starts_with(ACQUISITION_REL))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsPublication {
+    pub id: Option<String>,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub summary: Option<String>, // May contain HTML
+    pub content: Option<String>, // May contain HTML
+    pub language: Option<String>,
+    pub publisher: Option<String>,
+    pub published: Option<String>,
+    pub updated: Option<String>,
+    pub subjects: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub cover: Option<String>,
+    pub thumbnail: Option<String>,
+    pub acquisitions: Vec<OpdsLink>,
+    pub links: Vec<OpdsLink>, // All other links of the publication
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsFacetGroup {
+    pub title: Option<String>,
+    pub links: Vec<OpdsLink>,
+}
+
+// A titled section of an OPDS 2.0 feed, e.g. "New releases" on the start page.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsGroup {
+    pub title: Option<String>,
+    pub href: Option<String>, // Feed with all items of the group
+    pub navigation: Vec<OpdsLink>,
+    pub publications: Vec<OpdsPublication>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsPagination {
+    pub first: Option<String>,
+    pub previous: Option<String>,
+    pub next: Option<String>,
+    pub last: Option<String>,
+    pub total_results: Option<u64>,
+    pub items_per_page: Option<u64>,
+    pub current_page: Option<u64>, // Starting at 1
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsFeed {
+    pub url: String, // URL the feed was loaded from, after redirects
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub updated: Option<String>,
+    pub navigation: Vec<OpdsLink>,
+    pub publications: Vec<OpdsPublication>,
+    pub groups: Vec<OpdsGroup>,
+    pub facets: Vec<OpdsFacetGroup>,
+    pub pagination: OpdsPagination,
+    pub search: Option<OpdsLink>, // OpenSearch description or URI template
+    pub links: Vec<OpdsLink>,     // All other links of the feed
+}
+
+impl OpdsFeed {
+    fn new(base: &Url) -> Self {
+        Self {
+            url: base.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // Sorts a feed-level link into pagination, search, facets or the remaining links.
+    fn add_link(&mut self, link: OpdsLink) {
+        let page = match link.rel.as_deref() {
+            Some("first") => &mut self.pagination.first,
+            Some("previous" | "prev") => &mut self.pagination.previous,
+            Some("next") => &mut self.pagination.next,
+            Some("last") => &mut self.pagination.last,
+            Some("search") => {
+                // Prefer a search that returns feeds when several are offered
+                if self
+                    .search
+                    .as_ref()
+                    .map_or(true, |search| !is_feed(search) && is_feed(&link))
+                {
+                    self.search = Some(link);
+                }
+                return;
+            }
+            Some(FACET_REL) => {
+                let group = link.facet_group.clone();
+                match self.facets.iter_mut().find(|facets| facets.title == group) {
+                    Some(facets) => facets.links.push(link),
+                    None => self.facets.push(OpdsFacetGroup {
+                        title: group,
+                        links: vec![link],
+                    }),
+                }
+                return;
+            }
+            _ => {
+                self.links.push(link);
+                return;
+            }
+        };
+        page.get_or_insert(link.href);
+    }
+}
+
+fn is_feed(link: &OpdsLink) -> bool {
+    link.media_type
+        .as_deref()
+        .is_some_and(|t| t.contains("atom+xml") || t.contains("opds"))
+}
+
+// Resolves `href` against the URL of the document. URI templates are resolved up to their
+// first expression, since the URL parser would percent-encode the braces.
+fn resolve(base: &Url, href: &str, templated: bool) -> String {
+    let (prefix, template) = match href.find('{') {
+        Some(index) if templated => href.split_at(index),
+        _ => (href, ""),
+    };
+    let resolved = match base.join(prefix) {
+        Ok(url) => url.to_string(),
+        Err(_) => prefix.to_string(),
+    };
+    resolved + template
+}
+
+fn invalid(error: impl std::fmt::Display) -> Error {
+    Error::InvalidFeed(error.to_string())
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attr| attr.key.local_name().as_ref() == name)
+        .and_then(|attr| attr.unescape_value().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn atom_link(element: &BytesStart, base: &Url) -> Option<OpdsLink> {
+    let href = attribute(element, b"href")?;
+    Some(OpdsLink {
+        href: resolve(base, &href, false),
+        rel: attribute(element, b"rel"),
+        media_type: attribute(element, b"type"),
+        title: attribute(element, b"title"),
+        templated: false,
+        count: attribute(element, b"count").and_then(|count| count.parse().ok()),
+        active_facet: attribute(element, b"activeFacet").is_some_and(|active| active == "true"),
+        facet_group: attribute(element, b"facetGroup"),
+        indirect_types: Vec::new(),
+    })
+}
+
+#[derive(Default)]
+struct AtomEntry {
+    publication: OpdsPublication,
+    links: Vec<OpdsLink>,
+}
+
+impl AtomEntry {
+    // Entries with acquisition links are publications, all others lead to another feed.
+    fn finish(self, feed: &mut OpdsFeed) {
+        let AtomEntry {
+            mut publication,
+            links,
+        } = self;
+        if !links.iter().any(OpdsLink::is_acquisition) {
+            let link = links.iter().find(|link| is_feed(link)).or(links.first());
+            if let Some(link) = link {
+                feed.navigation.push(OpdsLink {
+                    title: Some(publication.title),
+                    ..link.clone()
+                });
+            }
+            return;
+        }
+
+        for link in links {
+            if link.is_acquisition() {
+                publication.acquisitions.push(link);
+            } else if link.has_rel(&IMAGE_RELS) {
+                publication.cover.get_or_insert(link.href);
+            } else if link.has_rel(&THUMBNAIL_RELS) {
+                publication.thumbnail.get_or_insert(link.href);
+            } else {
+                publication.links.push(link);
+            }
+        }
+        if publication.thumbnail.is_none() {
+            publication.thumbnail = publication.cover.clone();
+        }
+        feed.publications.push(publication);
+    }
+}
+
+// Parses an OPDS 1.x catalog, i.e. an Atom feed. Elements are matched by their local name,
+// since catalogs are not consistent in the namespace prefixes they use.
+pub fn parse_atom(xml: &str, base: &Url) -> Result<OpdsFeed> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut feed = OpdsFeed::new(base);
+    let mut entry: Option<AtomEntry> = None;
+    let mut link: Option<OpdsLink> = None; // Link with child elements
+    let mut path: Vec<Vec<u8>> = Vec::new(); // Local names of the open elements
+    let mut text = String::new();
+    let mut rich_text_depth: Option<usize> = None; // Set within summary and content elements
+    let mut start_index = None;
+
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(element) => {
+                let name = element.local_name().as_ref().to_vec();
+                if rich_text_depth.is_some() {
+                    path.push(name);
+                    continue;
+                }
+                match name.as_slice() {
+                    b"entry" => entry = Some(AtomEntry::default()),
+                    b"link" => link = atom_link(&element, base),
+                    b"summary" | b"content" if entry.is_some() => {
+                        rich_text_depth = Some(path.len())
+                    }
+                    b"category" => add_category(&element, &mut entry),
+                    b"indirectAcquisition" => add_indirect_type(&element, &mut link),
+                    _ => {}
+                }
+                text.clear();
+                path.push(name);
+            }
+            Event::Empty(element) => {
+                if rich_text_depth.is_some() {
+                    continue;
+                }
+                match element.local_name().as_ref() {
+                    b"link" => {
+                        if let Some(link) = atom_link(&element, base) {
+                            add_atom_link(link, &mut entry, &mut feed);
+                        }
+                    }
+                    b"category" => add_category(&element, &mut entry),
+                    b"indirectAcquisition" => add_indirect_type(&element, &mut link),
+                    _ => {}
+                }
+            }
+            Event::Text(content) => text.push_str(&content.unescape().map_err(invalid)?),
+            Event::CData(content) => text.push_str(&String::from_utf8_lossy(&content)),
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                if let Some(depth) = rich_text_depth {
+                    if path.len() > depth {
+                        // End of an XHTML element within the summary or content
+                        text.push(' ');
+                        continue;
+                    }
+                    rich_text_depth = None;
+                }
+                let value = std::mem::take(&mut text).trim().to_string();
+                let value = (!value.is_empty()).then_some(value);
+                let parent = path.last().map(Vec::as_slice);
+
+                if name == b"entry" {
+                    if let Some(entry) = entry.take() {
+                        entry.finish(&mut feed);
+                    }
+                } else if name == b"link" {
+                    if let Some(link) = link.take() {
+                        add_atom_link(link, &mut entry, &mut feed);
+                    }
+                } else if let Some(AtomEntry { publication, .. }) = &mut entry {
+                    match (name.as_slice(), parent) {
+                        (b"id", Some(b"entry")) => publication.id = value,
+                        (b"title", Some(b"entry")) => publication.title = value.unwrap_or_default(),
+                        (b"updated", Some(b"entry")) => publication.updated = value,
+                        (b"published" | b"issued" | b"date", Some(b"entry")) => {
+                            publication.published = publication.published.take().or(value)
+                        }
+                        (b"name", Some(b"author")) | (b"creator", Some(b"entry")) => {
+                            publication.authors.extend(value)
+                        }
+                        (b"summary", _) => publication.summary = value,
+                        (b"content", _) => publication.content = value,
+                        (b"language", _) => publication.language = value,
+                        (b"publisher", _) => publication.publisher = value,
+                        (b"series", _) => publication.series = value,
+                        (b"series_index" | b"seriesIndex", _) => {
+                            publication.series_index = value.and_then(|v| v.parse().ok())
+                        }
+                        _ => {}
+                    }
+                } else {
+                    match (name.as_slice(), parent) {
+                        (b"id", Some(b"feed")) => feed.id = value,
+                        (b"title", Some(b"feed")) => feed.title = value,
+                        (b"updated", Some(b"feed")) => feed.updated = value,
+                        (b"totalResults", _) => {
+                            feed.pagination.total_results = value.and_then(|v| v.parse().ok())
+                        }
+                        (b"itemsPerPage", _) => {
+                            feed.pagination.items_per_page = value.and_then(|v| v.parse().ok())
+                        }
+                        (b"startIndex", _) => start_index = value.and_then(|v| v.parse().ok()),
+                        _ => {}
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if let (Some(start_index), Some(items_per_page)) = (start_index, feed.pagination.items_per_page)
+    {
+        if items_per_page > 0 {
+            feed.pagination.current_page = Some(start_index.saturating_sub(1) / items_per_page + 1);
+        }
+    }
+    Ok(feed)
+}
+
+fn add_atom_link(link: OpdsLink, entry: &mut Option<AtomEntry>, feed: &mut OpdsFeed) {
+    match entry {
+        Some(entry) => entry.links.push(link),
+        None => feed.add_link(link),
+    }
+}
+
+fn add_category(element: &BytesStart, entry: &mut Option<AtomEntry>) {
+    if let Some(entry) = entry {
+        let subject = attribute(element, b"label").or_else(|| attribute(element, b"term"));
+        entry.publication.subjects.extend(subject);
+    }
+}
+
+fn add_indirect_type(element: &BytesStart, link: &mut Option<OpdsLink>) {
+    if let (Some(link), Some(media_type)) = (link, attribute(element, b"type")) {
+        link.indirect_types.push(media_type);
+    }
+}
+
+// Parses the URL template of an OpenSearch description, preferring one that returns a feed.
+pub fn parse_opensearch(xml: &str, base: &Url) -> Result<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut templates = Vec::new();
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(element) | Event::Empty(element)
+                if element.local_name().as_ref() == b"Url" =>
+            {
+                if let Some(template) = attribute(&element, b"template") {
+                    let media_type = attribute(&element, b"type").unwrap_or_default();
+                    templates.push((media_type, resolve(base, &template, true)));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if templates.is_empty() {
+        return Err(invalid("the OpenSearch description has no URL template"));
+    }
+    let index = templates
+        .iter()
+        .position(|(media_type, _)| media_type.contains("atom") || media_type.contains("opds"))
+        .unwrap_or(0);
+    Ok(templates.swap_remove(index).1)
+}
+
+// Reads a value that OPDS 2.0 allows to be either a single item or an array of items.
+fn json_items(value: Option<&Value>) -> Vec<&Value> {
+    match value {
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(Value::Null) | None => Vec::new(),
+        Some(item) => vec![item],
+    }
+}
+
+fn json_str(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+// Reads a string that may also be localized, i.e. a map from language to string.
+fn json_text(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(text) => Some(text.trim().to_string()),
+        Value::Object(texts) => texts.values().find_map(Value::as_str).map(str::to_string),
+        _ => None,
+    }
+    .filter(|text| !text.is_empty())
+}
+
+// Contributors, subjects and collections are either plain strings or objects with a name.
+fn json_names(value: Option<&Value>) -> Vec<String> {
+    json_items(value)
+        .into_iter()
+        .filter_map(|item| match item {
+            Value::String(_) => json_text(Some(item)),
+            item => json_text(item.get("name")),
+        })
+        .collect()
+}
+
+fn json_link(value: &Value, base: &Url) -> Option<OpdsLink> {
+    let href = json_str(value, "href")?;
+    let templated = value.get("templated").and_then(Value::as_bool) == Some(true);
+    // A link may have several relations, acquisitions being the ones that matter
+    let rels = json_items(value.get("rel"))
+        .into_iter()
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>();
+    let rel = rels
+        .iter()
+        .find(|rel| rel.starts_with(ACQUISITION_REL))
+        .or(rels.first())
+        .map(|rel| rel.to_string());
+    let properties = value.get("properties");
+
+    let mut indirect_types = Vec::new();
+    let mut indirect =
+        properties.and_then(|p| json_items(p.get("indirectAcquisition")).first().copied());
+    while let Some(acquisition) = indirect {
+        indirect_types.extend(json_str(acquisition, "type"));
+        indirect = json_items(acquisition.get("child")).first().copied();
+    }
+
+    Some(OpdsLink {
+        href: resolve(base, &href, templated),
+        active_facet: rels.contains(&"self"),
+        rel,
+        media_type: json_str(value, "type"),
+        title: json_str(value, "title"),
+        templated,
+        count: properties
+            .and_then(|p| p.get("numberOfItems"))
+            .and_then(Value::as_u64),
+        facet_group: None,
+        indirect_types,
+    })
+}
+
+fn json_links(value: Option<&Value>, base: &Url) -> Vec<OpdsLink> {
+    json_items(value)
+        .into_iter()
+        .filter_map(|link| json_link(link, base))
+        .collect()
+}
+
+fn json_publication(value: &Value, base: &Url) -> OpdsPublication {
+    let null = Value::Null;
+    let metadata = value.get("metadata").unwrap_or(&null);
+    let series = json_items(
+        metadata
+            .get("belongsTo")
+            .and_then(|collections| collections.get("series")),
+    )
+    .into_iter()
+    .next();
+
+    let mut publication = OpdsPublication {
+        id: json_str(metadata, "identifier"),
+        title: json_text(metadata.get("title")).unwrap_or_default(),
+        authors: json_names(metadata.get("author")),
+        summary: json_str(metadata, "description"),
+        content: None,
+        language: json_items(metadata.get("language"))
+            .into_iter()
+            .find_map(Value::as_str)
+            .map(str::to_string),
+        publisher: json_names(metadata.get("publisher")).into_iter().next(),
+        published: json_str(metadata, "published"),
+        updated: json_str(metadata, "modified"),
+        subjects: json_names(metadata.get("subject")),
+        series: series.and_then(|series| json_names(Some(series)).into_iter().next()),
+        series_index: series
+            .and_then(|series| series.get("position"))
+            .and_then(Value::as_f64),
+        ..Default::default()
+    };
+
+    for link in json_links(value.get("links"), base) {
+        if link.is_acquisition() {
+            publication.acquisitions.push(link);
+        } else {
+            publication.links.push(link);
+        }
+    }
+
+    // Images are listed from the most to the least preferred; the smallest one is the thumbnail
+    let images = json_items(value.get("images"));
+    let thumbnail = images.iter().copied().min_by_key(|image| {
+        image
+            .get("width")
+            .and_then(Value::as_u64)
+            .unwrap_or(u64::MAX)
+    });
+    let href = |image: &Value| json_str(image, "href").map(|href| resolve(base, &href, false));
+    publication.cover = images.first().and_then(|image| href(image));
+    publication.thumbnail = thumbnail.and_then(href);
+    publication
+}
+
+fn json_publications(value: Option<&Value>, base: &Url) -> Vec<OpdsPublication> {
+    json_items(value)
+        .into_iter()
+        .map(|publication| json_publication(publication, base))
+        .collect()
+}
+
+// Parses an OPDS 2.0 catalog.
+pub fn parse_json(json: &Value, base: &Url) -> Result<OpdsFeed> {
+    if !json.is_object() {
+        return Err(invalid("the feed is not a JSON object"));
+    }
+    let null = Value::Null;
+    let metadata = json.get("metadata").unwrap_or(&null);
+    let mut feed = OpdsFeed::new(base);
+    feed.id = json_str(metadata, "identifier");
+    feed.title = json_str(metadata, "title");
+    feed.updated = json_str(metadata, "modified");
+    feed.pagination.total_results = metadata.get("numberOfItems").and_then(Value::as_u64);
+    feed.pagination.items_per_page = metadata.get("itemsPerPage").and_then(Value::as_u64);
+    feed.pagination.current_page = metadata.get("currentPage").and_then(Value::as_u64);
+
+    for link in json_links(json.get("links"), base) {
+        feed.add_link(link);
+    }
+    feed.navigation = json_links(json.get("navigation"), base);
+    feed.publications = json_publications(json.get("publications"), base);
+
+    for facets in json_items(json.get("facets")) {
+        let title = facets.get("metadata").and_then(|m| json_str(m, "title"));
+        let links = json_links(facets.get("links"), base)
+            .into_iter()
+            .map(|link| OpdsLink {
+                facet_group: title.clone(),
+                ..link
+            })
+            .collect();
+        feed.facets.push(OpdsFacetGroup { title, links });
+    }
+
+    let mut hrefs = HashSet::new();
+    for group in json_items(json.get("groups")) {
+        let title = group.get("metadata").and_then(|m| json_str(m, "title"));
+        let links = json_links(group.get("links"), base);
+        let href = links
+            .iter()
+            .find(|link| link.rel.as_deref() == Some("self"))
+            .or(links.first())
+            .map(|link| link.href.clone());
+        if let Some(href) = &href {
+            // Some catalogs repeat the same group, e.g. once per facet
+            if !hrefs.insert(href.clone()) {
+                continue;
+            }
+        }
+        feed.groups.push(OpdsGroup {
+            title,
+            href,
+            navigation: json_links(group.get("navigation"), base),
+            publications: json_publications(group.get("publications"), base),
+        });
+    }
+    Ok(feed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ATOM_FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/terms/"
+      xmlns:opds="http://opds-spec.org/2010/catalog"
+      xmlns:opensearch="http://a9.com/-/spec/opensearch/1.1/">
+  <id>urn:catalog:new</id>
+  <title>New &amp; Noteworthy</title>
+  <updated>2025-01-02T03:04:05Z</updated>
+  <opensearch:totalResults>45</opensearch:totalResults>
+  <opensearch:itemsPerPage>20</opensearch:itemsPerPage>
+  <opensearch:startIndex>21</opensearch:startIndex>
+  <link rel="self" href="/opds/new?page=2" type="application/atom+xml;profile=opds-catalog;kind=acquisition"/>
+  <link rel="next" href="/opds/new?page=3" type="application/atom+xml;profile=opds-catalog;kind=acquisition"/>
+  <link rel="previous" href="new?page=1" type="application/atom+xml;profile=opds-catalog;kind=acquisition"/>
+  <link rel="search" href="/opds/search.xml" type="application/opensearchdescription+xml"/>
+  <link rel="http://opds-spec.org/facet" href="/opds/new?sort=title" title="Title" opds:facetGroup="Sort" opds:activeFacet="true"/>
+  <link rel="http://opds-spec.org/facet" href="/opds/new?sort=date" title="Date" opds:facetGroup="Sort" thr:count="12" xmlns:thr="http://purl.org/syndication/thread/1.0"/>
+  <link rel="http://opds-spec.org/facet" href="/opds/new?lang=en" title="English" opds:facetGroup="Language"/>
+  <entry>
+    <title>Popular</title>
+    <id>urn:catalog:popular</id>
+    <link rel="subsection" href="/opds/popular" type="application/atom+xml;profile=opds-catalog;kind=acquisition"/>
+  </entry>
+  <entry>
+    <title>The Time Machine</title>
+    <id>urn:book:1</id>
+    <updated>2024-12-01T00:00:00Z</updated>
+    <author><name>H. G. Wells</name></author>
+    <dc:language>en</dc:language>
+    <dc:issued>1895</dc:issued>
+    <category term="fiction" label="Science Fiction"/>
+    <summary type="xhtml"><div xmlns="http://www.w3.org/1999/xhtml"><p>A time traveller.</p><p>Who returns.</p></div></summary>
+    <link rel="http://opds-spec.org/image" href="/covers/1.jpg" type="image/jpeg"/>
+    <link rel="http://opds-spec.org/acquisition/open-access" href="/books/1.epub" type="application/epub+zip"/>
+    <link rel="http://opds-spec.org/acquisition/buy" href="https://shop.example.com/1" type="text/html">
+      <opds:indirectAcquisition type="application/vnd.adobe.adept+xml">
+        <opds:indirectAcquisition type="application/epub+zip"/>
+      </opds:indirectAcquisition>
+    </link>
+    <link rel="alternate" href="/books/1" type="text/html"/>
+  </entry>
+</feed>"#;
+
+    const JSON_FEED: &str = r#"{
+  "metadata": { "title": "Bestsellers", "numberOfItems": 120, "itemsPerPage": 50, "currentPage": 2 },
+  "links": [
+    { "rel": "self", "href": "/opds2/best?page=2", "type": "application/opds+json" },
+    { "rel": "next", "href": "/opds2/best?page=3", "type": "application/opds+json" },
+    { "rel": "previous", "href": "/opds2/best?page=1", "type": "application/opds+json" },
+    { "rel": "search", "href": "/opds2/search{?query,author}", "type": "application/opds+json", "templated": true }
+  ],
+  "facets": [{
+    "metadata": { "title": "Language" },
+    "links": [
+      { "rel": "self", "href": "/opds2/best?lang=en", "title": "English", "properties": { "numberOfItems": 80 } },
+      { "href": "/opds2/best?lang=fr", "title": "French" }
+    ]
+  }],
+  "navigation": [{ "href": "/opds2/new", "title": "New", "type": "application/opds+json" }],
+  "publications": [{
+    "metadata": {
+      "identifier": "urn:isbn:9780141439518",
+      "title": { "en": "Pride and Prejudice" },
+      "author": [{ "name": "Jane Austen" }, "Anonymous"],
+      "language": ["en", "fr"],
+      "subject": "Romance",
+      "belongsTo": { "series": [{ "name": "Classics", "position": 3 }] }
+    },
+    "links": [
+      { "rel": ["self", "http://opds-spec.org/acquisition"], "href": "/books/pp.epub", "type": "application/epub+zip" },
+      { "rel": "http://opds-spec.org/acquisition/borrow", "href": "/loans/pp", "type": "application/opds-publication+json",
+        "properties": { "indirectAcquisition": [{ "type": "application/vnd.readium.lcp.license.v1.0+json",
+          "child": [{ "type": "application/epub+zip" }] }] } },
+      { "rel": "alternate", "href": "/pp.html", "type": "text/html" }
+    ],
+    "images": [
+      { "href": "/covers/pp-large.jpg", "width": 1200 },
+      { "href": "/covers/pp-small.jpg", "width": 200 }
+    ]
+  }],
+  "groups": [
+    { "metadata": { "title": "Staff picks" }, "links": [{ "rel": "self", "href": "/opds2/picks" }],
+      "publications": [{ "metadata": { "title": "Emma" } }] },
+    { "metadata": { "title": "Staff picks again" }, "links": [{ "rel": "self", "href": "/opds2/picks" }] }
+  ]
+}"#;
+
+    fn base() -> Url {
+        Url::parse("https://catalog.example.com/opds/new").unwrap()
+    }
+
+    fn hrefs(links: &[OpdsLink]) -> Vec<&str> {
+        links.iter().map(|link| link.href.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_atom_feeds() {
+        let feed = parse_atom(ATOM_FEED, &base()).unwrap();
+        assert_eq!(feed.url, "https://catalog.example.com/opds/new");
+        assert_eq!(feed.id.as_deref(), Some("urn:catalog:new"));
+        assert_eq!(feed.title.as_deref(), Some("New & Noteworthy"));
+        assert_eq!(feed.updated.as_deref(), Some("2025-01-02T03:04:05Z"));
+
+        let pagination = &feed.pagination;
+        let next = "https://catalog.example.com/opds/new?page=3";
+        assert_eq!(pagination.next.as_deref(), Some(next));
+        let previous = "https://catalog.example.com/opds/new?page=1";
+        assert_eq!(pagination.previous.as_deref(), Some(previous));
+        assert_eq!(pagination.total_results, Some(45));
+        assert_eq!(pagination.items_per_page, Some(20));
+        assert_eq!(pagination.current_page, Some(2));
+        let search = feed.search.as_ref().unwrap();
+        assert_eq!(search.href, "https://catalog.example.com/opds/search.xml");
+        assert_eq!(
+            hrefs(&feed.links),
+            ["https://catalog.example.com/opds/new?page=2"]
+        );
+
+        assert_eq!(feed.facets.len(), 2);
+        let sort = &feed.facets[0];
+        assert_eq!(sort.title.as_deref(), Some("Sort"));
+        assert_eq!(sort.links.len(), 2);
+        assert!(sort.links[0].active_facet);
+        assert!(!sort.links[1].active_facet);
+        assert_eq!(sort.links[1].count, Some(12));
+        assert_eq!(feed.facets[1].title.as_deref(), Some("Language"));
+
+        assert_eq!(feed.navigation.len(), 1);
+        assert_eq!(feed.navigation[0].title.as_deref(), Some("Popular"));
+        assert_eq!(
+            feed.navigation[0].href,
+            "https://catalog.example.com/opds/popular"
+        );
+
+        assert_eq!(feed.publications.len(), 1);
+        let book = &feed.publications[0];
+        assert_eq!(book.id.as_deref(), Some("urn:book:1"));
+        assert_eq!(book.title, "The Time Machine");
+        assert_eq!(book.authors, ["H. G. Wells"]);
+        assert_eq!(book.language.as_deref(), Some("en"));
+        assert_eq!(book.published.as_deref(), Some("1895"));
+        assert_eq!(book.subjects, ["Science Fiction"]);
+        assert_eq!(
+            book.summary.as_deref(),
+            Some("A time traveller. Who returns.")
+        );
+        let cover = "https://catalog.example.com/covers/1.jpg";
+        assert_eq!(book.cover.as_deref(), Some(cover));
+        assert_eq!(book.thumbnail.as_deref(), Some(cover));
+        assert_eq!(
+            hrefs(&book.acquisitions),
+            [
+                "https://catalog.example.com/books/1.epub",
+                "https://shop.example.com/1"
+            ]
+        );
+        assert!(book.acquisitions[0].indirect_types.is_empty());
+        assert_eq!(
+            book.acquisitions[1].indirect_types,
+            ["application/vnd.adobe.adept+xml", "application/epub+zip"]
+        );
+        assert_eq!(hrefs(&book.links), ["https://catalog.example.com/books/1"]);
+    }
+
+    #[test]
+    fn rejects_malformed_atom() {
+        let xml = "<feed><title>Broken</feed>";
+        assert!(matches!(
+            parse_atom(xml, &base()),
+            Err(Error::InvalidFeed(_))
+        ));
+    }
+
+    #[test]
+    fn parses_json_feeds() {
+        let feed = parse_json(&serde_json::from_str(JSON_FEED).unwrap(), &base()).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Bestsellers"));
+
+        let pagination = &feed.pagination;
+        let next = "https://catalog.example.com/opds2/best?page=3";
+        assert_eq!(pagination.next.as_deref(), Some(next));
+        let previous = "https://catalog.example.com/opds2/best?page=1";
+        assert_eq!(pagination.previous.as_deref(), Some(previous));
+        assert_eq!(pagination.total_results, Some(120));
+        assert_eq!(pagination.items_per_page, Some(50));
+        assert_eq!(pagination.current_page, Some(2));
+        let search = feed.search.as_ref().unwrap();
+        assert!(search.templated);
+        assert_eq!(
+            search.href,
+            "https://catalog.example.com/opds2/search{?query,author}"
+        );
+
+        assert_eq!(feed.facets.len(), 1);
+        let facets = &feed.facets[0];
+        assert_eq!(facets.title.as_deref(), Some("Language"));
+        assert_eq!(facets.links[0].title.as_deref(), Some("English"));
+        assert!(facets.links[0].active_facet);
+        assert_eq!(facets.links[0].count, Some(80));
+        assert_eq!(facets.links[1].facet_group.as_deref(), Some("Language"));
+        assert!(!facets.links[1].active_facet);
+
+        assert_eq!(
+            hrefs(&feed.navigation),
+            ["https://catalog.example.com/opds2/new"]
+        );
+        assert_eq!(feed.publications.len(), 1);
+        let book = &feed.publications[0];
+        assert_eq!(book.id.as_deref(), Some("urn:isbn:9780141439518"));
+        assert_eq!(book.title, "Pride and Prejudice");
+        assert_eq!(book.authors, ["Jane Austen", "Anonymous"]);
+        assert_eq!(book.language.as_deref(), Some("en"));
+        assert_eq!(book.subjects, ["Romance"]);
+        assert_eq!(book.series.as_deref(), Some("Classics"));
+        assert_eq!(book.series_index, Some(3.0));
+        assert_eq!(
+            book.cover.as_deref(),
+            Some("https://catalog.example.com/covers/pp-large.jpg")
+        );
+        assert_eq!(
+            book.thumbnail.as_deref(),
+            Some("https://catalog.example.com/covers/pp-small.jpg")
+        );
+        assert_eq!(book.acquisitions.len(), 2);
+        assert_eq!(
+            book.acquisitions[0].rel.as_deref(),
+            Some("http://opds-spec.org/acquisition")
+        );
+        assert_eq!(
+            book.acquisitions[1].indirect_types,
+            [
+                "application/vnd.readium.lcp.license.v1.0+json",
+                "application/epub+zip"
+            ]
+        );
+        assert_eq!(hrefs(&book.links), ["https://catalog.example.com/pp.html"]);
+
+        // The repeated group is skipped
+        assert_eq!(feed.groups.len(), 1);
+        let group = &feed.groups[0];
+        assert_eq!(group.title.as_deref(), Some("Staff picks"));
+        assert_eq!(
+            group.href.as_deref(),
+            Some("https://catalog.example.com/opds2/picks")
+        );
+        assert_eq!(group.publications[0].title, "Emma");
+    }
+
+    #[test]
+    fn rejects_json_that_is_not_a_feed() {
+        assert!(parse_json(&json!([]), &base()).is_err());
+    }
+
+    #[test]
+    fn parses_opensearch_descriptions() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <ShortName>Catalog</ShortName>
+  <Url type="text/html" template="https://catalog.example.com/search?q={searchTerms}"/>
+  <Url type="application/atom+xml;profile=opds-catalog" template="/opds/search/{searchTerms}?page={startPage?}"/>
+</OpenSearchDescription>"#;
+        assert_eq!(
+            parse_opensearch(xml, &base()).unwrap(),
+            "https://catalog.example.com/opds/search/{searchTerms}?page={startPage?}"
+        );
+
+        let xml = r#"<OpenSearchDescription><Url template="search?q={searchTerms}"/></OpenSearchDescription>"#;
+        assert_eq!(
+            parse_opensearch(xml, &base()).unwrap(),
+            "https://catalog.example.com/opds/search?q={searchTerms}"
+        );
+
+        let xml = "<OpenSearchDescription><ShortName>Catalog</ShortName></OpenSearchDescription>";
+        assert!(parse_opensearch(xml, &base()).is_err());
+    }
+}
@@ -0,0 +1,1131 @@
+//! Optional OPDS catalog server that lets other readers on the local network (KOReader,
+//! Moon+ Reader, ...) browse and download the local library.
+//!
+//! The same catalog is served as OPDS 1.2 (Atom) below `/opds/v1` and as OPDS 2.0 (JSON)
+//! below `/opds/v2`, built from `library.json`, which is parsed again whenever it changes so
+//! that the catalog always reflects the library. Book files and covers are served with
+//! support for range requests.
+//!
+//! Without a password the server is only reachable from this computer; other devices on the
+//! network need credentials to browse the library.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::io::Cursor;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path as UrlPath, Query, Request, State as AxumState},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+        HeaderValue, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{command, AppHandle, Manager, State};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+use crate::embedded_server::{constant_time_eq, local_ip, EmbeddedServer};
+use crate::timestamp::{now_millis, rfc3339};
+
+const DEFAULT_PORT: u16 = 7201;
+const PAGE_SIZE: usize = 50;
+const CATALOG_TITLE: &str = "Readest Library";
+const THUMBNAIL_SIZE: u32 = 256; // Bounding box of cover thumbnails in pixels
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Atom, // OPDS 1.2
+    Json, // OPDS 2.0
+}
+
+impl Format {
+    fn from_version(version: &str) -> Option<Self> {
+        match version {
+            "v1" => Some(Format::Atom),
+            "v2" => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Format::Atom => "/opds/v1",
+            Format::Json => "/opds/v2",
+        }
+    }
+}
+
+// The subset of the frontend's Book type that ends up in the catalog.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LibraryBook {
+    hash: String,
+    format: String,
+    title: String,
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    updated_at: f64,
+    deleted_at: Option<f64>,
+    primary_language: Option<String>,
+    metadata: Option<Value>,
+}
+
+impl LibraryBook {
+    // Metadata comes from many different book formats, so its fields are read leniently.
+    fn metadata_str(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .as_ref()?
+            .get(key)?
+            .as_str()
+            .filter(|value| !value.is_empty())
+    }
+
+    fn series(&self) -> Option<&str> {
+        self.metadata_str("series")
+    }
+
+    fn series_index(&self) -> Option<f64> {
+        self.metadata.as_ref()?.get("seriesIndex")?.as_f64()
+    }
+
+    fn subjects(&self) -> Vec<&str> {
+        match self.metadata.as_ref().and_then(|m| m.get("subject")) {
+            Some(Value::String(subject)) => vec![subject.as_str()],
+            Some(Value::Array(subjects)) => subjects.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn mime_type(&self) -> &'static str {
+        match self.format.as_str() {
+            "EPUB" => "application/epub+zip",
+            "PDF" => "application/pdf",
+            "MOBI" => "application/x-mobipocket-ebook",
+            "CBZ" => "application/vnd.comicbook+zip",
+            "FB2" => "application/x-fictionbook+xml",
+            "FBZ" => "application/x-zip-compressed-fb2",
+            _ => "application/octet-stream",
+        }
+    }
+
+    fn updated(&self) -> String {
+        rfc3339(self.updated_at as u64)
+    }
+
+    // Every search term has to occur in the title, author, series, subjects or description.
+    fn matches(&self, terms: &[String]) -> bool {
+        let mut text = format!("{} {}", self.title, self.author);
+        for field in [
+            self.series(),
+            self.metadata_str("description"),
+            self.metadata_str("publisher"),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.subjects())
+        {
+            text.push(' ');
+            text.push_str(field);
+        }
+        let text = text.to_lowercase();
+        terms.iter().all(|term| text.contains(term.as_str()))
+    }
+}
+
+fn load_library(path: &Path) -> Vec<LibraryBook> {
+    std::fs::read(path)
+        .ok()
+        .and_then(|data| serde_json::from_slice::<Vec<Value>>(&data).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|book| serde_json::from_value::<LibraryBook>(book).ok())
+        .filter(|book| book.deleted_at.is_none())
+        .collect()
+}
+
+// Identifies a version of a file without reading it.
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+struct NavigationEntry {
+    title: String,
+    href: String,
+    count: usize,
+}
+
+enum FeedEntries<'a> {
+    Navigation(Vec<NavigationEntry>),
+    Publications(Vec<&'a LibraryBook>),
+}
+
+struct Feed<'a> {
+    id: String,
+    title: String,
+    href: String,
+    next: Option<String>,
+    entries: FeedEntries<'a>,
+}
+
+type Versioned<T> = Option<((SystemTime, u64), T)>;
+
+struct ServerState {
+    books_dir: PathBuf,
+    credentials: Option<(String, String)>,
+    library: Mutex<Versioned<Arc<Vec<LibraryBook>>>>, // Parsed library.json
+    thumbnails: Mutex<HashMap<String, Versioned<Bytes>>>, // PNG thumbnails by book hash
+}
+
+impl ServerState {
+    // Returns the books of the library, parsing library.json only if it changed.
+    fn library(&self) -> Arc<Vec<LibraryBook>> {
+        let path = self.books_dir.join("library.json");
+        let version = file_version(&path);
+        let mut cached = self.library.lock().unwrap();
+        match &*cached {
+            Some((cached_version, library)) if Some(*cached_version) == version => {
+                Arc::clone(library)
+            }
+            _ => {
+                let library = Arc::new(load_library(&path));
+                *cached = version.map(|version| (version, Arc::clone(&library)));
+                library
+            }
+        }
+    }
+
+    fn has_book(&self, hash: &str) -> bool {
+        self.library().iter().any(|book| book.hash == hash)
+    }
+}
+
+type SharedState = AxumState<Arc<ServerState>>;
+
+#[derive(Deserialize)]
+struct FeedQuery {
+    page: Option<usize>,
+    q: Option<String>,     // OpenSearch term of OPDS 1.2
+    query: Option<String>, // Search term of OPDS 2.0
+}
+
+fn router(state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route("/opds", get(|| async { Redirect::to("/opds/v1") }))
+        .route("/opds/:version", get(root))
+        .route("/opds/:version/recent", get(recent))
+        .route("/opds/:version/authors", get(authors))
+        .route("/opds/:version/authors/:author", get(author))
+        .route("/opds/:version/series", get(series_list))
+        .route("/opds/:version/series/:series", get(series))
+        .route("/opds/:version/search", get(search))
+        .route("/opds/opensearch.xml", get(opensearch))
+        .route("/books/:hash/file", get(book_file))
+        .route("/books/:hash/cover", get(book_cover))
+        .route("/books/:hash/thumbnail", get(book_thumbnail))
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&state),
+            require_auth,
+        ))
+        .with_state(state)
+}
+
+async fn require_auth(AxumState(state): SharedState, request: Request, next: Next) -> Response {
+    let Some((username, password)) = &state.credentials else {
+        return next.run(request).await;
+    };
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|v| BASE64.decode(v).ok())
+        .and_then(|v| String::from_utf8(v).ok())
+        .is_some_and(|v| {
+            let (user, pass) = v.split_once(':').unwrap_or((&v, ""));
+            // Both are compared, so that the time taken does not tell which one was wrong
+            let user_matches = constant_time_eq(user.as_bytes(), username.as_bytes());
+            let pass_matches = constant_time_eq(pass.as_bytes(), password.as_bytes());
+            user_matches & pass_matches
+        });
+    if authorized {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(WWW_AUTHENTICATE, "Basic realm=\"Readest\"")],
+        )
+            .into_response()
+    }
+}
+
+fn not_found() -> Response {
+    StatusCode::NOT_FOUND.into_response()
+}
+
+fn encode(segment: &str) -> String {
+    utf8_percent_encode(segment, NON_ALPHANUMERIC).to_string()
+}
+
+// Returns the books of the requested page and the href of the next page, if any.
+fn paginate<'a>(
+    books: Vec<&'a LibraryBook>,
+    page: Option<usize>,
+    href: &str,
+) -> (Vec<&'a LibraryBook>, Option<String>) {
+    let page = page.unwrap_or(1).max(1);
+    // The page comes from the query string, so it may be arbitrarily large
+    let has_next = page
+        .checked_mul(PAGE_SIZE)
+        .is_some_and(|end| books.len() > end);
+    let books = books
+        .into_iter()
+        .skip(page.saturating_sub(1).saturating_mul(PAGE_SIZE))
+        .take(PAGE_SIZE)
+        .collect();
+    let separator = if href.contains('?') { '&' } else { '?' };
+    let next = has_next.then(|| format!("{href}{separator}page={}", page + 1));
+    (books, next)
+}
+
+async fn root(UrlPath(version): UrlPath<String>) -> Response {
+    let Some(format) = Format::from_version(&version) else {
+        return not_found();
+    };
+    let prefix = format.prefix();
+    let entries = [
+        ("Recently Updated", "recent"),
+        ("Authors", "authors"),
+        ("Series", "series"),
+    ]
+    .into_iter()
+    .map(|(title, path)| NavigationEntry {
+        title: title.to_string(),
+        href: format!("{prefix}/{path}"),
+        count: 0,
+    })
+    .collect();
+    render(
+        Feed {
+            id: "urn:readest:catalog".into(),
+            title: CATALOG_TITLE.into(),
+            href: prefix.into(),
+            next: None,
+            entries: FeedEntries::Navigation(entries),
+        },
+        format,
+    )
+}
+
+async fn recent(
+    AxumState(state): SharedState,
+    UrlPath(version): UrlPath<String>,
+    Query(query): Query<FeedQuery>,
+) -> Response {
+    let Some(format) = Format::from_version(&version) else {
+        return not_found();
+    };
+    let library = state.library();
+    let mut books = library.iter().collect::<Vec<_>>();
+    books.sort_by(|a, b| b.updated_at.total_cmp(&a.updated_at));
+    let href = format!("{}/recent", format.prefix());
+    let (books, next) = paginate(books, query.page, &href);
+    render(
+        Feed {
+            id: "urn:readest:recent".into(),
+            title: "Recently Updated".into(),
+            href,
+            next,
+            entries: FeedEntries::Publications(books),
+        },
+        format,
+    )
+}
+
+// Lists the values of a field with links to the books having that value, sorted by value.
+fn grouped<'a>(
+    library: &'a [LibraryBook],
+    key: impl Fn(&'a LibraryBook) -> Option<&'a str>,
+    href: &str,
+) -> Vec<NavigationEntry> {
+    let mut groups = BTreeMap::<&str, usize>::new();
+    for book in library {
+        if let Some(value) = key(book) {
+            *groups.entry(value).or_default() += 1;
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(value, count)| NavigationEntry {
+            title: value.to_string(),
+            href: format!("{href}/{}", encode(value)),
+            count,
+        })
+        .collect()
+}
+
+async fn authors(AxumState(state): SharedState, UrlPath(version): UrlPath<String>) -> Response {
+    let Some(format) = Format::from_version(&version) else {
+        return not_found();
+    };
+    let library = state.library();
+    let href = format!("{}/authors", format.prefix());
+    let entries = grouped(
+        &library,
+        |book| Some(book.author.as_str()).filter(|author| !author.is_empty()),
+        &href,
+    );
+    render(
+        Feed {
+            id: "urn:readest:authors".into(),
+            title: "Authors".into(),
+            href,
+            next: None,
+            entries: FeedEntries::Navigation(entries),
+        },
+        format,
+    )
+}
+
+async fn author(
+    AxumState(state): SharedState,
+    UrlPath((version, author)): UrlPath<(String, String)>,
+    Query(query): Query<FeedQuery>,
+) -> Response {
+    let Some(format) = Format::from_version(&version) else {
+        return not_found();
+    };
+    let library = state.library();
+    let mut books = library
+        .iter()
+        .filter(|book| book.author == author)
+        .collect::<Vec<_>>();
+    books.sort_by(|a, b| a.title.cmp(&b.title));
+    let href = format!("{}/authors/{}", format.prefix(), encode(&author));
+    let (books, next) = paginate(books, query.page, &href);
+    render(
+        Feed {
+            id: format!("urn:readest:author:{}", encode(&author)),
+            title: author,
+            href,
+            next,
+            entries: FeedEntries::Publications(books),
+        },
+        format,
+    )
+}
+
+async fn series_list(AxumState(state): SharedState, UrlPath(version): UrlPath<String>) -> Response {
+    let Some(format) = Format::from_version(&version) else {
+        return not_found();
+    };
+    let library = state.library();
+    let href = format!("{}/series", format.prefix());
+    let entries = grouped(&library, LibraryBook::series, &href);
+    render(
+        Feed {
+            id: "urn:readest:series".into(),
+            title: "Series".into(),
+            href,
+            next: None,
+            entries: FeedEntries::Navigation(entries),
+        },
+        format,
+    )
+}
+
+async fn series(
+    AxumState(state): SharedState,
+    UrlPath((version, series)): UrlPath<(String, String)>,
+    Query(query): Query<FeedQuery>,
+) -> Response {
+    let Some(format) = Format::from_version(&version) else {
+        return not_found();
+    };
+    let library = state.library();
+    let mut books = library
+        .iter()
+        .filter(|book| book.series() == Some(series.as_str()))
+        .collect::<Vec<_>>();
+    books.sort_by(|a, b| {
+        let index = |book: &LibraryBook| book.series_index().unwrap_or(f64::MAX);
+        index(a).total_cmp(&index(b))
+    });
+    let href = format!("{}/series/{}", format.prefix(), encode(&series));
+    let (books, next) = paginate(books, query.page, &href);
+    render(
+        Feed {
+            id: format!("urn:readest:series:{}", encode(&series)),
+            title: series,
+            href,
+            next,
+            entries: FeedEntries::Publications(books),
+        },
+        format,
+    )
+}
+
+async fn search(
+    AxumState(state): SharedState,
+    UrlPath(version): UrlPath<String>,
+    Query(query): Query<FeedQuery>,
+) -> Response {
+    let Some(format) = Format::from_version(&version) else {
+        return not_found();
+    };
+    let term = query.q.or(query.query).unwrap_or_default();
+    let terms = term
+        .to_lowercase()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let library = state.library();
+    let mut books = library
+        .iter()
+        .filter(|book| book.matches(&terms))
+        .collect::<Vec<_>>();
+    books.sort_by(|a, b| a.title.cmp(&b.title));
+    let param = if format == Format::Atom { "q" } else { "query" };
+    let href = format!("{}/search?{param}={}", format.prefix(), encode(&term));
+    let (books, next) = paginate(books, query.page, &href);
+    render(
+        Feed {
+            id: format!("urn:readest:search:{}", encode(&term)),
+            title: format!("Search results for \"{term}\""),
+            href,
+            next,
+            entries: FeedEntries::Publications(books),
+        },
+        format,
+    )
+}
+
+async fn opensearch() -> Response {
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+<ShortName>Readest</ShortName>
+<Description>Search the Readest library</Description>
+<InputEncoding>UTF-8</InputEncoding>
+<OutputEncoding>UTF-8</OutputEncoding>
+<Url type="application/atom+xml;profile=opds-catalog;kind=acquisition" template="{}/search?q={{searchTerms}}"/>
+</OpenSearchDescription>"#,
+        Format::Atom.prefix()
+    );
+    (
+        [(CONTENT_TYPE, "application/opensearchdescription+xml")],
+        xml,
+    )
+        .into_response()
+}
+
+// Hashes name the book folders, so anything else could be used to escape the library.
+fn book_dir(state: &ServerState, hash: &str) -> Option<PathBuf> {
+    (!hash.is_empty() && hash.chars().all(|c| c.is_ascii_alphanumeric()))
+        .then(|| state.books_dir.join(hash))
+}
+
+async fn serve_file(path: PathBuf, mime_type: &'static str, request: Request) -> Response {
+    match ServeFile::new_with_mime(path, &HeaderValue::from_static(mime_type))
+        .oneshot(request)
+        .await
+    {
+        Ok(response) => response.map(Body::new),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn book_file(
+    AxumState(state): SharedState,
+    UrlPath(hash): UrlPath<String>,
+    request: Request,
+) -> Response {
+    let Some(dir) = book_dir(&state, &hash) else {
+        return not_found();
+    };
+    let library = state.library();
+    let Some(book) = library.iter().find(|book| book.hash == hash) else {
+        return not_found();
+    };
+
+    // The file is named after the book's original title, so look it up by its extension
+    let ext = book.format.to_lowercase();
+    let file = std::fs::read_dir(&dir).ok().and_then(|entries| {
+        entries.flatten().map(|entry| entry.path()).find(|path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| e.eq_ignore_ascii_case(&ext))
+        })
+    });
+    match file {
+        Some(file) => serve_file(file, book.mime_type(), request).await,
+        None => not_found(),
+    }
+}
+
+async fn book_cover(
+    AxumState(state): SharedState,
+    UrlPath(hash): UrlPath<String>,
+    request: Request,
+) -> Response {
+    match book_dir(&state, &hash) {
+        Some(dir) if state.has_book(&hash) => {
+            serve_file(dir.join("cover.png"), "image/png", request).await
+        }
+        _ => not_found(),
+    }
+}
+
+// Scales a cover down to fit THUMBNAIL_SIZE, keeping its aspect ratio.
+fn make_thumbnail(cover: &Path) -> Result<Bytes, image::ImageError> {
+    let thumbnail = image::open(cover)?.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    let mut data = Cursor::new(Vec::new());
+    thumbnail.write_to(&mut data, image::ImageFormat::Png)?;
+    Ok(Bytes::from(data.into_inner()))
+}
+
+// Thumbnails are made on first request and kept until the cover changes.
+async fn book_thumbnail(AxumState(state): SharedState, UrlPath(hash): UrlPath<String>) -> Response {
+    let Some(dir) = book_dir(&state, &hash).filter(|_| state.has_book(&hash)) else {
+        return not_found();
+    };
+    let cover = dir.join("cover.png");
+    let Some(version) = file_version(&cover) else {
+        return not_found();
+    };
+    let cached = state
+        .thumbnails
+        .lock()
+        .unwrap()
+        .get(&hash)
+        .cloned()
+        .flatten();
+    let thumbnail = match cached {
+        Some((cached_version, thumbnail)) if cached_version == version => thumbnail,
+        _ => {
+            let result = tauri::async_runtime::spawn_blocking(move || make_thumbnail(&cover)).await;
+            match result {
+                Ok(Ok(thumbnail)) => {
+                    state
+                        .thumbnails
+                        .lock()
+                        .unwrap()
+                        .insert(hash, Some((version, thumbnail.clone())));
+                    thumbnail
+                }
+                Ok(Err(e)) => {
+                    log::warn!("Failed to make the cover thumbnail of {hash}: {e}");
+                    return not_found();
+                }
+                Err(e) => {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+                }
+            }
+        }
+    };
+    ([(CONTENT_TYPE, "image/png")], thumbnail).into_response()
+}
+
+fn render(feed: Feed, format: Format) -> Response {
+    match format {
+        Format::Atom => {
+            let kind = match feed.entries {
+                FeedEntries::Navigation(_) => "navigation",
+                FeedEntries::Publications(_) => "acquisition",
+            };
+            let content_type = format!("application/atom+xml;profile=opds-catalog;kind={kind}");
+            ([(CONTENT_TYPE, content_type)], render_atom(&feed, kind)).into_response()
+        }
+        Format::Json => (
+            [(CONTENT_TYPE, "application/opds+json")],
+            render_json(&feed).to_string(),
+        )
+            .into_response(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_atom(feed: &Feed, kind: &str) -> String {
+    let prefix = Format::Atom.prefix();
+    let nav_type = "application/atom+xml;profile=opds-catalog;kind=navigation";
+    let feed_type = format!("application/atom+xml;profile=opds-catalog;kind={kind}");
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/terms/" xmlns:opds="http://opds-spec.org/2010/catalog">"#,
+    );
+    let _ = write!(
+        xml,
+        r#"<id>{}</id><title>{}</title><updated>{}</updated><author><name>Readest</name></author>"#,
+        escape(&feed.id),
+        escape(&feed.title),
+        rfc3339(now_millis()),
+    );
+    let _ = write!(
+        xml,
+        r#"<link rel="self" href="{}" type="{feed_type}"/><link rel="start" href="{prefix}" type="{nav_type}"/><link rel="search" href="/opds/opensearch.xml" type="application/opensearchdescription+xml"/>"#,
+        escape(&feed.href),
+    );
+    if let Some(next) = &feed.next {
+        let _ = write!(
+            xml,
+            r#"<link rel="next" href="{}" type="{feed_type}"/>"#,
+            escape(next)
+        );
+    }
+
+    match &feed.entries {
+        FeedEntries::Navigation(entries) => {
+            for entry in entries {
+                let _ = write!(
+                    xml,
+                    r#"<entry><title>{title}</title><id>urn:readest:nav:{href}</id><updated>{updated}</updated><link rel="subsection" href="{href}" type="{nav_type}"/>"#,
+                    title = escape(&entry.title),
+                    href = escape(&entry.href),
+                    updated = rfc3339(now_millis()),
+                );
+                if entry.count > 0 {
+                    let _ = write!(
+                        xml,
+                        r#"<content type="text">{} books</content>"#,
+                        entry.count
+                    );
+                }
+                xml.push_str("</entry>");
+            }
+        }
+        FeedEntries::Publications(books) => {
+            for book in books {
+                let _ = write!(
+                    xml,
+                    r#"<entry><title>{}</title><id>urn:readest:book:{}</id><updated>{}</updated><author><name>{}</name></author>"#,
+                    escape(&book.title),
+                    book.hash,
+                    book.updated(),
+                    escape(&book.author),
+                );
+                if let Some(language) = &book.primary_language {
+                    let _ = write!(xml, "<dc:language>{}</dc:language>", escape(language));
+                }
+                if let Some(publisher) = book.metadata_str("publisher") {
+                    let _ = write!(xml, "<dc:publisher>{}</dc:publisher>", escape(publisher));
+                }
+                for subject in book.subjects() {
+                    let _ = write!(
+                        xml,
+                        r#"<category term="{0}" label="{0}"/>"#,
+                        escape(subject)
+                    );
+                }
+                if let Some(description) = book.metadata_str("description") {
+                    let _ = write!(
+                        xml,
+                        r#"<summary type="text">{}</summary>"#,
+                        escape(description)
+                    );
+                }
+                let _ = write!(
+                    xml,
+                    r#"<link rel="http://opds-spec.org/acquisition" href="/books/{hash}/file" type="{mime}"/><link rel="http://opds-spec.org/image" href="/books/{hash}/cover" type="image/png"/><link rel="http://opds-spec.org/image/thumbnail" href="/books/{hash}/thumbnail" type="image/png"/></entry>"#,
+                    hash = book.hash,
+                    mime = book.mime_type(),
+                );
+            }
+        }
+    }
+    xml.push_str("</feed>");
+    xml
+}
+
+fn render_json(feed: &Feed) -> Value {
+    let prefix = Format::Json.prefix();
+    let mut links = vec![
+        json!({ "rel": "self", "href": feed.href, "type": "application/opds+json" }),
+        json!({ "rel": "start", "href": prefix, "type": "application/opds+json" }),
+        json!({
+            "rel": "search",
+            "href": format!("{prefix}/search{{?query}}"),
+            "type": "application/opds+json",
+            "templated": true,
+        }),
+    ];
+    if let Some(next) = &feed.next {
+        links.push(json!({ "rel": "next", "href": next, "type": "application/opds+json" }));
+    }
+
+    let mut result = json!({
+        "metadata": { "title": feed.title },
+        "links": links,
+    });
+    match &feed.entries {
+        FeedEntries::Navigation(entries) => {
+            result["navigation"] = entries
+                .iter()
+                .map(|entry| {
+                    let mut link = json!({
+                        "href": entry.href,
+                        "title": entry.title,
+                        "type": "application/opds+json",
+                        "rel": "subsection",
+                    });
+                    if entry.count > 0 {
+                        link["properties"] = json!({ "numberOfItems": entry.count });
+                    }
+                    link
+                })
+                .collect();
+        }
+        FeedEntries::Publications(books) => {
+            result["publications"] = books
+                .iter()
+                .map(|book| {
+                    let mut metadata = json!({
+                        "@type": "http://schema.org/Book",
+                        "identifier": format!("urn:readest:book:{}", book.hash),
+                        "title": book.title,
+                        "author": [{ "name": book.author }],
+                        "modified": book.updated(),
+                    });
+                    if let Some(language) = &book.primary_language {
+                        metadata["language"] = json!(language);
+                    }
+                    if let Some(publisher) = book.metadata_str("publisher") {
+                        metadata["publisher"] = json!(publisher);
+                    }
+                    if let Some(description) = book.metadata_str("description") {
+                        metadata["description"] = json!(description);
+                    }
+                    let subjects = book.subjects();
+                    if !subjects.is_empty() {
+                        metadata["subject"] = json!(subjects);
+                    }
+                    if let Some(series) = book.series() {
+                        metadata["belongsTo"] = json!({
+                            "series": { "name": series, "position": book.series_index() }
+                        });
+                    }
+                    json!({
+                        "metadata": metadata,
+                        "links": [{
+                            "rel": "http://opds-spec.org/acquisition",
+                            "href": format!("/books/{}/file", book.hash),
+                            "type": book.mime_type(),
+                        }],
+                        "images": [
+                            {
+                                "href": format!("/books/{}/cover", book.hash),
+                                "type": "image/png",
+                            },
+                            {
+                                "href": format!("/books/{}/thumbnail", book.hash),
+                                "type": "image/png",
+                                "width": THUMBNAIL_SIZE,
+                                "height": THUMBNAIL_SIZE,
+                            },
+                        ],
+                    })
+                })
+                .collect();
+        }
+    }
+    result
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsServerOptions {
+    pub port: Option<u16>, // Defaults to 7201
+    pub username: Option<String>,
+    pub password: Option<String>, // Requires HTTP Basic authentication and serves the network
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsServerStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+    pub url: Option<String>, // OPDS 1.2 catalog URL to enter in other readers
+}
+
+struct RunningServer {
+    server: EmbeddedServer,
+    public: bool, // Reachable from the network rather than only from this computer
+}
+
+#[derive(Default)]
+pub struct OpdsServer(Mutex<Option<RunningServer>>);
+
+impl OpdsServer {
+    fn status(&self) -> OpdsServerStatus {
+        let running = self.0.lock().unwrap();
+        let address = running.as_ref().map(|running| {
+            let host = if running.public {
+                local_ip()
+            } else {
+                Ipv4Addr::LOCALHOST
+            };
+            (host, running.server.port())
+        });
+        OpdsServerStatus {
+            running: address.is_some(),
+            port: address.map(|(_, port)| port),
+            url: address.map(|(host, port)| format!("http://{host}:{port}/opds/v1")),
+        }
+    }
+}
+
+// Starts the server, restarting it if it is already running so that new options take effect.
+#[command]
+pub async fn start_opds_server(
+    app: AppHandle,
+    server: State<'_, OpdsServer>,
+    options: Option<OpdsServerOptions>,
+) -> Result<OpdsServerStatus, String> {
+    let options = options.unwrap_or_default();
+    let previous = server.0.lock().unwrap().take();
+    if let Some(previous) = previous {
+        previous.server.shutdown().await;
+    }
+
+    let books_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join("Readest")
+        .join("Books");
+    let credentials = options
+        .password
+        .filter(|password| !password.is_empty())
+        .map(|password| (options.username.unwrap_or_default(), password));
+    let public = credentials.is_some();
+    let state = Arc::new(ServerState {
+        books_dir,
+        credentials,
+        library: Mutex::new(None),
+        thumbnails: Mutex::new(HashMap::new()),
+    });
+    let router = router(state);
+    let port = options.port.unwrap_or(DEFAULT_PORT);
+    let running = if public {
+        EmbeddedServer::start("OPDS", router, port).await
+    } else {
+        EmbeddedServer::start_loopback("OPDS", router, port).await
+    }
+    .map_err(|e| format!("Failed to start OPDS server: {e}"))?;
+
+    *server.0.lock().unwrap() = Some(RunningServer {
+        server: running,
+        public,
+    });
+    Ok(server.status())
+}
+
+#[command]
+pub fn stop_opds_server(server: State<'_, OpdsServer>) -> OpdsServerStatus {
+    drop(server.0.lock().unwrap().take());
+    server.status()
+}
+
+#[command]
+pub fn get_opds_server_status(server: State<'_, OpdsServer>) -> OpdsServerStatus {
+    server.status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library() -> Vec<LibraryBook> {
+        let books = json!([
+            {
+                "hash": "aaa111",
+                "format": "EPUB",
+                "title": "The Left Hand of Darkness",
+                "author": "Ursula K. Le Guin",
+                "updatedAt": 1700000000000.0,
+                "primaryLanguage": "en",
+                "metadata": {
+                    "series": "Hainish Cycle",
+                    "seriesIndex": 6,
+                    "subject": ["Science Fiction", "Gender"],
+                    "publisher": "Ace & Sons",
+                    "description": "An envoy on the planet <Gethen>.",
+                },
+            },
+            {
+                "hash": "bbb222",
+                "format": "PDF",
+                "title": "A Wizard of Earthsea",
+                "author": "Ursula K. Le Guin",
+                "metadata": { "subject": "Fantasy" },
+            },
+            {
+                "hash": "ccc333",
+                "format": "EPUB",
+                "title": "Deleted",
+                "deletedAt": 1700000000000.0,
+            },
+            { "title": "Not a book" },
+        ]);
+        let path =
+            std::env::temp_dir().join(format!("readest-opds-library-{}.json", std::process::id()));
+        std::fs::write(&path, books.to_string()).unwrap();
+        let library = load_library(&path);
+        let _ = std::fs::remove_file(path);
+        library
+    }
+
+    fn feed(books: &[LibraryBook]) -> Feed<'_> {
+        Feed {
+            id: "urn:readest:recent".into(),
+            title: "Recently Updated".into(),
+            href: "/opds/v1/recent".into(),
+            next: Some("/opds/v1/recent?page=2".into()),
+            entries: FeedEntries::Publications(books.iter().collect()),
+        }
+    }
+
+    fn terms(query: &str) -> Vec<String> {
+        query.split_whitespace().map(str::to_lowercase).collect()
+    }
+
+    #[test]
+    fn loads_books_that_are_not_deleted() {
+        let hashes = library()
+            .into_iter()
+            .map(|book| book.hash)
+            .collect::<Vec<_>>();
+        assert_eq!(hashes, ["aaa111", "bbb222"]);
+    }
+
+    #[test]
+    fn paginates_books() {
+        let library = (0..PAGE_SIZE * 2 + 1)
+            .map(|i| LibraryBook {
+                hash: i.to_string(),
+                format: "EPUB".into(),
+                title: String::new(),
+                author: String::new(),
+                updated_at: 0.0,
+                deleted_at: None,
+                primary_language: None,
+                metadata: None,
+            })
+            .collect::<Vec<_>>();
+        let page = |page, href| {
+            let (books, next) = paginate(library.iter().collect(), page, href);
+            let first = books.first().map(|book| book.hash.clone());
+            (books.len(), first, next)
+        };
+
+        let first = Some("0".to_string());
+        let next = Some("/recent?page=2".to_string());
+        assert_eq!(page(None, "/recent"), (PAGE_SIZE, first.clone(), next));
+        assert_eq!(page(Some(0), "/recent").1, first);
+        let next = Some("/search?q=a&page=3".to_string());
+        assert_eq!(
+            page(Some(2), "/search?q=a"),
+            (PAGE_SIZE, Some("50".into()), next)
+        );
+        assert_eq!(page(Some(3), "/recent"), (1, Some("100".into()), None));
+        assert_eq!(page(Some(4), "/recent"), (0, None, None));
+        assert_eq!(page(Some(usize::MAX), "/recent"), (0, None, None));
+        assert_eq!(
+            page(Some(usize::MAX / PAGE_SIZE + 1), "/recent"),
+            (0, None, None)
+        );
+    }
+
+    #[test]
+    fn matches_all_terms_in_any_field() {
+        let library = library();
+        let book = &library[0];
+        assert!(book.matches(&[]));
+        assert!(book.matches(&terms("left darkness")));
+        assert!(book.matches(&terms("LE GUIN")));
+        assert!(book.matches(&terms("hainish")));
+        assert!(book.matches(&terms("gender fiction")));
+        assert!(book.matches(&terms("ace")));
+        assert!(book.matches(&terms("gethen")));
+        assert!(!book.matches(&terms("left earthsea")));
+        assert!(library[1].matches(&terms("fantasy wizard")));
+        assert!(!library[1].matches(&terms("science")));
+    }
+
+    #[test]
+    fn renders_atom_feeds() {
+        let library = library();
+        let xml = render_atom(&feed(&library), "acquisition");
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.ends_with("</feed>"));
+        assert_eq!(xml.matches("<entry>").count(), 2);
+        for part in [
+            r#"<link rel="self" href="/opds/v1/recent" type="application/atom+xml;profile=opds-catalog;kind=acquisition"/>"#,
+            r#"<link rel="next" href="/opds/v1/recent?page=2""#,
+            "<title>The Left Hand of Darkness</title><id>urn:readest:book:aaa111</id>",
+            "<updated>2023-11-14T22:13:20Z</updated>",
+            "<dc:language>en</dc:language>",
+            "<dc:publisher>Ace &amp; Sons</dc:publisher>",
+            r#"<category term="Gender" label="Gender"/>"#,
+            "An envoy on the planet &lt;Gethen&gt;.",
+            r#"<link rel="http://opds-spec.org/acquisition" href="/books/aaa111/file" type="application/epub+zip"/>"#,
+            r#"<link rel="http://opds-spec.org/acquisition" href="/books/bbb222/file" type="application/pdf"/>"#,
+            r#"<link rel="http://opds-spec.org/image/thumbnail" href="/books/bbb222/thumbnail" type="image/png"/>"#,
+        ] {
+            assert!(xml.contains(part), "{part} missing from {xml}");
+        }
+    }
+
+    #[test]
+    fn renders_json_feeds() {
+        let library = library();
+        let feed = render_json(&feed(&library));
+        assert_eq!(feed["metadata"]["title"], "Recently Updated");
+        let links = feed["links"].as_array().unwrap();
+        assert!(links.contains(&json!({
+            "rel": "next",
+            "href": "/opds/v1/recent?page=2",
+            "type": "application/opds+json",
+        })));
+
+        let publications = feed["publications"].as_array().unwrap();
+        assert_eq!(publications.len(), 2);
+        let metadata = &publications[0]["metadata"];
+        assert_eq!(metadata["identifier"], "urn:readest:book:aaa111");
+        assert_eq!(metadata["author"], json!([{ "name": "Ursula K. Le Guin" }]));
+        assert_eq!(metadata["modified"], "2023-11-14T22:13:20Z");
+        assert_eq!(metadata["subject"], json!(["Science Fiction", "Gender"]));
+        assert_eq!(
+            metadata["belongsTo"],
+            json!({ "series": { "name": "Hainish Cycle", "position": 6.0 } })
+        );
+        assert_eq!(
+            publications[1]["links"],
+            json!([{
+                "rel": "http://opds-spec.org/acquisition",
+                "href": "/books/bbb222/file",
+                "type": "application/pdf",
+            }])
+        );
+        assert!(publications[1]["metadata"].get("belongsTo").is_none());
+    }
+}
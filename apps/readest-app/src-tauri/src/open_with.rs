@@ -0,0 +1,295 @@
+//! Files handed to the app on the command line, by a second instance or by the OS.
+//!
+//! Files named one by one are opened. Folders are searched recursively and glob patterns are
+//! expanded, since not every shell does so, and the books found there, i.e. the files with the
+//! extensions of the bundle's file associations, are imported. Every file is checked with
+//! `detect_book_format` and only books, and the folders that were named, are allowed in the fs
+//! and asset protocol scopes.
+//!
+//! Books to open are queued as pending opens until the frontend takes them with
+//! take_pending_opens, after which they arrive as `open-files` events.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::{command, AppHandle, Manager, State, Url};
+use tauri_plugin_fs::FsExt;
+
+use crate::book_format::{detect_book_format, BookFormat};
+use crate::cli::{self, CliRequest};
+use crate::event_queue::EventQueue;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenFile {
+    pub path: PathBuf,
+    pub format: BookFormat,
+    pub cfi: Option<String>, // Location to open the book at
+    pub page: Option<u32>,   // 1-based page to open the book at, if there is no CFI
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct BookPaths {
+    pub opened: Vec<OpenFile>, // Files that were named one by one
+    pub found: Vec<PathBuf>,   // Books found in folders or by glob patterns
+    pub rejected: Vec<RejectedFile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingOpenFiles {
+    pub files: Vec<OpenFile>,
+    pub rejected: Vec<RejectedFile>,
+}
+
+pub struct PendingOpens {
+    files: EventQueue<OpenFile>,
+    rejected: EventQueue<RejectedFile>,
+}
+
+impl Default for PendingOpens {
+    fn default() -> Self {
+        PendingOpens {
+            files: EventQueue::new("open-files"),
+            rejected: EventQueue::new("open-files-rejected"),
+        }
+    }
+}
+
+impl PendingOpens {
+    pub fn reset(&self) {
+        self.files.reset();
+        self.rejected.reset();
+    }
+}
+
+// The extensions of the file associations in tauri.conf.json, in lower case.
+pub fn book_extensions(app: &AppHandle) -> Vec<String> {
+    app.config()
+        .bundle
+        .file_associations
+        .iter()
+        .flatten()
+        .flat_map(|association| association.ext.iter())
+        .map(|ext| ext.0.to_lowercase())
+        .collect()
+}
+
+fn is_book(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.contains(&e.to_lowercase()))
+}
+
+pub fn allow_file_in_scopes(app: &AppHandle, files: &[PathBuf]) {
+    let fs_scope = app.fs_scope();
+    let asset_protocol_scope = app.asset_protocol_scope();
+    for file in files {
+        if let Err(e) = fs_scope.allow_file(file) {
+            log::error!("Failed to allow file in fs_scope: {e}");
+        } else {
+            log::debug!("Allowed file in fs_scope: {file:?}");
+        }
+        if let Err(e) = asset_protocol_scope.allow_file(file) {
+            log::error!("Failed to allow file in asset_protocol_scope: {e}");
+        } else {
+            log::debug!("Allowed file in asset_protocol_scope: {file:?}");
+        }
+    }
+}
+
+// Allows a folder and everything below it, so that the frontend can read the folders it was
+// asked to import.
+fn allow_directory_in_scopes(app: &AppHandle, dir: &Path) {
+    if let Err(e) = app.fs_scope().allow_directory(dir, true) {
+        log::error!("Failed to allow directory in fs_scope: {e}");
+    } else {
+        log::debug!("Allowed directory in fs_scope: {dir:?}");
+    }
+    if let Err(e) = app.asset_protocol_scope().allow_directory(dir, true) {
+        log::error!("Failed to allow directory in asset_protocol_scope: {e}");
+    } else {
+        log::debug!("Allowed directory in asset_protocol_scope: {dir:?}");
+    }
+}
+
+// Collects the books in `dir`, sorted by path so that they are imported in a stable order.
+fn collect_books(dir: &Path, recursive: bool, extensions: &[String], books: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        log::warn!("Failed to read {}", dir.display());
+        return;
+    };
+    let mut paths = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            if recursive {
+                collect_books(&path, recursive, extensions, books);
+            }
+        } else if is_book(&path, extensions) {
+            books.push(path);
+        }
+    }
+}
+
+// URLs such as deep links may contain `?` or `[`, so only other arguments are patterns. The
+// drive letter of a Windows path parses as a URL scheme, so one letter schemes don't count.
+fn is_glob(arg: &str) -> bool {
+    let is_url = Url::parse(arg).is_ok_and(|url| url.scheme().len() > 1);
+    !is_url && arg.contains(['*', '?', '['])
+}
+
+impl BookPaths {
+    // Resolves `args` against `cwd`, the working directory of the process that received them,
+    // and allows the books among them in the scopes. Folders are only searched below their top
+    // level if `recursive` is set. Arguments that are neither files nor folders, such as flags
+    // and deep links, are skipped.
+    pub fn resolve<'a>(
+        app: &AppHandle,
+        args: impl IntoIterator<Item = &'a str>,
+        cwd: &Path,
+        recursive: bool,
+    ) -> Self {
+        let extensions = book_extensions(app);
+        let mut paths = BookPaths::default();
+        let mut opened = Vec::new();
+        for arg in args {
+            // Files may also be passed as `file://path/to/file`
+            let path = match Url::parse(arg) {
+                Ok(url) if url.scheme() == "file" => match url.to_file_path() {
+                    Ok(path) => path,
+                    Err(_) => continue,
+                },
+                _ => cwd.join(arg),
+            };
+            if path.is_dir() {
+                allow_directory_in_scopes(app, &path);
+                collect_books(&path, recursive, &extensions, &mut paths.found);
+            } else if path.is_file() {
+                opened.push(path);
+            } else if is_glob(arg) {
+                let Some(pattern) = path.to_str() else {
+                    continue;
+                };
+                match glob::glob(pattern) {
+                    Ok(matches) => paths.found.extend(
+                        matches
+                            .flatten()
+                            .filter(|path| path.is_file() && is_book(path, &extensions)),
+                    ),
+                    Err(e) => log::warn!("Invalid pattern {arg}: {e}"),
+                }
+            }
+        }
+        paths.opened = opened
+            .into_iter()
+            .filter_map(|path| {
+                let format = paths.check_book(&path)?;
+                Some(OpenFile {
+                    path,
+                    format,
+                    cfi: None,
+                    page: None,
+                })
+            })
+            .collect();
+        let found = std::mem::take(&mut paths.found);
+        paths.found = found
+            .into_iter()
+            .filter(|path| paths.check_book(path).is_some())
+            .collect();
+
+        let opened = paths.opened.iter().map(|file| file.path.clone());
+        allow_file_in_scopes(app, &opened.collect::<Vec<_>>());
+        allow_file_in_scopes(app, &paths.found);
+        paths
+    }
+
+    // Detects the format of a file, recording why it was rejected if it is not a book.
+    fn check_book(&mut self, path: &Path) -> Option<BookFormat> {
+        match detect_book_format(path) {
+            Ok(format) => Some(format),
+            Err(e) => {
+                log::warn!("Rejected {}: {e}", path.display());
+                self.rejected.push(RejectedFile {
+                    path: path.to_path_buf(),
+                    reason: e.to_string(),
+                });
+                None
+            }
+        }
+    }
+
+    // Resolves the arguments of the process, skipping the program and flags. Everything after
+    // `--` is a file, even if it looks like a flag.
+    pub fn from_argv(app: &AppHandle, argv: &[String], cwd: &Path) -> Self {
+        let args = argv.get(1..).unwrap_or_default();
+        let (flags, files) = match args.iter().position(|arg| arg == "--") {
+            Some(end) => (&args[..end], &args[end + 1..]),
+            None => (args, &[][..]),
+        };
+        let args = flags
+            .iter()
+            .filter(|arg| !arg.starts_with('-'))
+            .chain(files)
+            .map(String::as_str);
+        BookPaths::resolve(app, args, cwd, true)
+    }
+}
+
+// Hands the books to the frontend: the ones named one by one are opened and the ones found in
+// folders are imported.
+pub fn open_books(app: &AppHandle, paths: BookPaths) {
+    let pending = app.state::<PendingOpens>();
+    pending.files.push(app, paths.opened);
+    if !paths.found.is_empty() {
+        cli::dispatch(app, CliRequest::import(paths.found));
+    }
+    pending.rejected.push(app, paths.rejected);
+}
+
+pub fn report_rejected(app: &AppHandle, rejected: Vec<RejectedFile>) {
+    app.state::<PendingOpens>().rejected.push(app, rejected);
+}
+
+// Returns the files that arrived before the frontend was listening. Later files are emitted
+// as `open-files` and `open-files-rejected` events.
+#[command]
+pub fn take_pending_opens(pending: State<'_, PendingOpens>) -> PendingOpenFiles {
+    PendingOpenFiles {
+        files: pending.files.take(),
+        rejected: pending.rejected.take(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_glob_patterns() {
+        assert!(is_glob("*.epub"));
+        assert!(is_glob("books/vol[12].epub"));
+        assert!(is_glob(r"C:\Books\*.epub"));
+        assert!(!is_glob("book.epub"));
+    }
+
+    #[test]
+    fn ignores_patterns_in_urls() {
+        assert!(!is_glob(
+            "readest://open?book=0123456789abcdef0123456789abcdef"
+        ));
+        assert!(!is_glob("https://example.com/books?page=[2]"));
+        assert!(!is_glob("file:///home/user/*.epub"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Reader windows opened from Rust, one per book, next to the main window.
+//!
+//! Each window is labelled `reader-<book hash>`, so opening a book that is already open focuses
+//! its window, and the window-state plugin remembers the size and position of each book's
+//! window across sessions.
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{ser::Serializer, Serialize};
+use tauri::{command, AppHandle, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder};
+
+#[cfg(target_os = "macos")]
+use tauri::TitleBarStyle;
+
+use crate::deep_link::is_book_hash;
+
+type Result<T> = std::result::Result<T, Error>;
+
+const LABEL_PREFIX: &str = "reader-";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[error("invalid book id {0}")]
+    InvalidBookId(String),
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_ref())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReaderWindow {
+    pub label: String,
+    pub book_id: String,
+    pub title: String,
+    pub focused: bool,
+}
+
+fn label(book_id: &str) -> Result<String> {
+    if is_book_hash(book_id) {
+        Ok(format!("{LABEL_PREFIX}{}", book_id.to_lowercase()))
+    } else {
+        Err(Error::InvalidBookId(book_id.to_string()))
+    }
+}
+
+// Reader windows opened by the frontend have other labels, such as `reader-0`.
+fn book_id(window: &WebviewWindow) -> Option<String> {
+    window
+        .label()
+        .strip_prefix(LABEL_PREFIX)
+        .filter(|id| is_book_hash(id))
+        .map(str::to_string)
+}
+
+// Builds a window that looks like the main one, see run() in lib.rs.
+fn build(app: &AppHandle, label: &str, url: String) -> Result<WebviewWindow> {
+    let builder = WebviewWindowBuilder::new(app, label, WebviewUrl::App(url.into()))
+        .inner_size(800.0, 600.0)
+        .center()
+        .resizable(true);
+
+    #[cfg(target_os = "macos")]
+    let builder = builder
+        .decorations(true)
+        .title_bar_style(TitleBarStyle::Overlay)
+        .title("");
+
+    #[cfg(not(target_os = "macos"))]
+    let builder = builder
+        .decorations(false)
+        .shadow(true)
+        .transparent(cfg!(target_os = "linux"))
+        .title("Readest");
+
+    Ok(builder.build()?)
+}
+
+// Opens a book in its own window, at `location` if given, or focuses the book's window if it
+// is already open. Async, since creating a window in a sync command deadlocks on Windows.
+#[command]
+pub async fn open_reader_window(
+    app: AppHandle,
+    book_id: String,
+    location: Option<String>, // A CFI, only used when the window is created
+) -> Result<ReaderWindow> {
+    let label = label(&book_id)?;
+    let window = match app.get_webview_window(&label) {
+        Some(window) => {
+            window.unminimize()?;
+            window
+        }
+        None => {
+            let mut url = format!("reader?ids={}", book_id.to_lowercase());
+            if let Some(cfi) = location.filter(|cfi| !cfi.is_empty()) {
+                url.push_str("&cfi=");
+                url.extend(utf8_percent_encode(&cfi, NON_ALPHANUMERIC));
+            }
+            build(&app, &label, url)?
+        }
+    };
+    window.show()?;
+    window.set_focus()?;
+    Ok(ReaderWindow {
+        book_id: book_id.to_lowercase(),
+        title: window.title()?,
+        focused: true,
+        label,
+    })
+}
+
+// Closes the book's window, giving its frontend the chance to save the reading progress.
+// Returns false if the book is not open in a window.
+#[command]
+pub fn close_reader_window(app: AppHandle, book_id: String) -> Result<bool> {
+    match app.get_webview_window(&label(&book_id)?) {
+        Some(window) => {
+            window.close()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[command]
+pub fn list_reader_windows(app: AppHandle) -> Result<Vec<ReaderWindow>> {
+    let mut windows = app
+        .webview_windows()
+        .into_values()
+        .filter_map(|window| {
+            let book_id = book_id(&window)?;
+            Some((window, book_id))
+        })
+        .map(|(window, book_id)| {
+            Ok(ReaderWindow {
+                label: window.label().to_string(),
+                title: window.title()?,
+                focused: window.is_focused()?,
+                book_id,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    windows.sort_by(|a, b| a.label.cmp(&b.label));
+    Ok(windows)
+}
@@ -0,0 +1,321 @@
+//! Typed runtime configuration for the frontend, in place of reading arbitrary environment
+//! variables.
+//!
+//! Each setting can come from `runtime-config.json` in the app config dir, an allowlisted
+//! `READEST_*` environment variable or a command line flag, in increasing order of precedence.
+//! The frontend is told where each value came from.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tauri::{command, AppHandle, Manager};
+
+const CONFIG_FILE: &str = "runtime-config.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConfigSource {
+    Default,
+    ConfigFile,
+    Environment,
+    CommandLine,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigValue<T> {
+    pub value: T,
+    pub source: ConfigSource,
+    pub name: Option<String>, // Environment variable or flag that set the value
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeConfig {
+    // Sign in through a loopback server instead of the readest:// scheme, e.g. in Flatpak
+    pub use_custom_oauth: ConfigValue<bool>,
+}
+
+// A boolean setting and the names it goes by in each source.
+struct BoolSetting {
+    file_key: &'static str,
+    env_vars: &'static [&'static str],
+    flag: &'static str, // Given as --flag, --flag=<bool> or --no-flag
+}
+
+const USE_CUSTOM_OAUTH: BoolSetting = BoolSetting {
+    file_key: "useCustomOAuth",
+    // USE_CUSTOM_OAUTH predates the READEST_ prefix and is still set by existing launchers
+    env_vars: &["READEST_USE_CUSTOM_OAUTH", "USE_CUSTOM_OAUTH"],
+    flag: "custom-oauth",
+};
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" | "" => Some(false),
+        _ => None,
+    }
+}
+
+impl BoolSetting {
+    // Environment variables are read through `env`, so that the result only depends on the
+    // arguments.
+    fn resolve(
+        &self,
+        file: &Map<String, Value>,
+        env: impl Fn(&str) -> Option<String>,
+        args: &[String],
+    ) -> ConfigValue<bool> {
+        let mut setting = ConfigValue {
+            value: false,
+            source: ConfigSource::Default,
+            name: None,
+        };
+        if let Some(value) = file.get(self.file_key).and_then(Value::as_bool) {
+            setting = ConfigValue {
+                value,
+                source: ConfigSource::ConfigFile,
+                name: Some(self.file_key.to_string()),
+            };
+        }
+        // Earlier names take precedence over later ones
+        for name in self.env_vars.iter().rev() {
+            let Some(raw) = env(name) else {
+                continue;
+            };
+            match parse_bool(&raw) {
+                Some(value) => {
+                    setting = ConfigValue {
+                        value,
+                        source: ConfigSource::Environment,
+                        name: Some(name.to_string()),
+                    }
+                }
+                None => log::warn!("Ignoring {name}={raw}, expected a boolean"),
+            }
+        }
+        // The last occurrence of the flag wins, as with most command line tools. Only files
+        // follow `--`
+        for arg in args.iter().take_while(|arg| *arg != "--") {
+            let value = if let Some(rest) = arg
+                .strip_prefix("--")
+                .and_then(|a| a.strip_prefix(self.flag))
+            {
+                match rest.strip_prefix('=') {
+                    Some(raw) => parse_bool(raw),
+                    None if rest.is_empty() => Some(true),
+                    None => None,
+                }
+            } else if arg.strip_prefix("--no-") == Some(self.flag) {
+                Some(false)
+            } else {
+                None
+            };
+            if let Some(value) = value {
+                setting = ConfigValue {
+                    value,
+                    source: ConfigSource::CommandLine,
+                    name: Some(format!("--{}", self.flag)),
+                };
+            }
+        }
+        setting
+    }
+}
+
+fn load_config_file(app: &AppHandle) -> Map<String, Value> {
+    let Ok(path) = app.path().app_config_dir().map(|dir| dir.join(CONFIG_FILE)) else {
+        return Map::new();
+    };
+    let Ok(data) = std::fs::read(&path) else {
+        return Map::new();
+    };
+    match serde_json::from_slice(&data) {
+        Ok(Value::Object(map)) => map,
+        Ok(_) => {
+            log::warn!("Ignoring {}, expected a JSON object", path.display());
+            Map::new()
+        }
+        Err(e) => {
+            log::warn!("Ignoring {}: {e}", path.display());
+            Map::new()
+        }
+    }
+}
+
+fn runtime_config(app: &AppHandle) -> RuntimeConfig {
+    let file = load_config_file(app);
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    RuntimeConfig {
+        use_custom_oauth: USE_CUSTOM_OAUTH.resolve(&file, |name| std::env::var(name).ok(), &args),
+    }
+}
+
+#[command]
+pub fn get_runtime_config(app: AppHandle) -> RuntimeConfig {
+    runtime_config(&app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn resolve(
+        file: Value,
+        env: &[(&str, &str)],
+        args: &[&str],
+    ) -> (bool, ConfigSource, Option<String>) {
+        let Value::Object(file) = file else {
+            panic!("the config file must be an object");
+        };
+        let env = |name: &str| {
+            env.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        };
+        let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let setting = USE_CUSTOM_OAUTH.resolve(&file, env, &args);
+        (setting.value, setting.source, setting.name)
+    }
+
+    fn set(value: bool, source: ConfigSource, name: &str) -> (bool, ConfigSource, Option<String>) {
+        (value, source, Some(name.to_string()))
+    }
+
+    #[test]
+    fn resolves_each_source() {
+        let cases = [
+            (
+                json!({}),
+                vec![],
+                vec![],
+                (false, ConfigSource::Default, None),
+            ),
+            (
+                json!({ "useCustomOAuth": true }),
+                vec![],
+                vec![],
+                set(true, ConfigSource::ConfigFile, "useCustomOAuth"),
+            ),
+            (
+                json!({ "useCustomOAuth": "yes" }),
+                vec![],
+                vec![],
+                (false, ConfigSource::Default, None),
+            ),
+            (
+                json!({}),
+                vec![("READEST_USE_CUSTOM_OAUTH", "1")],
+                vec![],
+                set(true, ConfigSource::Environment, "READEST_USE_CUSTOM_OAUTH"),
+            ),
+            (
+                json!({}),
+                vec![("USE_CUSTOM_OAUTH", " On ")],
+                vec![],
+                set(true, ConfigSource::Environment, "USE_CUSTOM_OAUTH"),
+            ),
+            (
+                json!({}),
+                vec![("READEST_USE_CUSTOM_OAUTH", "maybe")],
+                vec![],
+                (false, ConfigSource::Default, None),
+            ),
+            (
+                json!({}),
+                vec![],
+                vec!["--custom-oauth"],
+                set(true, ConfigSource::CommandLine, "--custom-oauth"),
+            ),
+            (
+                json!({}),
+                vec![],
+                vec!["--custom-oauth=false"],
+                set(false, ConfigSource::CommandLine, "--custom-oauth"),
+            ),
+            (
+                json!({ "useCustomOAuth": true }),
+                vec![],
+                vec!["--no-custom-oauth"],
+                set(false, ConfigSource::CommandLine, "--custom-oauth"),
+            ),
+        ];
+        for (file, env, args, expected) in cases {
+            assert_eq!(
+                resolve(file.clone(), &env, &args),
+                expected,
+                "{file} {env:?} {args:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn applies_sources_in_order_of_precedence() {
+        let file = json!({ "useCustomOAuth": false });
+        let env = [("USE_CUSTOM_OAUTH", "true")];
+        assert_eq!(
+            resolve(file.clone(), &env, &[]),
+            set(true, ConfigSource::Environment, "USE_CUSTOM_OAUTH")
+        );
+        assert_eq!(
+            resolve(file, &env, &["--custom-oauth=0"]),
+            set(false, ConfigSource::CommandLine, "--custom-oauth")
+        );
+        // Earlier environment variables win, also when the later one is set to true
+        let env = [
+            ("USE_CUSTOM_OAUTH", "true"),
+            ("READEST_USE_CUSTOM_OAUTH", "false"),
+        ];
+        assert_eq!(
+            resolve(json!({}), &env, &[]),
+            set(false, ConfigSource::Environment, "READEST_USE_CUSTOM_OAUTH")
+        );
+        // An invalid value leaves the later one in effect
+        let env = [
+            ("READEST_USE_CUSTOM_OAUTH", "maybe"),
+            ("USE_CUSTOM_OAUTH", "true"),
+        ];
+        assert_eq!(
+            resolve(json!({}), &env, &[]),
+            set(true, ConfigSource::Environment, "USE_CUSTOM_OAUTH")
+        );
+    }
+
+    #[test]
+    fn reads_flags_up_to_the_separator() {
+        let cases: [(&[&str], _); 7] = [
+            (
+                &["--custom-oauth", "--no-custom-oauth"],
+                set(false, ConfigSource::CommandLine, "--custom-oauth"),
+            ),
+            (
+                &["--no-custom-oauth", "--custom-oauth=yes"],
+                set(true, ConfigSource::CommandLine, "--custom-oauth"),
+            ),
+            (
+                &["--custom-oauth", "--custom-oauth=maybe"],
+                set(true, ConfigSource::CommandLine, "--custom-oauth"),
+            ),
+            (
+                &["--", "--custom-oauth"],
+                (false, ConfigSource::Default, None),
+            ),
+            (
+                &["--custom-oauth", "--", "--no-custom-oauth"],
+                set(true, ConfigSource::CommandLine, "--custom-oauth"),
+            ),
+            (
+                &["--custom-oauthx", "-custom-oauth", "custom-oauth"],
+                (false, ConfigSource::Default, None),
+            ),
+            (
+                &["--no-custom-oauthx", "--no-custom-oauth=true"],
+                (false, ConfigSource::Default, None),
+            ),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(resolve(json!({}), &[], args), expected, "{args:?}");
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Tunables of the transfer subsystem: bandwidth limit, part size and concurrency of
+//! multi-part downloads, set globally and optionally overridden per transfer.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+const MIN_PART_SIZE: u64 = 64 * 1024;
+const MAX_CONCURRENCY: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TransferConfig {
+    pub max_bytes_per_sec: Option<u64>, // Bandwidth limit shared by all transfers, None for unlimited
+    pub part_size: u64,                 // Size of each range request of a multi-part download
+    pub concurrency: usize,             // Number of range requests in flight per download
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes_per_sec: None,
+            part_size: 1024 * 1024,
+            concurrency: 8,
+        }
+    }
+}
+
+impl TransferConfig {
+    // Keeps the values in a range that neither floods the server nor degenerates into tiny requests.
+    pub fn sanitized(self) -> Self {
+        Self {
+            max_bytes_per_sec: self.max_bytes_per_sec.filter(|rate| *rate > 0),
+            part_size: self.part_size.max(MIN_PART_SIZE),
+            concurrency: self.concurrency.clamp(1, MAX_CONCURRENCY),
+        }
+    }
+}
+
+// Per-call overrides of the global TransferConfig, passed to download_file and upload_file.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferOverrides {
+    pub max_bytes_per_sec: Option<u64>, // Additional limit for this transfer only
+    pub part_size: Option<u64>,
+    pub concurrency: Option<usize>,
+}
+
+impl TransferOverrides {
+    // The bandwidth limit of the result only applies to this transfer, on top of the global one.
+    pub fn apply(self, config: TransferConfig) -> TransferConfig {
+        TransferConfig {
+            max_bytes_per_sec: self.max_bytes_per_sec,
+            part_size: self.part_size.unwrap_or(config.part_size),
+            concurrency: self.concurrency.unwrap_or(config.concurrency),
+        }
+        .sanitized()
+    }
+}
+
+struct Bucket {
+    tokens: f64,   // Bytes that may be sent right away, negative while waiting for a refill
+    last: Instant, // Time of the last refill
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Self {
+            tokens: 0.0,
+            last: Instant::now(),
+        }
+    }
+}
+
+// The RateLimiter struct is a token bucket holding at most one second worth of bytes.
+// Its rate can be changed at any time and applies to the next acquired chunk.
+#[derive(Default)]
+pub struct RateLimiter {
+    rate: AtomicU64, // Bytes per second, 0 for unlimited
+    bucket: tokio::sync::Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: Option<u64>) -> Self {
+        Self {
+            rate: AtomicU64::new(rate.unwrap_or(0)),
+            bucket: tokio::sync::Mutex::default(),
+        }
+    }
+
+    pub fn set_rate(&self, rate: Option<u64>) {
+        self.rate.store(rate.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    // Waits until `bytes` may be transferred without exceeding the rate.
+    pub async fn acquire(&self, bytes: usize) {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return;
+        }
+        // The lock is held while sleeping so that concurrent parts queue up in order
+        let mut bucket = self.bucket.lock().await;
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last).as_secs_f64() * rate as f64;
+        bucket.tokens = (bucket.tokens + refill).min(rate as f64) - bytes as f64;
+        bucket.last = now;
+        if bucket.tokens < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-bucket.tokens / rate as f64)).await;
+        }
+    }
+}
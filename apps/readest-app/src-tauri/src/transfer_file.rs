@@ -6,69 +6,434 @@
 //!
 //! Download files from a remote HTTP server to disk.
 
+use bytes::{Bytes, BytesMut};
 use futures_util::TryStreamExt;
-use serde::{ser::Serializer, Serialize};
-use tauri::{command, ipc::Channel};
+use md5::Md5;
+use reqwest::header::{HeaderMap, HeaderName, AUTHORIZATION, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Method, RequestBuilder};
+use serde::{ser::Serializer, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{command, ipc::Channel, AppHandle, Manager, Runtime, State};
 use tokio::{
-    fs::File,
-    io::{AsyncWriteExt, BufWriter},
+    fs::{File, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter},
 };
 use tokio_util::codec::{BytesCodec, FramedRead};
 
 use read_progress_stream::ReadProgressStream;
 
-use std::time::Instant;
+use crate::http_client::HttpClient;
+use crate::transfer_config::TransferOverrides;
+use crate::transfer_registry::{CancelPolicy, TransferHandle, TransferRegistry};
+
+use std::cmp::min;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use std::{collections::HashMap, sync::Arc};
 
-type Result<T> = std::result::Result<T, Error>;
+pub(crate) type Result<T> = std::result::Result<T, Error>;
+
+// Suffix of the temporary file a download is written to before it is renamed into place.
+pub(crate) const TEMP_SUFFIX: &str = ".download";
+
+// Suffix of the sidecar file that records the progress of an interrupted download.
+pub(crate) const PARTIAL_SUFFIX: &str = ".part.json";
+
+// Resumable downloads left untouched for longer than this are swept at startup.
+const STALE_DOWNLOAD_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+// How many bytes the single-stream download writes between two checkpoints of its sidecar file.
+const CHECKPOINT_SIZE: u64 = 4 * 1024 * 1024;
+
+// How many times a single part of a multi-part transfer is attempted before giving up.
+const PART_ATTEMPTS: u32 = 4;
+
+// Delay before the first retry of a failed part, doubled after every further attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+// Length of the periods over which the transfer speed is sampled.
+const SPEED_SAMPLE_PERIOD: Duration = Duration::from_millis(500);
 
-// The TransferStats struct tracks both transfer speed and cumulative transfer progress.
+// Time constant of the smoothed transfer speed: a sample this old has about a third of its weight left.
+const SPEED_TIME_CONSTANT: Duration = Duration::from_secs(3);
+
+struct RateEstimate {
+    transferred: u64,      // Bytes that count towards the progress
+    sample_bytes: u64,     // Bytes received in the current sample period
+    sample_start: Instant, // Time when the current sample period started
+    speed: Option<f64>,    // Smoothed speed in bytes per second, None until the first sample
+}
+
+// The TransferStats struct estimates the progress, speed and remaining time of a transfer.
+// A single instance is shared by all parts of a transfer, so the speed is that of the whole
+// transfer rather than of whichever part reported last.
 pub struct TransferStats {
-    accumulated_chunk_len: usize, // Total length of chunks transferred in the current period
-    accumulated_time: u128,       // Total time taken for the transfers in the current period
-    pub transfer_speed: u64,      // Calculated transfer speed in bytes per second
-    pub total_transferred: u64,   // Cumulative total of all transferred data
-    start_time: Instant,          // Time when the current period started
-    granularity: u32, // Time period (in milliseconds) over which the transfer speed is calculated
+    total: u64,          // Total size of the transfer, 0 if unknown
+    start_time: Instant, // Time when the transfer started
+    state: std::sync::Mutex<RateEstimate>,
 }
 
 impl TransferStats {
-    // Initializes a new TransferStats instance with the specified granularity.
-    pub fn start(granularity: u32) -> Self {
+    // Starts tracking a transfer of `total` bytes, `transferred` of which are already done (e.g. when resuming).
+    pub fn new(total: u64, transferred: u64) -> Self {
+        let now = Instant::now();
         Self {
-            accumulated_chunk_len: 0,
-            accumulated_time: 0,
-            transfer_speed: 0,
-            total_transferred: 0,
-            start_time: Instant::now(),
-            granularity,
+            total,
+            start_time: now,
+            state: std::sync::Mutex::new(RateEstimate {
+                transferred,
+                sample_bytes: 0,
+                sample_start: now,
+                speed: None,
+            }),
         }
     }
-    // Records the transfer of a data chunk and updates both transfer speed and total progress.
-    pub fn record_chunk_transfer(&mut self, chunk_len: usize) {
+
+    // Records bytes moved over the network, which drive the speed estimate.
+    pub fn record_received(&self, len: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.sample_bytes += len as u64;
         let now = Instant::now();
-        let it_took = now.duration_since(self.start_time).as_millis();
-        self.accumulated_chunk_len += chunk_len;
-        self.total_transferred += chunk_len as u64;
-        self.accumulated_time += it_took;
+        let elapsed = now.duration_since(state.sample_start).as_secs_f64();
+        if elapsed < SPEED_SAMPLE_PERIOD.as_secs_f64() {
+            return;
+        }
+
+        // Exponentially weighted moving average, with weights based on the actual period length
+        let sample = state.sample_bytes as f64 / elapsed;
+        let weight = 1.0 - (-elapsed / SPEED_TIME_CONSTANT.as_secs_f64()).exp();
+        state.speed = Some(match state.speed {
+            Some(speed) => speed + weight * (sample - speed),
+            None => sample,
+        });
+        state.sample_bytes = 0;
+        state.sample_start = now;
+    }
+
+    // Records bytes that are final and count towards the progress.
+    pub fn record_completed(&self, len: usize) {
+        self.state.lock().unwrap().transferred += len as u64;
+    }
+
+    // Records a chunk that is final as soon as it is received, as in a single-stream transfer.
+    pub fn record_chunk_transfer(&self, len: usize) {
+        self.record_received(len);
+        self.record_completed(len);
+    }
+
+    pub fn payload(&self) -> ProgressPayload {
+        let state = self.state.lock().unwrap();
+        let speed = state.speed.unwrap_or(0.0);
+        let eta_secs = if self.total > 0 && state.transferred >= self.total {
+            Some(0)
+        } else if self.total > 0 && speed >= 1.0 {
+            Some(((self.total - state.transferred) as f64 / speed).ceil() as u64)
+        } else {
+            None
+        };
+        ProgressPayload {
+            progress: state.transferred,
+            total: self.total,
+            transfer_speed: speed.round() as u64,
+            eta_secs,
+            elapsed_secs: self.start_time.elapsed().as_secs(),
+        }
+    }
+}
+
+// The PartialDownload struct is kept next to the target file while a download is in progress,
+// so that an interrupted transfer can be resumed by requesting only the missing byte ranges.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartialDownload {
+    total: u64,                    // Total size of the remote file, 0 if unknown
+    etag: Option<String>,          // ETag of the remote file when the download started
+    last_modified: Option<String>, // Last-Modified of the remote file when the download started
+    completed: Vec<(u64, u64)>,    // Sorted, non-overlapping [start, end) ranges already on disk
+}
+
+impl PartialDownload {
+    // Creates an empty partial state for the remote file described by the response headers.
+    fn from_headers(total: u64, headers: &HeaderMap) -> Self {
+        Self {
+            total,
+            etag: header_value(headers, ETAG),
+            last_modified: header_value(headers, LAST_MODIFIED),
+            completed: Vec::new(),
+        }
+    }
+
+    fn sidecar_path(file_path: &str) -> PathBuf {
+        PathBuf::from(format!("{file_path}{PARTIAL_SUFFIX}"))
+    }
+
+    // Loads the sidecar of a previous attempt, if there is a readable one.
+    async fn load(file_path: &str) -> Option<Self> {
+        let data = tokio::fs::read(Self::sidecar_path(file_path)).await.ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    // Persists the sidecar through a temporary file so a crash never leaves it half written.
+    async fn save(&self, file_path: &str) -> Result<()> {
+        let path = Self::sidecar_path(file_path);
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec(self)?).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    async fn discard(file_path: &str) {
+        let _ = tokio::fs::remove_file(Self::sidecar_path(file_path)).await;
+    }
+
+    // Without a validator there is no way to tell whether the remote file changed in between.
+    fn is_resumable(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+
+    // Checks whether `remote` describes the same version of the file this state was recorded for.
+    fn is_same_resource(&self, remote: &Self) -> bool {
+        if !self.is_resumable() || self.total != remote.total {
+            return false;
+        }
+        match (&self.etag, &remote.etag) {
+            (Some(ours), Some(theirs)) => ours == theirs,
+            _ => self.last_modified.is_some() && self.last_modified == remote.last_modified,
+        }
+    }
+
+    // Value for the If-Range header; weak ETags are not allowed there, so fall back to Last-Modified.
+    fn if_range(&self) -> Option<&str> {
+        match &self.etag {
+            Some(etag) if !etag.starts_with("W/") => Some(etag),
+            _ => self.last_modified.as_deref(),
+        }
+    }
+
+    // Records [start, end) as written, merging it with adjacent or overlapping ranges.
+    fn mark_completed(&mut self, start: u64, end: u64) {
+        self.completed.push((start, end));
+        self.completed.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.completed.len());
+        for &(start, end) in &self.completed {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.completed = merged;
+    }
+
+    fn completed_len(&self) -> u64 {
+        self.completed.iter().map(|(start, end)| end - start).sum()
+    }
+
+    // Length of the contiguous range completed from the beginning of the file.
+    fn prefix_len(&self) -> u64 {
+        match self.completed.first() {
+            Some(&(0, end)) => end,
+            _ => 0,
+        }
+    }
 
-        // Calculate transfer speed if accumulated time exceeds granularity.
-        if self.accumulated_time >= self.granularity as u128 {
-            self.transfer_speed =
-                (self.accumulated_chunk_len as u128 / self.accumulated_time * 1024) as u64;
-            self.accumulated_chunk_len = 0;
-            self.accumulated_time = 0;
+    // Splits every hole between completed ranges into inclusive ranges of at most `part_size` bytes.
+    fn missing_parts(&self, part_size: u64) -> Vec<(u64, u64)> {
+        let mut parts = Vec::new();
+        let mut cursor = 0;
+        let boundaries = self
+            .completed
+            .iter()
+            .copied()
+            .chain(std::iter::once((self.total, self.total)));
+        for (start, end) in boundaries {
+            let hole_end = start.min(self.total);
+            while cursor < hole_end {
+                let part_end = min(cursor + part_size, hole_end);
+                parts.push((cursor, part_end - 1));
+                cursor = part_end;
+            }
+            cursor = cursor.max(end);
         }
+        parts
+    }
+}
 
-        // Reset the start time for the next period.
-        self.start_time = now;
+pub(crate) fn download_temp_path(file_path: &str) -> String {
+    format!("{file_path}{TEMP_SUFFIX}")
+}
+
+// Makes the downloaded bytes durable and moves them to their final path in one step.
+pub(crate) async fn commit_download(temp_path: &str, file_path: &str) -> Result<()> {
+    // Opened for writing since Windows refuses to flush a read-only handle
+    let file = OpenOptions::new().write(true).open(temp_path).await?;
+    file.sync_all().await?;
+    drop(file);
+    tokio::fs::rename(temp_path, file_path).await?;
+    Ok(())
+}
+
+// Sweeps the leftovers of downloads interrupted in a previous run from the dirs the frontend
+// downloads into: the books dir with a subdir per book, and the top level of the cache dir.
+pub async fn sweep_stale_downloads<R: Runtime>(app: &AppHandle<R>) {
+    let started = SystemTime::now();
+    let books_dir = app
+        .path()
+        .app_data_dir()
+        .map(|dir| dir.join("Readest").join("Books"));
+    let cache_dir = app.path().app_cache_dir();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        if let Ok(dir) = books_dir {
+            sweep_dir(&dir, true, started);
+        }
+        if let Ok(dir) = cache_dir {
+            sweep_dir(&dir, false, started);
+        }
+    })
+    .await;
+    if let Err(e) = result {
+        log::error!("Failed to sweep stale downloads: {e}");
     }
 }
 
-// Provides a default implementation for TransferStats with a granularity of 500 milliseconds.
-impl Default for TransferStats {
-    fn default() -> Self {
-        Self::start(500) // Default granularity is 500 ms
+// Removes temporary files that cannot be resumed or were abandoned, as well as orphaned sidecars.
+// Files modified after `started` belong to downloads of this run, which have no sidecar until
+// their first checkpoint, and are left alone.
+fn sweep_dir(dir: &Path, recursive: bool, started: SystemTime) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if recursive {
+                sweep_dir(&path, recursive, started);
+            }
+            continue;
+        }
+        let modified = entry.metadata().and_then(|m| m.modified()).ok();
+        if modified.is_some_and(|modified| modified >= started) {
+            continue;
+        }
+
+        let name = path.to_string_lossy();
+        if let Some(file_path) = name.strip_suffix(TEMP_SUFFIX) {
+            let sidecar = PartialDownload::sidecar_path(file_path);
+            let abandoned = modified
+                .map(|modified| modified.elapsed().unwrap_or_default() > STALE_DOWNLOAD_AGE)
+                .unwrap_or(true);
+            if !sidecar.exists() || abandoned {
+                log::info!("Removing stale download {name}");
+                let _ = std::fs::remove_file(&path);
+                let _ = std::fs::remove_file(&sidecar);
+            }
+        } else if let Some(file_path) = name.strip_suffix(PARTIAL_SUFFIX) {
+            if !Path::new(&download_temp_path(file_path)).exists() {
+                let _ = std::fs::remove_file(&path);
+            }
+        } else if name.ends_with(&format!("{PARTIAL_SUFFIX}.tmp")) {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+// The IntegrityCheck struct hashes the downloaded bytes and compares them with what the caller expects.
+pub(crate) struct IntegrityCheck {
+    sha256: Option<(Sha256, String)>, // Running SHA-256 digest and the expected hex value
+    md5: Option<(Md5, String)>,       // Running MD5 digest and the expected hex value
+    expected_size: Option<u64>,       // Expected size of the file in bytes
+    size: u64,                        // Number of bytes hashed so far
+}
+
+impl IntegrityCheck {
+    pub(crate) fn new(
+        expected_sha256: Option<String>,
+        expected_md5: Option<String>,
+        expected_size: Option<u64>,
+    ) -> Self {
+        Self {
+            sha256: expected_sha256.map(|hex| (Sha256::new(), hex.to_lowercase())),
+            md5: expected_md5.map(|hex| (Md5::new(), hex.to_lowercase())),
+            expected_size,
+            size: 0,
+        }
+    }
+
+    fn has_checksum(&self) -> bool {
+        self.sha256.is_some() || self.md5.is_some()
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        if let Some((hasher, _)) = &mut self.sha256 {
+            hasher.update(data);
+        }
+        if let Some((hasher, _)) = &mut self.md5 {
+            hasher.update(data);
+        }
+        self.size += data.len() as u64;
+    }
+
+    // Feeds the first `len` bytes already on disk, e.g. the part written by an earlier attempt.
+    async fn update_from_file(&mut self, file_path: &str, len: u64) -> Result<()> {
+        if !self.has_checksum() {
+            self.size += len;
+            return Ok(());
+        }
+        let mut file = File::open(file_path).await?.take(len);
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            self.update(&buf[..n]);
+        }
+        Ok(())
+    }
+
+    // Fails early when the announced size already differs from the expected one.
+    fn check_size(&self, size: u64) -> Result<()> {
+        match self.expected_size {
+            Some(expected) if size != 0 && size != expected => Err(Error::SizeMismatch {
+                expected,
+                actual: size,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn verify(self) -> Result<()> {
+        self.check_size(self.size)?;
+        if let Some((hasher, expected)) = self.sha256 {
+            let actual = format!("{:x}", hasher.finalize());
+            if actual != expected {
+                return Err(Error::ChecksumMismatch {
+                    algorithm: "SHA-256",
+                    expected,
+                    actual,
+                });
+            }
+        }
+        if let Some((hasher, expected)) = self.md5 {
+            let actual = format!("{:x}", hasher.finalize());
+            if actual != expected {
+                return Err(Error::ChecksumMismatch {
+                    algorithm: "MD5",
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(())
     }
 }
 
@@ -77,11 +442,46 @@ pub enum Error {
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
     Request(#[from] reqwest::Error),
     #[error("{0}")]
     ContentLength(String),
     #[error("request failed with status code {0}: {1}")]
     HttpErrorCode(u16, String),
+    #[error("failed to download byte ranges {}", format_ranges(.0))]
+    PartsFailed(Vec<(u64, u64)>),
+    #[error("transfer was cancelled")]
+    Cancelled,
+    #[error("multipart upload failed: {0}")]
+    MultipartUpload(String),
+    #[error("{algorithm} checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        algorithm: &'static str,
+        expected: String,
+        actual: String,
+    },
+    #[error("size mismatch: expected {expected} bytes, got {actual}")]
+    SizeMismatch { expected: u64, actual: u64 },
+}
+
+impl Error {
+    // Network hiccups, short reads and server-side errors are worth another attempt.
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::Request(_) | Error::ContentLength(_) => true,
+            Error::HttpErrorCode(code, _) => *code == 408 || *code == 429 || *code >= 500,
+            _ => false,
+        }
+    }
+}
+
+fn format_ranges(ranges: &[(u64, u64)]) -> String {
+    ranges
+        .iter()
+        .map(|(start, end)| format!("{start}-{end}"))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl Serialize for Error {
@@ -96,29 +496,138 @@ impl Serialize for Error {
 #[derive(Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProgressPayload {
-    progress: u64,
-    total: u64,
-    transfer_speed: u64,
+    pub progress: u64,
+    pub total: u64,
+    pub transfer_speed: u64, // Smoothed speed in bytes per second, 0 until the first sample
+    pub eta_secs: Option<u64>, // Estimated seconds left, None while the total or speed is unknown
+    pub elapsed_secs: u64,   // Seconds since the transfer (or this resumed attempt) started
+}
+
+// Receives the progress of a transfer, either forwarded to a frontend channel or aggregated by the queue.
+pub type ProgressCallback = Arc<dyn Fn(ProgressPayload) + Send + Sync>;
+
+// Computes the Authorization header of every request of a download, for schemes such as Digest
+// whose headers can't be sent twice.
+pub(crate) type Authorize = Arc<dyn Fn(&Method) -> String + Send + Sync>;
+
+fn authorized(
+    request: RequestBuilder,
+    authorize: Option<&Authorize>,
+    method: &Method,
+) -> RequestBuilder {
+    match authorize {
+        Some(authorize) => request.header(AUTHORIZATION, authorize(method)),
+        None => request,
+    }
+}
+
+pub(crate) fn channel_progress(channel: Channel<ProgressPayload>) -> ProgressCallback {
+    Arc::new(move |payload| {
+        let _ = channel.send(payload);
+    })
 }
 
 #[command]
+#[allow(clippy::too_many_arguments)]
 pub async fn download_file(
+    registry: State<'_, TransferRegistry>,
+    http: State<'_, HttpClient>,
+    id: u32,
     url: &str,
     file_path: &str,
     headers: HashMap<String, String>,
     body: Option<String>,
+    expected_sha256: Option<String>,
+    expected_md5: Option<String>,
+    expected_size: Option<u64>,
+    config: Option<TransferOverrides>,
     on_progress: Channel<ProgressPayload>,
+) -> Result<()> {
+    let transfer = registry.register(id, config);
+    let integrity = IntegrityCheck::new(expected_sha256, expected_md5, expected_size);
+    run_download(
+        &transfer,
+        http.client(),
+        url,
+        file_path,
+        headers,
+        None,
+        body,
+        integrity,
+        channel_progress(on_progress),
+    )
+    .await
+}
+
+// Runs a registered download to completion, cleaning up after cancellation or a failed integrity check.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn run_download(
+    transfer: &TransferHandle,
+    client: reqwest::Client,
+    url: &str,
+    file_path: &str,
+    headers: HashMap<String, String>,
+    authorize: Option<Authorize>,
+    body: Option<String>,
+    integrity: IntegrityCheck,
+    on_progress: ProgressCallback,
+) -> Result<()> {
+    let download = download(
+        transfer,
+        client,
+        url,
+        file_path,
+        headers,
+        authorize,
+        body,
+        integrity,
+        on_progress,
+    );
+    match transfer.until_cancelled(download).await {
+        Some(Err(e @ (Error::ChecksumMismatch { .. } | Error::SizeMismatch { .. }))) => {
+            // Never leave a corrupt book behind, nor a resume state that would reproduce it
+            let _ = tokio::fs::remove_file(download_temp_path(file_path)).await;
+            PartialDownload::discard(file_path).await;
+            Err(e)
+        }
+        Some(result) => result,
+        None => {
+            if transfer.cancel_policy() == CancelPolicy::Discard {
+                let _ = tokio::fs::remove_file(download_temp_path(file_path)).await;
+                PartialDownload::discard(file_path).await;
+            }
+            Err(Error::Cancelled)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn download(
+    transfer: &TransferHandle,
+    client: reqwest::Client,
+    url: &str,
+    file_path: &str,
+    headers: HashMap<String, String>,
+    authorize: Option<Authorize>,
+    body: Option<String>,
+    mut integrity: IntegrityCheck,
+    on_progress: ProgressCallback,
 ) -> Result<()> {
     use futures::stream::{self, StreamExt};
-    use std::cmp::min;
-    use tokio::io::AsyncSeekExt;
 
-    const PART_SIZE: u64 = 1024 * 1024;
+    let config = transfer.config();
 
-    let client = reqwest::Client::new();
+    // Everything is written next to the target first, so a crash never leaves a truncated book
+    let temp_path = download_temp_path(file_path);
 
     // Check if server supports range requests
-    let range_resp = client.get(url).header("Range", "bytes=0-0").send().await?;
+    let mut range_req = client.get(url).header(RANGE, "bytes=0-0");
+    for (key, value) in headers.iter() {
+        range_req = range_req.header(key, value);
+    }
+    let range_resp = authorized(range_req, authorize.as_ref(), &Method::GET)
+        .send()
+        .await?;
     let accept_ranges = range_resp
         .headers()
         .get("accept-ranges")
@@ -134,17 +643,44 @@ pub async fn download_file(
         .unwrap_or(0);
 
     if !accept_ranges || total == 0 {
-        // Fallback to original single-threaded logic
-        let mut request = if let Some(body) = body {
-            client.post(url).body(body)
+        // Fallback to single-threaded logic, resuming after the contiguous prefix written before
+        let file_len = tokio::fs::metadata(&temp_path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+        let partial = if body.is_none() {
+            PartialDownload::load(file_path).await.filter(|state| {
+                state.is_resumable() && state.prefix_len() > 0 && state.prefix_len() <= file_len
+            })
         } else {
-            client.get(url)
+            None
         };
 
+        let method = if body.is_some() {
+            Method::POST
+        } else {
+            Method::GET
+        };
+        let mut request = authorized(
+            client.request(method.clone(), url),
+            authorize.as_ref(),
+            &method,
+        );
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+
         for (key, value) in headers.iter() {
             request = request.header(key, value);
         }
 
+        if let Some(partial) = &partial {
+            request = request.header(RANGE, format!("bytes={}-", partial.prefix_len()));
+            if let Some(validator) = partial.if_range() {
+                request = request.header(IF_RANGE, validator);
+            }
+        }
+
         let response = request.send().await?;
         if !response.status().is_success() {
             return Err(Error::HttpErrorCode(
@@ -153,101 +689,344 @@ pub async fn download_file(
             ));
         }
 
-        let total = response.content_length().unwrap_or(0);
-        let mut file = BufWriter::new(File::create(file_path).await?);
+        // A 200 instead of a 206 means the file changed on the server and has to be fetched again
+        let offset = match &partial {
+            Some(partial) if response.status() == reqwest::StatusCode::PARTIAL_CONTENT => {
+                partial.prefix_len()
+            }
+            _ => 0,
+        };
+        if offset == 0 {
+            PartialDownload::discard(file_path).await;
+        }
+
+        let total = response
+            .content_length()
+            .map(|len| offset + len)
+            .unwrap_or(0);
+        integrity.check_size(total)?;
+        let mut state = PartialDownload::from_headers(total, response.headers());
+        if offset > 0 {
+            state.mark_completed(0, offset);
+            integrity.update_from_file(&temp_path, offset).await?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&temp_path)
+            .await?;
+        file.set_len(offset).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        let mut file = BufWriter::new(file);
         let mut stream = response.bytes_stream();
 
-        let mut stats = TransferStats::default();
+        let stats = TransferStats::new(total, offset);
+        let mut unsaved = 0;
         while let Some(chunk) = stream.try_next().await? {
+            transfer.wait_if_paused().await;
+            transfer.throttle(chunk.len()).await;
             file.write_all(&chunk).await?;
+            integrity.update(&chunk);
             stats.record_chunk_transfer(chunk.len());
-            let _ = on_progress.send(ProgressPayload {
-                progress: stats.total_transferred,
-                total,
-                transfer_speed: stats.transfer_speed,
-            });
+            let payload = stats.payload();
+            let progress = payload.progress;
+            on_progress(payload);
+
+            unsaved += chunk.len() as u64;
+            if state.is_resumable() && unsaved >= CHECKPOINT_SIZE {
+                file.flush().await?;
+                state.mark_completed(0, progress);
+                state.save(file_path).await?;
+                unsaved = 0;
+            }
         }
         file.flush().await?;
+        drop(file);
+        integrity.verify()?;
+        commit_download(&temp_path, file_path).await?;
+        PartialDownload::discard(file_path).await;
         return Ok(());
     }
 
-    // Multi-part download with range access
-    let part_count = total.div_ceil(PART_SIZE);
-    let file = File::create(file_path).await?;
+    // Multi-part download with range access, skipping the parts completed by a previous attempt
+    integrity.check_size(total)?;
+    let remote = PartialDownload::from_headers(total, range_resp.headers());
+    let file_len = tokio::fs::metadata(&temp_path)
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let state = match PartialDownload::load(file_path).await {
+        Some(state) if file_len == total && state.is_same_resource(&remote) => state,
+        _ => remote,
+    };
+
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&temp_path)
+        .await?;
     file.set_len(total).await?;
 
+    let parts = state.missing_parts(config.part_size);
+    let if_range = state.if_range().map(str::to_string);
+    let stats = Arc::new(TransferStats::new(total, state.completed_len()));
+
     let file = Arc::new(tokio::sync::Mutex::new(file));
-    let progress = Arc::new(tokio::sync::Mutex::new(TransferStats::default()));
+    let state = Arc::new(tokio::sync::Mutex::new(state));
+    let failed = Arc::new(tokio::sync::Mutex::new(Vec::new()));
 
-    stream::iter(0..part_count)
-        .for_each_concurrent(8, |i| {
+    stream::iter(parts)
+        .for_each_concurrent(config.concurrency, |(start, end)| {
             let client = client.clone();
             let file = Arc::clone(&file);
-            let progress = Arc::clone(&progress);
+            let stats = Arc::clone(&stats);
+            let state = Arc::clone(&state);
+            let failed = Arc::clone(&failed);
             let headers = headers.clone();
+            let authorize = authorize.clone();
             let url = url.to_string();
-            let on_progress = on_progress.clone();
+            let file_path = file_path.to_string();
+            let if_range = if_range.clone();
+            let on_progress = Arc::clone(&on_progress);
+            let transfer = transfer.clone();
 
             async move {
-                let start = i * PART_SIZE;
-                let end = min(start + PART_SIZE - 1, total - 1);
-                let range_header = format!("bytes={start}-{end}");
-
-                let mut req = client.get(&url).header("Range", range_header);
-                for (key, value) in headers {
-                    req = req.header(key, value);
-                }
-
-                let resp = match req.send().await {
-                    Ok(r) => r,
-                    Err(_) => return,
-                };
-
-                if !resp.status().is_success()
-                    && resp.status() != reqwest::StatusCode::PARTIAL_CONTENT
+                transfer.wait_if_paused().await;
+                let bytes = match fetch_part(
+                    &transfer,
+                    &stats,
+                    &client,
+                    &url,
+                    &headers,
+                    authorize.as_ref(),
+                    if_range.as_deref(),
+                    start,
+                    end,
+                )
+                .await
                 {
-                    return;
-                }
-
-                let bytes = match resp.bytes().await {
-                    Ok(b) => b,
-                    Err(_) => return,
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        log::error!("Failed to download range {start}-{end}: {e}");
+                        failed.lock().await.push((start, end));
+                        return;
+                    }
                 };
 
-                {
+                let written: std::io::Result<()> = async {
                     let mut f = file.lock().await;
-                    f.seek(std::io::SeekFrom::Start(start)).await.unwrap();
-                    f.write_all(&bytes).await.unwrap();
+                    f.seek(std::io::SeekFrom::Start(start)).await?;
+                    f.write_all(&bytes).await?;
+                    f.flush().await
+                }
+                .await;
+                if let Err(e) = written {
+                    log::error!("Failed to write range {start}-{end} to {file_path}: {e}");
+                    failed.lock().await.push((start, end));
+                    return;
                 }
 
                 {
-                    let mut stat = progress.lock().await;
-                    stat.record_chunk_transfer(bytes.len());
-                    let _ = on_progress.send(ProgressPayload {
-                        progress: stat.total_transferred,
-                        total,
-                        transfer_speed: stat.transfer_speed,
-                    });
+                    let mut state = state.lock().await;
+                    state.mark_completed(start, start + bytes.len() as u64);
+                    if state.is_resumable() {
+                        let _ = state.save(&file_path).await;
+                    }
                 }
+
+                stats.record_completed(bytes.len());
+                on_progress(stats.payload());
             }
         })
         .await;
 
+    drop(file);
+    let mut failed = std::mem::take(&mut *failed.lock().await);
+    if !failed.is_empty() {
+        // Keep the holes around only when the next attempt can safely fill them in
+        if !state.lock().await.is_resumable() {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+        }
+        failed.sort_unstable();
+        return Err(Error::PartsFailed(failed));
+    }
+
+    // Parts arrive out of order, so the checksums are computed once the file is complete
+    integrity.update_from_file(&temp_path, total).await?;
+    integrity.verify()?;
+    commit_download(&temp_path, file_path).await?;
+    PartialDownload::discard(file_path).await;
+
     Ok(())
 }
 
+// Runs `attempt` until it succeeds, fails with a permanent error or runs out of attempts,
+// waiting with exponential backoff in between.
+pub(crate) async fn with_retry<T, F, Fut>(what: &str, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let mut delay = RETRY_BASE_DELAY;
+    let mut attempts = 1;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempts < PART_ATTEMPTS && e.is_transient() => {
+                log::warn!("Retrying {what} (attempt {attempts}) after error: {e}");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempts += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Fetches the inclusive byte range `start..=end`, retrying transient failures.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_part(
+    transfer: &TransferHandle,
+    stats: &TransferStats,
+    client: &reqwest::Client,
+    url: &str,
+    headers: &HashMap<String, String>,
+    authorize: Option<&Authorize>,
+    if_range: Option<&str>,
+    start: u64,
+    end: u64,
+) -> Result<Bytes> {
+    with_retry(&format!("range {start}-{end}"), || {
+        try_fetch_part(
+            transfer, stats, client, url, headers, authorize, if_range, start, end,
+        )
+    })
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn try_fetch_part(
+    transfer: &TransferHandle,
+    stats: &TransferStats,
+    client: &reqwest::Client,
+    url: &str,
+    headers: &HashMap<String, String>,
+    authorize: Option<&Authorize>,
+    if_range: Option<&str>,
+    start: u64,
+    end: u64,
+) -> Result<Bytes> {
+    // Authorized on every attempt, since a Digest header is only valid once
+    let mut req = authorized(client.get(url), authorize, &Method::GET)
+        .header(RANGE, format!("bytes={start}-{end}"));
+    for (key, value) in headers {
+        req = req.header(key, value);
+    }
+    if let Some(validator) = if_range {
+        req = req.header(IF_RANGE, validator);
+    }
+
+    let resp = req.send().await?;
+    let status = resp.status();
+    if status == reqwest::StatusCode::OK {
+        // The whole (possibly changed) file was sent back, which must never land at `start`
+        return Err(Error::HttpErrorCode(
+            status.as_u16(),
+            "server ignored the range request".into(),
+        ));
+    }
+    if status != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(Error::HttpErrorCode(
+            status.as_u16(),
+            resp.text().await.unwrap_or_default(),
+        ));
+    }
+
+    // Read the body chunk by chunk so that pausing and bandwidth limits take effect within a part
+    let expected = end - start + 1;
+    let mut bytes = BytesMut::with_capacity(expected as usize);
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.try_next().await? {
+        transfer.wait_if_paused().await;
+        transfer.throttle(chunk.len()).await;
+        stats.record_received(chunk.len());
+        bytes.extend_from_slice(&chunk);
+    }
+    if bytes.len() as u64 != expected {
+        return Err(Error::ContentLength(format!(
+            "expected {expected} bytes for range {start}-{end}, received {}",
+            bytes.len()
+        )));
+    }
+    Ok(bytes.freeze())
+}
+
 #[command]
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_file(
+    registry: State<'_, TransferRegistry>,
+    http: State<'_, HttpClient>,
+    id: u32,
     url: &str,
     file_path: &str,
     method: &str,
     headers: HashMap<String, String>,
+    config: Option<TransferOverrides>,
     on_progress: Channel<ProgressPayload>,
+) -> Result<String> {
+    let transfer = registry.register(id, config);
+    run_upload(
+        &transfer,
+        http.client(),
+        url,
+        file_path,
+        method,
+        headers,
+        channel_progress(on_progress),
+    )
+    .await
+}
+
+// Runs a registered upload to completion unless it is cancelled first.
+pub(crate) async fn run_upload(
+    transfer: &TransferHandle,
+    client: reqwest::Client,
+    url: &str,
+    file_path: &str,
+    method: &str,
+    headers: HashMap<String, String>,
+    on_progress: ProgressCallback,
+) -> Result<String> {
+    let upload = upload(
+        transfer,
+        client,
+        url,
+        file_path,
+        method,
+        headers,
+        on_progress,
+    );
+    transfer
+        .until_cancelled(upload)
+        .await
+        .unwrap_or(Err(Error::Cancelled))
+}
+
+async fn upload(
+    transfer: &TransferHandle,
+    client: reqwest::Client,
+    url: &str,
+    file_path: &str,
+    method: &str,
+    headers: HashMap<String, String>,
+    on_progress: ProgressCallback,
 ) -> Result<String> {
     let file = File::open(file_path).await?;
     let file_len = file.metadata().await.unwrap().len();
 
-    let client = reqwest::Client::new();
     let mut request = match method.to_uppercase().as_str() {
         "POST" => client.post(url),
         "PUT" => client.put(url),
@@ -256,7 +1035,7 @@ pub async fn upload_file(
 
     request = request
         .header(reqwest::header::CONTENT_LENGTH, file_len)
-        .body(file_to_body(on_progress.clone(), file, file_len));
+        .body(file_to_body(on_progress, transfer.clone(), file, file_len));
 
     for (key, value) in headers {
         request = request.header(&key, value);
@@ -273,19 +1052,88 @@ pub async fn upload_file(
     }
 }
 
-fn file_to_body(channel: Channel<ProgressPayload>, file: File, file_len: u64) -> reqwest::Body {
-    let stream = FramedRead::new(file, BytesCodec::new()).map_ok(|r| r.freeze());
+fn file_to_body(
+    on_progress: ProgressCallback,
+    transfer: TransferHandle,
+    file: File,
+    file_len: u64,
+) -> reqwest::Body {
+    use futures::stream::StreamExt;
 
-    let mut stats = TransferStats::default();
+    // Hold back the next chunk while the transfer is paused or over its bandwidth limit
+    let stream = FramedRead::new(file, BytesCodec::new())
+        .map_ok(|r| r.freeze())
+        .then(move |chunk| {
+            let transfer = transfer.clone();
+            async move {
+                transfer.wait_if_paused().await;
+                if let Ok(chunk) = &chunk {
+                    transfer.throttle(chunk.len()).await;
+                }
+                chunk
+            }
+        });
+    let stream = Box::pin(stream);
+
+    let stats = TransferStats::new(file_len, 0);
     reqwest::Body::wrap_stream(ReadProgressStream::new(
         stream,
         Box::new(move |progress_chunk, _progress_total| {
             stats.record_chunk_transfer(progress_chunk as usize);
-            let _ = channel.send(ProgressPayload {
-                progress: stats.total_transferred,
-                total: file_len,
-                transfer_speed: stats.transfer_speed,
-            });
+            on_progress(stats.payload());
         }),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(total: u64, completed: &[(u64, u64)]) -> PartialDownload {
+        let mut state = PartialDownload::from_headers(total, &HeaderMap::new());
+        for &(start, end) in completed {
+            state.mark_completed(start, end);
+        }
+        state
+    }
+
+    #[test]
+    fn mark_completed_merges_ranges() {
+        let state = partial(100, &[(50, 60), (0, 10), (10, 20), (55, 70), (80, 90)]);
+        assert_eq!(state.completed, vec![(0, 20), (50, 70), (80, 90)]);
+        assert_eq!(state.completed_len(), 50);
+        assert_eq!(state.prefix_len(), 20);
+    }
+
+    #[test]
+    fn mark_completed_keeps_contained_ranges() {
+        let state = partial(100, &[(0, 50), (10, 20)]);
+        assert_eq!(state.completed, vec![(0, 50)]);
+    }
+
+    #[test]
+    fn prefix_len_needs_range_from_start() {
+        assert_eq!(partial(100, &[(10, 20)]).prefix_len(), 0);
+    }
+
+    #[test]
+    fn missing_parts_of_empty_download() {
+        let state = partial(25, &[]);
+        assert_eq!(state.missing_parts(10), vec![(0, 9), (10, 19), (20, 24)]);
+    }
+
+    #[test]
+    fn missing_parts_fill_holes() {
+        let state = partial(100, &[(0, 20), (50, 70), (80, 90)]);
+        assert_eq!(
+            state.missing_parts(20),
+            vec![(20, 39), (40, 49), (70, 79), (90, 99)]
+        );
+    }
+
+    #[test]
+    fn missing_parts_of_complete_download() {
+        let state = partial(100, &[(0, 100)]);
+        assert!(state.missing_parts(10).is_empty());
+    }
+}
@@ -0,0 +1,636 @@
+//! Background queue of downloads and uploads driven from Rust.
+//!
+//! Jobs are persisted to disk so that pending transfers survive a restart, run a few at a
+//! time in priority order, and report aggregate progress through `transfer-queue-progress`.
+//! Jobs with credentials, in their headers or as a signed URL, are never written to disk and
+//! have to be queued again after a restart.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tauri::{command, AppHandle, Emitter, Manager, State};
+use tokio::sync::Notify;
+
+use crate::http_client::HttpClient;
+use crate::transfer_file::{
+    run_download, run_upload, sweep_stale_downloads, IntegrityCheck, ProgressCallback,
+    ProgressPayload,
+};
+use crate::transfer_registry::TransferRegistry;
+
+const QUEUE_FILE: &str = "transfer-queue.json";
+const DEFAULT_CONCURRENCY: usize = 3;
+const MAX_CONCURRENCY: usize = 16;
+
+// Minimum interval between two aggregate progress events.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+// Headers that carry credentials, in lower case.
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-api-key",
+    "x-amz-security-token",
+];
+
+// Query parameters of pre-signed URLs, such as those of S3, Google Cloud Storage and Azure, in
+// lower case.
+const SIGNED_URL_PARAMS: &[&str] = &[
+    "x-amz-signature",
+    "x-amz-credential",
+    "x-goog-signature",
+    "signature",
+    "sig",
+    "token",
+    "access_token",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TransferKind {
+    #[serde(rename_all = "camelCase")]
+    Download {
+        url: String,
+        file_path: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        body: Option<String>,
+        expected_sha256: Option<String>,
+        expected_md5: Option<String>,
+        expected_size: Option<u64>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Upload {
+        url: String,
+        file_path: String,
+        method: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferJob {
+    pub id: u32,
+    #[serde(default)]
+    pub priority: i32, // Higher runs first, jobs of equal priority run in the order they were queued
+    #[serde(flatten)]
+    pub kind: TransferKind,
+    #[serde(default)]
+    seq: u64, // Enqueue order, assigned by the queue
+}
+
+impl TransferJob {
+    fn has_credentials(&self) -> bool {
+        let (url, headers) = match &self.kind {
+            TransferKind::Download { url, headers, .. } => (url, headers),
+            TransferKind::Upload { url, headers, .. } => (url, headers),
+        };
+        let sensitive_header = headers
+            .keys()
+            .any(|name| SENSITIVE_HEADERS.contains(&name.to_lowercase().as_str()));
+        let signed_url = Url::parse(url).is_ok_and(|url| {
+            url.query_pairs()
+                .any(|(key, _)| SIGNED_URL_PARAMS.contains(&key.to_lowercase().as_str()))
+        });
+        sensitive_header || signed_url
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueueFile {
+    jobs: Vec<TransferJob>,
+    concurrency: Option<usize>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueProgress {
+    pub pending: usize,
+    pub running: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub progress: u64,         // Bytes transferred by the running jobs
+    pub total: u64,            // Total bytes of the running jobs
+    pub transfer_speed: u64,   // Combined speed of the running jobs in bytes per second
+    pub eta_secs: Option<u64>, // Estimated seconds until the running jobs finish
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobResult {
+    pub id: u32,
+    pub response: Option<String>, // Response body of a successful upload
+    pub error: Option<String>,
+}
+
+struct RunningJob {
+    job: TransferJob,
+    progress: Option<ProgressPayload>,
+}
+
+struct QueueState {
+    pending: Vec<TransferJob>,
+    running: HashMap<u32, RunningJob>,
+    completed: usize,
+    failed: usize,
+    concurrency: usize,
+    next_seq: u64,
+    last_progress_event: Option<Instant>,
+    generation: u64, // Incremented for every change to persist
+}
+
+// A serialized queue file waiting to be written.
+struct QueueWrite {
+    generation: u64,
+    data: Vec<u8>,
+}
+
+// The queue file, written to a temp file and renamed into place so that a crash cannot
+// leave it half written.
+struct QueueStore {
+    path: PathBuf,
+    written: Mutex<u64>, // Generation of the last write, as writes may finish out of order
+}
+
+impl QueueStore {
+    fn write(&self, write: QueueWrite) {
+        let mut written = self.written.lock().unwrap();
+        if *written >= write.generation {
+            return;
+        }
+        let temp_path = self.path.with_extension("json.tmp");
+        let result = std::fs::write(&temp_path, write.data)
+            .and_then(|_| std::fs::rename(&temp_path, &self.path));
+        match result {
+            Ok(()) => *written = write.generation,
+            Err(e) => log::error!("Failed to persist transfer queue: {e}"),
+        }
+    }
+}
+
+pub struct TransferQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+    store: Arc<QueueStore>,
+}
+
+impl TransferQueue {
+    // Restores the jobs of the previous run; the ones that were running are started again.
+    fn load(path: PathBuf) -> Self {
+        let mut file: QueueFile = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        // Written by older versions, which persisted credentials
+        let len = file.jobs.len();
+        file.jobs.retain(|job| !job.has_credentials());
+        if file.jobs.len() < len {
+            log::warn!(
+                "Dropped {} queued transfers with credentials",
+                len - file.jobs.len()
+            );
+        }
+        let next_seq = file.jobs.iter().map(|job| job.seq + 1).max().unwrap_or(0);
+        Self {
+            state: Mutex::new(QueueState {
+                pending: file.jobs,
+                running: HashMap::new(),
+                completed: 0,
+                failed: 0,
+                concurrency: file
+                    .concurrency
+                    .unwrap_or(DEFAULT_CONCURRENCY)
+                    .clamp(1, MAX_CONCURRENCY),
+                next_seq,
+                last_progress_event: None,
+                generation: 0,
+            }),
+            notify: Notify::new(),
+            store: Arc::new(QueueStore {
+                path,
+                written: Mutex::new(0),
+            }),
+        }
+    }
+
+    // Serializes the queue while the state is locked; the file is written with persist.
+    fn serialize(state: &mut QueueState) -> Option<QueueWrite> {
+        state.generation += 1;
+        let file = QueueFile {
+            jobs: state
+                .running
+                .values()
+                .map(|running| &running.job)
+                .chain(state.pending.iter())
+                .filter(|job| !job.has_credentials())
+                .cloned()
+                .collect(),
+            concurrency: Some(state.concurrency),
+        };
+        match serde_json::to_vec(&file) {
+            Ok(data) => Some(QueueWrite {
+                generation: state.generation,
+                data,
+            }),
+            Err(e) => {
+                log::error!("Failed to serialize transfer queue: {e}");
+                None
+            }
+        }
+    }
+
+    // Writes the queue file on a blocking thread, after the state has been unlocked.
+    fn persist(&self, write: Option<QueueWrite>) {
+        let Some(write) = write else {
+            return;
+        };
+        let store = self.store.clone();
+        tauri::async_runtime::spawn_blocking(move || store.write(write));
+    }
+
+    // Takes the most urgent pending job if there is a free slot for it.
+    fn next_job(&self) -> Option<TransferJob> {
+        let mut state = self.state.lock().unwrap();
+        if state.running.len() >= state.concurrency {
+            return None;
+        }
+        let index = state
+            .pending
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, job)| (job.priority, std::cmp::Reverse(job.seq)))
+            .map(|(index, _)| index)?;
+        let job = state.pending.remove(index);
+        state.running.insert(
+            job.id,
+            RunningJob {
+                job: job.clone(),
+                progress: None,
+            },
+        );
+        Some(job)
+    }
+
+    fn snapshot(state: &QueueState) -> QueueProgress {
+        let (progress, total, transfer_speed) = state
+            .running
+            .values()
+            .filter_map(|running| running.progress.as_ref())
+            .fold((0, 0, 0), |(progress, total, speed), payload| {
+                (
+                    progress + payload.progress,
+                    total + payload.total,
+                    speed + payload.transfer_speed,
+                )
+            });
+        // The slowest job decides when the running ones are all done
+        let eta_secs = state
+            .running
+            .values()
+            .map(|running| {
+                running
+                    .progress
+                    .as_ref()
+                    .and_then(|payload| payload.eta_secs)
+            })
+            .try_fold(0, |eta, job_eta| job_eta.map(|job_eta| eta.max(job_eta)))
+            .filter(|_| !state.running.is_empty());
+        QueueProgress {
+            pending: state.pending.len(),
+            running: state.running.len(),
+            completed: state.completed,
+            failed: state.failed,
+            progress,
+            total,
+            transfer_speed,
+            eta_secs,
+        }
+    }
+
+    fn record_progress(&self, app: &AppHandle, id: u32, payload: ProgressPayload) {
+        let snapshot = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(running) = state.running.get_mut(&id) {
+                running.progress = Some(payload);
+            }
+            if matches!(state.last_progress_event, Some(last) if last.elapsed() < PROGRESS_INTERVAL)
+            {
+                return;
+            }
+            state.last_progress_event = Some(Instant::now());
+            Self::snapshot(&state)
+        };
+        let _ = app.emit("transfer-queue-progress", snapshot);
+    }
+
+    fn finish(&self, app: &AppHandle, result: JobResult) {
+        let (write, snapshot) = {
+            let mut state = self.state.lock().unwrap();
+            state.running.remove(&result.id);
+            if result.error.is_some() {
+                state.failed += 1;
+            } else {
+                state.completed += 1;
+            }
+            (Self::serialize(&mut state), Self::snapshot(&state))
+        };
+        self.persist(write);
+        let _ = app.emit("transfer-queue-job", result);
+        let _ = app.emit("transfer-queue-progress", snapshot);
+        self.notify.notify_one();
+    }
+
+    fn enqueue(&self, mut job: TransferJob) -> bool {
+        let write = {
+            let mut state = self.state.lock().unwrap();
+            if state.running.contains_key(&job.id) {
+                return false;
+            }
+            job.seq = state.next_seq;
+            state.next_seq += 1;
+            state.pending.retain(|pending| pending.id != job.id);
+            state.pending.push(job);
+            Self::serialize(&mut state)
+        };
+        self.persist(write);
+        self.notify.notify_one();
+        true
+    }
+}
+
+pub fn init(app: &AppHandle) -> tauri::Result<()> {
+    let dir = app.path().app_data_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    app.manage(TransferQueue::load(dir.join(QUEUE_FILE)));
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        // Persisted jobs may resume temp files that the sweep would otherwise remove
+        sweep_stale_downloads(&app).await;
+        let queue = app.state::<TransferQueue>();
+        loop {
+            match queue.next_job() {
+                Some(job) => {
+                    let app = app.clone();
+                    tauri::async_runtime::spawn(async move {
+                        let result = run_job(&app, job).await;
+                        app.state::<TransferQueue>().finish(&app, result);
+                    });
+                }
+                None => queue.notify.notified().await,
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn run_job(app: &AppHandle, job: TransferJob) -> JobResult {
+    let registry = app.state::<TransferRegistry>();
+    let transfer = registry.register(job.id, None);
+    let client = app.state::<HttpClient>().client();
+    let on_progress: ProgressCallback = {
+        let app = app.clone();
+        let id = job.id;
+        Arc::new(move |payload| {
+            app.state::<TransferQueue>()
+                .record_progress(&app, id, payload)
+        })
+    };
+
+    let result = match job.kind {
+        TransferKind::Download {
+            url,
+            file_path,
+            headers,
+            body,
+            expected_sha256,
+            expected_md5,
+            expected_size,
+        } => {
+            let integrity = IntegrityCheck::new(expected_sha256, expected_md5, expected_size);
+            run_download(
+                &transfer,
+                client,
+                &url,
+                &file_path,
+                headers,
+                None,
+                body,
+                integrity,
+                on_progress,
+            )
+            .await
+            .map(|_| None)
+        }
+        TransferKind::Upload {
+            url,
+            file_path,
+            method,
+            headers,
+        } => run_upload(
+            &transfer,
+            client,
+            &url,
+            &file_path,
+            &method,
+            headers,
+            on_progress,
+        )
+        .await
+        .map(Some),
+    };
+
+    match result {
+        Ok(response) => JobResult {
+            id: job.id,
+            response,
+            error: None,
+        },
+        Err(e) => {
+            log::error!("Queued transfer {} failed: {e}", job.id);
+            JobResult {
+                id: job.id,
+                response: None,
+                error: Some(e.to_string()),
+            }
+        }
+    }
+}
+
+// Queues a job, replacing a pending one with the same id. Returns false if a job with the id
+// is already running, since its transfer is registered under that id.
+#[command]
+pub fn enqueue_transfer(queue: State<'_, TransferQueue>, job: TransferJob) -> bool {
+    queue.enqueue(job)
+}
+
+// Changes the priority of a pending job, e.g. to move the book being opened to the front.
+#[command]
+pub fn prioritize_transfer(queue: State<'_, TransferQueue>, id: u32, priority: i32) -> bool {
+    let write = {
+        let mut state = queue.state.lock().unwrap();
+        let Some(job) = state.pending.iter_mut().find(|job| job.id == id) else {
+            return false;
+        };
+        job.priority = priority;
+        TransferQueue::serialize(&mut state)
+    };
+    queue.persist(write);
+    true
+}
+
+// Drops a job that has not started yet; running jobs are stopped with cancel_transfer.
+#[command]
+pub fn dequeue_transfer(queue: State<'_, TransferQueue>, id: u32) -> bool {
+    let write = {
+        let mut state = queue.state.lock().unwrap();
+        let len = state.pending.len();
+        state.pending.retain(|job| job.id != id);
+        if state.pending.len() == len {
+            return false;
+        }
+        TransferQueue::serialize(&mut state)
+    };
+    queue.persist(write);
+    true
+}
+
+#[command]
+pub fn get_transfer_queue(queue: State<'_, TransferQueue>) -> QueueProgress {
+    TransferQueue::snapshot(&queue.state.lock().unwrap())
+}
+
+#[command]
+pub fn set_transfer_queue_concurrency(queue: State<'_, TransferQueue>, concurrency: usize) {
+    let write = {
+        let mut state = queue.state.lock().unwrap();
+        state.concurrency = concurrency.clamp(1, MAX_CONCURRENCY);
+        TransferQueue::serialize(&mut state)
+    };
+    queue.persist(write);
+    queue.notify.notify_one();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_queue(name: &str) -> (TransferQueue, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "readest-{name}-{}-{QUEUE_FILE}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        (TransferQueue::load(path.clone()), path)
+    }
+
+    fn download(id: u32, priority: i32, url: &str, headers: &[(&str, &str)]) -> TransferJob {
+        TransferJob {
+            id,
+            priority,
+            kind: TransferKind::Download {
+                url: url.to_string(),
+                file_path: format!("/books/{id}.epub"),
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                body: None,
+                expected_sha256: None,
+                expected_md5: None,
+                expected_size: None,
+            },
+            seq: 0,
+        }
+    }
+
+    fn job(id: u32, priority: i32) -> TransferJob {
+        download(id, priority, "https://example.com/book.epub", &[])
+    }
+
+    fn run_order(queue: &TransferQueue) -> Vec<u32> {
+        queue.state.lock().unwrap().concurrency = MAX_CONCURRENCY;
+        std::iter::from_fn(|| queue.next_job().map(|job| job.id)).collect()
+    }
+
+    #[test]
+    fn runs_jobs_by_priority_then_in_order() {
+        let (queue, _) = temp_queue("priority");
+        for (id, priority) in [(1, 0), (2, 5), (3, 0), (4, 5), (5, -1)] {
+            assert!(queue.enqueue(job(id, priority)));
+        }
+        assert_eq!(run_order(&queue), [2, 4, 1, 3, 5]);
+    }
+
+    #[test]
+    fn replaces_pending_jobs_but_not_running_ones() {
+        let (queue, _) = temp_queue("running");
+        queue.enqueue(job(1, 0));
+        assert_eq!(queue.next_job().map(|job| job.id), Some(1));
+        assert!(!queue.enqueue(job(1, 0)));
+
+        queue.enqueue(job(2, 0));
+        queue.enqueue(job(3, 0));
+        assert!(queue.enqueue(job(2, 0)));
+        let state = queue.state.lock().unwrap();
+        let pending = state.pending.iter().map(|job| job.id).collect::<Vec<_>>();
+        assert_eq!(pending, [3, 2]);
+    }
+
+    #[test]
+    fn persists_jobs_without_credentials() {
+        let (queue, path) = temp_queue("round-trip");
+        queue.enqueue(job(1, 2));
+        queue.enqueue(job(2, 0));
+        queue.enqueue(download(
+            3,
+            0,
+            "https://example.com/a",
+            &[("Authorization", "x")],
+        ));
+        queue.enqueue(download(
+            4,
+            0,
+            "https://example.com/a?X-Amz-Signature=x",
+            &[],
+        ));
+        queue.next_job();
+        let write = TransferQueue::serialize(&mut queue.state.lock().unwrap()).unwrap();
+        queue.store.write(write);
+
+        let data = std::fs::read_to_string(&path).unwrap();
+        assert!(!data.contains("Authorization") && !data.contains("X-Amz-Signature"));
+        let loaded = TransferQueue::load(path.clone());
+        assert_eq!(run_order(&loaded), [1, 2]);
+        // Jobs queued after the restart still run after the restored ones
+        loaded.enqueue(job(5, 0));
+        loaded.enqueue(job(6, 0));
+        assert_eq!(run_order(&loaded), [5, 6]);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn drops_jobs_with_credentials_on_load() {
+        let (_, path) = temp_queue("legacy");
+        let file = QueueFile {
+            jobs: vec![
+                job(1, 0),
+                download(2, 0, "https://example.com/a", &[("cookie", "session=x")]),
+            ],
+            concurrency: Some(2),
+        };
+        std::fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        let loaded = TransferQueue::load(path.clone());
+        assert_eq!(loaded.state.lock().unwrap().concurrency, 2);
+        assert_eq!(run_order(&loaded), [1]);
+        let _ = std::fs::remove_file(path);
+    }
+}
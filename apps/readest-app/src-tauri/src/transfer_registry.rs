@@ -0,0 +1,203 @@
+//! Keep track of the running file transfers so that they can be paused, resumed or
+//! cancelled from the frontend by the id it passed to `download_file` or `upload_file`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use tauri::{command, State};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::transfer_config::{RateLimiter, TransferConfig, TransferOverrides};
+
+// What happens to the bytes already on disk when a download is cancelled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CancelPolicy {
+    #[default]
+    Discard, // Remove the partial file together with its resume state
+    KeepPartial, // Keep both so that the next download_file call resumes where this one stopped
+}
+
+#[derive(Clone)]
+pub struct TransferHandle {
+    cancel: CancellationToken,
+    paused: Arc<watch::Sender<bool>>,
+    policy: Arc<Mutex<CancelPolicy>>,
+    config: TransferConfig,
+    limiter: Arc<RateLimiter>,
+    global_limiter: Arc<RateLimiter>,
+}
+
+impl TransferHandle {
+    fn new(config: TransferConfig, global_limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            cancel: CancellationToken::new(),
+            paused: Arc::new(watch::channel(false).0),
+            policy: Arc::default(),
+            config,
+            limiter: Arc::new(RateLimiter::new(config.max_bytes_per_sec)),
+            global_limiter,
+        }
+    }
+
+    // Part size and concurrency are fixed for the lifetime of a transfer.
+    pub fn config(&self) -> TransferConfig {
+        self.config
+    }
+
+    // Waits until `bytes` fit into both this transfer's and the global bandwidth limit.
+    pub async fn throttle(&self, bytes: usize) {
+        self.limiter.acquire(bytes).await;
+        self.global_limiter.acquire(bytes).await;
+    }
+
+    // Drives `fut` to completion unless the transfer is cancelled first, in which case the future
+    // is dropped together with all of its in-flight requests and None is returned.
+    pub async fn until_cancelled<F: Future>(&self, fut: F) -> Option<F::Output> {
+        tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => None,
+            output = fut => Some(output),
+        }
+    }
+
+    // Returns immediately unless the transfer is paused, otherwise waits until it is resumed.
+    pub async fn wait_if_paused(&self) {
+        let mut paused = self.paused.subscribe();
+        let _ = paused.wait_for(|paused| !*paused).await;
+    }
+
+    pub fn cancel_policy(&self) -> CancelPolicy {
+        *self.policy.lock().unwrap()
+    }
+}
+
+#[derive(Default)]
+pub struct TransferRegistry {
+    transfers: Mutex<HashMap<u32, TransferHandle>>,
+    config: Mutex<TransferConfig>,
+    global_limiter: Arc<RateLimiter>,
+}
+
+impl TransferRegistry {
+    // Registers a new transfer, which stays controllable until the returned guard is dropped.
+    pub fn register(&self, id: u32, overrides: Option<TransferOverrides>) -> TransferGuard<'_> {
+        let config = overrides
+            .unwrap_or_default()
+            .apply(*self.config.lock().unwrap());
+        let handle = TransferHandle::new(config, Arc::clone(&self.global_limiter));
+        self.transfers.lock().unwrap().insert(id, handle.clone());
+        TransferGuard {
+            registry: self,
+            id,
+            handle,
+        }
+    }
+
+    fn get(&self, id: u32) -> Option<TransferHandle> {
+        self.transfers.lock().unwrap().get(&id).cloned()
+    }
+}
+
+pub struct TransferGuard<'a> {
+    registry: &'a TransferRegistry,
+    id: u32,
+    handle: TransferHandle,
+}
+
+impl Deref for TransferGuard<'_> {
+    type Target = TransferHandle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl Drop for TransferGuard<'_> {
+    fn drop(&mut self) {
+        let mut transfers = self.registry.transfers.lock().unwrap();
+        // The id may have been reused by a newer transfer in the meantime
+        if transfers
+            .get(&self.id)
+            .is_some_and(|handle| Arc::ptr_eq(&handle.paused, &self.handle.paused))
+        {
+            transfers.remove(&self.id);
+        }
+    }
+}
+
+#[command]
+pub fn cancel_transfer(
+    registry: State<'_, TransferRegistry>,
+    id: u32,
+    policy: Option<CancelPolicy>,
+) -> bool {
+    match registry.get(id) {
+        Some(handle) => {
+            *handle.policy.lock().unwrap() = policy.unwrap_or_default();
+            handle.cancel.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+#[command]
+pub fn pause_transfer(registry: State<'_, TransferRegistry>, id: u32) -> bool {
+    match registry.get(id) {
+        Some(handle) => {
+            handle.paused.send_replace(true);
+            true
+        }
+        None => false,
+    }
+}
+
+#[command]
+pub fn resume_transfer(registry: State<'_, TransferRegistry>, id: u32) -> bool {
+    match registry.get(id) {
+        Some(handle) => {
+            handle.paused.send_replace(false);
+            true
+        }
+        None => false,
+    }
+}
+
+#[command]
+pub fn get_transfer_config(registry: State<'_, TransferRegistry>) -> TransferConfig {
+    *registry.config.lock().unwrap()
+}
+
+// Changes the global settings; the bandwidth limit also applies to the transfers already running.
+#[command]
+pub fn set_transfer_config(
+    registry: State<'_, TransferRegistry>,
+    config: TransferConfig,
+) -> TransferConfig {
+    let config = config.sanitized();
+    *registry.config.lock().unwrap() = config;
+    registry.global_limiter.set_rate(config.max_bytes_per_sec);
+    config
+}
+
+#[command]
+pub fn set_transfer_rate_limit(
+    registry: State<'_, TransferRegistry>,
+    id: u32,
+    max_bytes_per_sec: Option<u64>,
+) -> bool {
+    match registry.get(id) {
+        Some(handle) => {
+            handle
+                .limiter
+                .set_rate(max_bytes_per_sec.filter(|rate| *rate > 0));
+            true
+        }
+        None => false,
+    }
+}
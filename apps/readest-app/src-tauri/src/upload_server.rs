@@ -0,0 +1,346 @@
+//! Opt-in web server for sending books to the desktop app from a phone or another computer
+//! on the same network.
+//!
+//! The server serves a minimal upload page at a URL with a random token, shown in the app
+//! together with a QR code. Uploaded books are streamed into the import directory and
+//! announced to the main window with `lan-upload-progress` and `lan-upload-file` events, for
+//! the library to import them.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Query, State as AxumState},
+    http::{header::CONTENT_LENGTH, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use qrcode::{render::svg, QrCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::{command, AppHandle, Emitter, Manager, State};
+use tokio::{fs::File, io::AsyncWriteExt, io::BufWriter};
+
+use crate::book_format::detect_format;
+use crate::embedded_server::{constant_time_eq, local_ip, random_token, EmbeddedServer};
+use crate::transfer_file::{commit_download, download_temp_path};
+
+const UPLOAD_PAGE: &str = include_str!("upload_server.html");
+const MAIN_WINDOW: &str = "main";
+const ACCEPTED_EXTENSIONS: [&str; 6] = ["epub", "mobi", "azw3", "fb2", "cbz", "pdf"];
+const MAX_UPLOAD_SIZE: usize = 2 * 1024 * 1024 * 1024;
+const TOKEN_LENGTH: usize = 24;
+
+// Minimum interval between two progress events of an upload.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadServerOptions {
+    pub port: Option<u16>,          // A free port is picked if not given
+    pub import_dir: Option<String>, // Defaults to Readest/Imports in the app data dir
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadServerInfo {
+    pub url: String, // Upload page, including the access token
+    pub port: u16,
+    pub token: String,
+    pub import_dir: String,
+    pub qr_code: String, // SVG image of the URL
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadProgress {
+    file_name: String,
+    received: u64,
+    total: u64, // Size of the whole request, which may carry several files; 0 if unknown
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadedFile {
+    file_name: String,
+    path: String,
+}
+
+struct ServerState {
+    app: AppHandle,
+    token: String,
+    import_dir: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+impl ServerState {
+    fn check_token(&self, query: &TokenQuery) -> Result<(), Response> {
+        match &query.token {
+            Some(token) if constant_time_eq(token.as_bytes(), self.token.as_bytes()) => Ok(()),
+            _ => Err((StatusCode::UNAUTHORIZED, "Invalid or missing token").into_response()),
+        }
+    }
+}
+
+type SharedState = AxumState<Arc<ServerState>>;
+
+fn router(state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route("/", get(upload_page).post(upload_files))
+        .layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE))
+        .with_state(state)
+}
+
+async fn upload_page(AxumState(state): SharedState, Query(query): Query<TokenQuery>) -> Response {
+    if let Err(response) = state.check_token(&query) {
+        return response;
+    }
+    Html(UPLOAD_PAGE.replace("{{accept}}", &accept_attribute())).into_response()
+}
+
+fn accept_attribute() -> String {
+    ACCEPTED_EXTENSIONS
+        .iter()
+        .map(|ext| format!(".{ext}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+async fn upload_files(
+    AxumState(state): SharedState,
+    Query(query): Query<TokenQuery>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Response {
+    if let Err(response) = state.check_token(&query) {
+        return response;
+    }
+    let total = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    let mut received = 0;
+    let mut files = Vec::new();
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        };
+        let Some(file_name) = field.file_name().and_then(sanitize_file_name) else {
+            continue;
+        };
+        if !is_accepted(&file_name) {
+            return (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("{file_name} is not a supported book format"),
+            )
+                .into_response();
+        }
+
+        let path = match unique_path(&state.import_dir, &file_name).await {
+            Ok(path) => path,
+            Err(e) => return internal_error(&file_name, e),
+        };
+        let file_path = path.to_string_lossy();
+        let temp_path = download_temp_path(&file_path);
+        let result: Result<(), String> = async {
+            let mut file =
+                BufWriter::new(File::create(&temp_path).await.map_err(|e| e.to_string())?);
+            let mut last_event: Option<Instant> = None;
+            while let Some(chunk) = field.chunk().await.map_err(|e| e.to_string())? {
+                file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+                received += chunk.len() as u64;
+                if !matches!(last_event, Some(last) if last.elapsed() < PROGRESS_INTERVAL) {
+                    last_event = Some(Instant::now());
+                    let _ = state.app.emit_to(
+                        MAIN_WINDOW,
+                        "lan-upload-progress",
+                        UploadProgress {
+                            file_name: file_name.clone(),
+                            received,
+                            total,
+                        },
+                    );
+                }
+            }
+            file.flush().await.map_err(|e| e.to_string())?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return internal_error(&file_name, e);
+        }
+
+        // The extension is no proof, so files that are not books are deleted right away
+        let detected = {
+            let (temp_path, path) = (temp_path.clone(), path.clone());
+            tauri::async_runtime::spawn_blocking(move || {
+                detect_format(std::fs::File::open(&temp_path)?, &path)
+            })
+            .await
+        };
+        let committed = match detected {
+            Ok(Ok(_)) => commit_download(&temp_path, &file_path)
+                .await
+                .map_err(|e| e.to_string()),
+            Ok(Err(e)) => {
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                log::warn!("Rejected {file_name} received over the local network: {e}");
+                return (
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    format!("{file_name} was rejected: {e}"),
+                )
+                    .into_response();
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = committed {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return internal_error(&file_name, e);
+        }
+
+        log::info!("Received {file_name} over the local network");
+        crate::open_with::allow_file_in_scopes(&state.app, std::slice::from_ref(&path));
+        let _ = state.app.emit_to(
+            MAIN_WINDOW,
+            "lan-upload-file",
+            UploadedFile {
+                file_name: file_name.clone(),
+                path: file_path.to_string(),
+            },
+        );
+        files.push(file_name);
+    }
+    Json(json!({ "files": files })).into_response()
+}
+
+fn internal_error(file_name: &str, error: impl std::fmt::Display) -> Response {
+    log::error!("Failed to receive {file_name}: {error}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to save {file_name}"),
+    )
+        .into_response()
+}
+
+// Keeps only the last path component, so that an upload can never escape the import directory.
+fn sanitize_file_name(name: &str) -> Option<String> {
+    let name = name.rsplit(|c| c == '/' || c == '\\').next()?.trim();
+    let name = name
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+        .collect::<String>();
+    (!name.is_empty() && !name.starts_with('.')).then_some(name)
+}
+
+fn is_accepted(file_name: &str) -> bool {
+    Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ACCEPTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+// Appends a counter to the file name instead of overwriting an earlier upload.
+async fn unique_path(dir: &Path, file_name: &str) -> std::io::Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let path = Path::new(file_name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    let mut candidate = dir.join(file_name);
+    let mut counter = 1;
+    while tokio::fs::try_exists(&candidate).await?
+        || tokio::fs::try_exists(download_temp_path(&candidate.to_string_lossy())).await?
+    {
+        candidate = dir.join(format!("{stem} ({counter}).{ext}"));
+        counter += 1;
+    }
+    Ok(candidate)
+}
+
+struct RunningServer {
+    server: EmbeddedServer,
+    info: UploadServerInfo,
+}
+
+#[derive(Default)]
+pub struct UploadServer(Mutex<Option<RunningServer>>);
+
+#[command]
+pub async fn start_upload_server(
+    app: AppHandle,
+    server: State<'_, UploadServer>,
+    options: Option<UploadServerOptions>,
+) -> Result<UploadServerInfo, String> {
+    let options = options.unwrap_or_default();
+    let previous = server.0.lock().unwrap().take();
+    if let Some(previous) = previous {
+        previous.server.shutdown().await;
+    }
+
+    let import_dir = match options.import_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => app
+            .path()
+            .app_data_dir()
+            .map_err(|e| e.to_string())?
+            .join("Readest")
+            .join("Imports"),
+    };
+    let token = random_token(TOKEN_LENGTH);
+    let state = Arc::new(ServerState {
+        app: app.clone(),
+        token: token.clone(),
+        import_dir: import_dir.clone(),
+    });
+    let running = EmbeddedServer::start("Upload", router(state), options.port.unwrap_or(0))
+        .await
+        .map_err(|e| format!("Failed to start upload server: {e}"))?;
+
+    let port = running.port();
+    let url = format!("http://{}:{port}/?token={token}", local_ip());
+    let qr_code = QrCode::new(url.as_bytes())
+        .map_err(|e| e.to_string())?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    let info = UploadServerInfo {
+        url,
+        port,
+        token,
+        import_dir: import_dir.to_string_lossy().into_owned(),
+        qr_code,
+    };
+    *server.0.lock().unwrap() = Some(RunningServer {
+        server: running,
+        info: info.clone(),
+    });
+    Ok(info)
+}
+
+#[command]
+pub fn stop_upload_server(server: State<'_, UploadServer>) {
+    if let Some(running) = server.0.lock().unwrap().take() {
+        running.server.stop();
+    }
+}
+
+// Returns the details of the running server, if any.
+#[command]
+pub fn get_upload_server_info(server: State<'_, UploadServer>) -> Option<UploadServerInfo> {
+    server
+        .0
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|running| running.info.clone())
+}
@@ -0,0 +1,516 @@
+//! Encrypted storage for credentials such as sync tokens and API keys, so that they do not
+//! have to be kept by the webview.
+//!
+//! Secrets are scoped by service ID and stored in `vault.json` in the app data dir, each
+//! encrypted with XChaCha20-Poly1305. The vault key is kept in the OS keyring (Keychain,
+//! Credential Manager, Secret Service) where available. Otherwise, e.g. on headless Linux,
+//! it is derived from a passphrase with Argon2id and the vault has to be unlocked first.
+//! If the key in the keyring is lost, the secrets can't be recovered and the vault has to be
+//! reset.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use serde::{ser::Serializer, Deserialize, Serialize};
+use tauri::{command, AppHandle, Manager, State};
+use tokio::sync::Mutex;
+
+const VAULT_FILE: &str = "vault.json";
+const VAULT_VERSION: u32 = 1;
+const SALT_LENGTH: usize = 16;
+
+// Encrypted with the vault key to tell whether a key or passphrase is the right one.
+const CHECK_PLAINTEXT: &[u8] = b"readest-vault";
+const CHECK_AAD: &[u8] = b"readest-vault-check";
+
+#[cfg(desktop)]
+const KEYRING_SERVICE: &str = "com.bilingify.readest.vault";
+#[cfg(desktop)]
+const KEYRING_USER: &str = "vault-key";
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[error("the vault is locked")]
+    Locked,
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+    #[error("the vault key is missing from the OS keyring")]
+    KeyMissing,
+    #[error("OS keyring error: {0}")]
+    Keyring(String),
+    #[error("failed to encrypt or decrypt the vault: {0}")]
+    Crypto(String),
+    #[error("invalid service ID or secret name")]
+    InvalidName,
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_ref())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeySource {
+    Keyring,
+    Passphrase,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KdfParams {
+    salt: String, // Base64
+    memory_cost: u32,
+    time_cost: u32,
+    parallelism: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Sealed {
+    nonce: String, // Base64
+    data: String,  // Base64 ciphertext with the authentication tag
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultFile {
+    version: u32,
+    key_source: Option<KeySource>, // None until the vault has been set up
+    kdf: Option<KdfParams>,        // Only with a passphrase
+    check: Option<Sealed>,
+    secrets: BTreeMap<String, BTreeMap<String, Sealed>>, // By service ID and secret name
+}
+
+impl VaultFile {
+    fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+}
+
+fn crypto_error(error: impl std::fmt::Display) -> Error {
+    Error::Crypto(error.to_string())
+}
+
+fn seal(key: &Key, plaintext: &[u8], aad: &[u8]) -> Result<Sealed> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let data = XChaCha20Poly1305::new(key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(crypto_error)?;
+    Ok(Sealed {
+        nonce: BASE64.encode(nonce),
+        data: BASE64.encode(data),
+    })
+}
+
+fn open(key: &Key, sealed: &Sealed, aad: &[u8]) -> Result<Vec<u8>> {
+    let nonce = BASE64.decode(&sealed.nonce).map_err(crypto_error)?;
+    if nonce.len() != 24 {
+        return Err(crypto_error("invalid nonce"));
+    }
+    let data = BASE64.decode(&sealed.data).map_err(crypto_error)?;
+    XChaCha20Poly1305::new(key)
+        .decrypt(XNonce::from_slice(&nonce), Payload { msg: &data, aad })
+        .map_err(crypto_error)
+}
+
+// Binds each ciphertext to its place in the vault, so that entries cannot be swapped.
+fn secret_aad(service: &str, name: &str) -> Vec<u8> {
+    [service.as_bytes(), b"\0", name.as_bytes()].concat()
+}
+
+fn derive_key(passphrase: &str, kdf: &KdfParams) -> Result<Key> {
+    let salt = BASE64.decode(&kdf.salt).map_err(crypto_error)?;
+    let params = Params::new(kdf.memory_cost, kdf.time_cost, kdf.parallelism, Some(32))
+        .map_err(crypto_error)?;
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(crypto_error)?;
+    Ok(key)
+}
+
+// Argon2id is deliberately slow, so it runs on a blocking thread.
+async fn derive_key_blocking(passphrase: String, kdf: KdfParams) -> Result<Key> {
+    tauri::async_runtime::spawn_blocking(move || derive_key(&passphrase, &kdf)).await?
+}
+
+fn new_kdf_params() -> KdfParams {
+    let mut salt = [0; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let params = Params::default();
+    KdfParams {
+        salt: BASE64.encode(salt),
+        memory_cost: params.m_cost(),
+        time_cost: params.t_cost(),
+        parallelism: params.p_cost(),
+    }
+}
+
+// Returns the vault key from the OS keyring, creating it if `create` is set. Keyring calls may
+// block on IPC with the keyring daemon, so they run on a blocking thread.
+#[cfg(desktop)]
+async fn keyring_key(create: bool) -> Result<Key> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+            .map_err(|e| Error::Keyring(e.to_string()))?;
+        match entry.get_password() {
+            Ok(encoded) => {
+                let key = BASE64.decode(encoded).map_err(crypto_error)?;
+                if key.len() != 32 {
+                    return Err(crypto_error("invalid key in the OS keyring"));
+                }
+                Ok(*Key::from_slice(&key))
+            }
+            Err(keyring::Error::NoEntry) if create => {
+                let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+                entry
+                    .set_password(&BASE64.encode(key))
+                    .map_err(|e| Error::Keyring(e.to_string()))?;
+                Ok(key)
+            }
+            Err(keyring::Error::NoEntry) => Err(Error::KeyMissing),
+            Err(e) => Err(Error::Keyring(e.to_string())),
+        }
+    })
+    .await?
+}
+
+// Removes the vault key from the OS keyring, if it is there.
+#[cfg(desktop)]
+async fn delete_keyring_key() -> Result<()> {
+    tauri::async_runtime::spawn_blocking(|| {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+            .map_err(|e| Error::Keyring(e.to_string()))?;
+        match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::Keyring(e.to_string())),
+        }
+    })
+    .await?
+}
+
+// There is no OS keyring to use on mobile, so the vault is always protected by a passphrase.
+#[cfg(mobile)]
+async fn keyring_key(_create: bool) -> Result<Key> {
+    Err(Error::Keyring("not available on this platform".into()))
+}
+
+#[cfg(mobile)]
+async fn delete_keyring_key() -> Result<()> {
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultStatus {
+    pub key_source: Option<KeySource>, // None if the vault still has to be set up with a passphrase
+    pub unlocked: bool,
+}
+
+// Holds the key of the unlocked vault. The lock is also held while the vault file is read and
+// written, so that concurrent commands do not lose each other's changes.
+#[derive(Default)]
+pub struct Vault(Mutex<Option<Key>>);
+
+// Returns the key of the vault, setting the vault up with a key in the OS keyring if it is new.
+async fn vault_key(cached: &mut Option<Key>, path: &Path, file: &mut VaultFile) -> Result<Key> {
+    if let Some(key) = *cached {
+        return Ok(key);
+    }
+
+    let key = match file.key_source {
+        Some(KeySource::Passphrase) => return Err(Error::Locked),
+        Some(KeySource::Keyring) => keyring_key(false).await?,
+        None => match keyring_key(true).await {
+            Ok(key) => {
+                file.version = VAULT_VERSION;
+                file.key_source = Some(KeySource::Keyring);
+                file.check = Some(seal(&key, CHECK_PLAINTEXT, CHECK_AAD)?);
+                file.save(path)?;
+                key
+            }
+            Err(e) => {
+                log::warn!("Falling back to a passphrase for the vault: {e}");
+                return Err(Error::Locked);
+            }
+        },
+    };
+    if let Some(check) = &file.check {
+        open(&key, check, CHECK_AAD).map_err(|_| Error::KeyMissing)?;
+    }
+    *cached = Some(key);
+    Ok(key)
+}
+
+fn vault_path(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(VAULT_FILE))
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains('\0') {
+        return Err(Error::InvalidName);
+    }
+    Ok(())
+}
+
+// Reports how the vault is protected, unlocking it with the OS keyring if possible.
+#[command]
+pub async fn get_vault_status(app: AppHandle, vault: State<'_, Vault>) -> Result<VaultStatus> {
+    let path = vault_path(&app)?;
+    let mut cached = vault.0.lock().await;
+    let mut file = VaultFile::load(&path)?;
+    let unlocked = match vault_key(&mut cached, &path, &mut file).await {
+        Ok(_) => true,
+        Err(Error::Locked) => false,
+        Err(e) => return Err(e),
+    };
+    Ok(VaultStatus {
+        key_source: file.key_source,
+        unlocked,
+    })
+}
+
+// Unlocks a vault that is protected by a passphrase, or sets a new vault up with it.
+#[command]
+pub async fn unlock_vault(
+    app: AppHandle,
+    vault: State<'_, Vault>,
+    passphrase: String,
+) -> Result<VaultStatus> {
+    let path = vault_path(&app)?;
+    // The vault file is replaced atomically, so it can be read without the lock
+    let file = VaultFile::load(&path)?;
+    if file.key_source == Some(KeySource::Keyring) {
+        let mut cached = vault.0.lock().await;
+        let mut file = VaultFile::load(&path)?;
+        vault_key(&mut cached, &path, &mut file).await?;
+        return Ok(VaultStatus {
+            key_source: file.key_source,
+            unlocked: true,
+        });
+    }
+
+    // The key is derived without holding the lock, which would block every other command
+    let kdf = match (file.kdf, &file.check) {
+        (Some(kdf), Some(_)) => kdf,
+        _ if passphrase.is_empty() => return Err(Error::WrongPassphrase),
+        _ => new_kdf_params(),
+    };
+    let key = derive_key_blocking(passphrase, kdf.clone()).await?;
+
+    let mut cached = vault.0.lock().await;
+    let mut file = VaultFile::load(&path)?;
+    match (&file.kdf, &file.check) {
+        // Set up with another salt in the meantime, so the key can't be the right one
+        (Some(current), Some(check)) => {
+            if current.salt != kdf.salt {
+                return Err(Error::WrongPassphrase);
+            }
+            open(&key, check, CHECK_AAD).map_err(|_| Error::WrongPassphrase)?;
+        }
+        _ => {
+            file.version = VAULT_VERSION;
+            file.key_source = Some(KeySource::Passphrase);
+            file.kdf = Some(kdf);
+            file.check = Some(seal(&key, CHECK_PLAINTEXT, CHECK_AAD)?);
+            file.secrets.clear();
+            file.save(&path)?;
+        }
+    }
+    *cached = Some(key);
+    Ok(VaultStatus {
+        key_source: file.key_source,
+        unlocked: true,
+    })
+}
+
+// Deletes all secrets and sets the vault up again, for when its key is lost, e.g. after the
+// OS keyring was cleared, or its passphrase is forgotten.
+#[command]
+pub async fn reset_vault(app: AppHandle, vault: State<'_, Vault>) -> Result<VaultStatus> {
+    let path = vault_path(&app)?;
+    let mut cached = vault.0.lock().await;
+    *cached = None;
+    delete_keyring_key().await?;
+    let mut file = VaultFile::default();
+    file.save(&path)?;
+    let unlocked = match vault_key(&mut cached, &path, &mut file).await {
+        Ok(_) => true,
+        Err(Error::Locked) => false,
+        Err(e) => return Err(e),
+    };
+    Ok(VaultStatus {
+        key_source: file.key_source,
+        unlocked,
+    })
+}
+
+// Forgets the key of a vault protected by a passphrase until it is unlocked again.
+#[command]
+pub async fn lock_vault(vault: State<'_, Vault>) -> Result<()> {
+    *vault.0.lock().await = None;
+    Ok(())
+}
+
+#[command]
+pub async fn set_secret(
+    app: AppHandle,
+    vault: State<'_, Vault>,
+    service: String,
+    name: String,
+    secret: String,
+) -> Result<()> {
+    check_name(&service)?;
+    check_name(&name)?;
+    let path = vault_path(&app)?;
+    let mut cached = vault.0.lock().await;
+    let mut file = VaultFile::load(&path)?;
+    let key = vault_key(&mut cached, &path, &mut file).await?;
+    let sealed = seal(&key, secret.as_bytes(), &secret_aad(&service, &name))?;
+    file.secrets
+        .entry(service)
+        .or_default()
+        .insert(name, sealed);
+    file.save(&path)
+}
+
+// Returns None if there is no such secret.
+#[command]
+pub async fn get_secret(
+    app: AppHandle,
+    vault: State<'_, Vault>,
+    service: String,
+    name: String,
+) -> Result<Option<String>> {
+    let path = vault_path(&app)?;
+    let mut cached = vault.0.lock().await;
+    let mut file = VaultFile::load(&path)?;
+    let key = vault_key(&mut cached, &path, &mut file).await?;
+    let Some(sealed) = file
+        .secrets
+        .get(&service)
+        .and_then(|secrets| secrets.get(&name))
+    else {
+        return Ok(None);
+    };
+    let secret = open(&key, sealed, &secret_aad(&service, &name))?;
+    String::from_utf8(secret).map(Some).map_err(crypto_error)
+}
+
+// Deletes a secret, or all secrets of the service if no name is given. Deleting does not
+// need the vault key, so it also works while the vault is locked.
+#[command]
+pub async fn delete_secret(
+    app: AppHandle,
+    vault: State<'_, Vault>,
+    service: String,
+    name: Option<String>,
+) -> Result<()> {
+    let path = vault_path(&app)?;
+    let _cached = vault.0.lock().await;
+    let mut file = VaultFile::load(&path)?;
+    let changed = match name {
+        Some(name) => {
+            let secrets = file.secrets.get_mut(&service);
+            let removed = secrets.is_some_and(|secrets| secrets.remove(&name).is_some());
+            if file.secrets.get(&service).is_some_and(BTreeMap::is_empty) {
+                file.secrets.remove(&service);
+            }
+            removed
+        }
+        None => file.secrets.remove(&service).is_some(),
+    };
+    if changed {
+        file.save(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seals_and_opens_secrets() {
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        let aad = secret_aad("webdav", "password");
+        let sealed = seal(&key, b"hunter2", &aad).unwrap();
+        assert_eq!(open(&key, &sealed, &aad).unwrap(), b"hunter2");
+
+        let other = XChaCha20Poly1305::generate_key(&mut OsRng);
+        assert!(open(&other, &sealed, &aad).is_err());
+    }
+
+    #[test]
+    fn binds_secrets_to_their_place() {
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        let sealed = seal(&key, b"hunter2", &secret_aad("webdav", "password")).unwrap();
+        assert!(open(&key, &sealed, &secret_aad("webdav", "token")).is_err());
+        assert!(open(&key, &sealed, &secret_aad("kosync", "password")).is_err());
+        // The separator keeps the service and name from being shifted into each other
+        assert!(open(&key, &sealed, &secret_aad("webda", "vpassword")).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_secrets() {
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        let aad = secret_aad("webdav", "password");
+        let mut sealed = seal(&key, b"hunter2", &aad).unwrap();
+        let mut data = BASE64.decode(&sealed.data).unwrap();
+        data[0] ^= 1;
+        sealed.data = BASE64.encode(data);
+        assert!(open(&key, &sealed, &aad).is_err());
+    }
+
+    #[test]
+    fn derives_the_same_key_from_the_same_passphrase() {
+        // Small parameters, the defaults take too long for a test
+        let kdf = KdfParams {
+            salt: BASE64.encode([7; SALT_LENGTH]),
+            memory_cost: 64,
+            time_cost: 1,
+            parallelism: 1,
+        };
+        let key = derive_key("correct horse", &kdf).unwrap();
+        assert_eq!(key, derive_key("correct horse", &kdf).unwrap());
+        assert_ne!(key, derive_key("wrong horse", &kdf).unwrap());
+    }
+}
@@ -0,0 +1,419 @@
+use percent_encoding::percent_decode_str;
+use quick_xml::{events::Event, Reader};
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH};
+use reqwest::{Body, Method, RequestBuilder, Response, StatusCode, Url};
+use serde::Serialize;
+
+use super::{Error, Result, WebDavServer};
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?><d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getetag/><d:getcontentlength/><d:getlastmodified/></d:prop></d:propfind>"#;
+
+const LOCK_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?><d:lockinfo xmlns:d="DAV:"><d:lockscope><d:exclusive/></d:lockscope><d:locktype><d:write/></d:locktype><d:owner>Readest</d:owner></d:lockinfo>"#;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DavEntry {
+    pub path: String, // Decoded path relative to the server URL, without leading or trailing slashes
+    pub is_collection: bool,
+    pub etag: Option<String>,
+    pub size: u64,
+    pub last_modified: Option<String>, // HTTP date
+}
+
+impl DavEntry {
+    // Identifies the content of a file, also on servers that do not report ETags.
+    pub fn version(&self) -> String {
+        self.etag.clone().unwrap_or_else(|| {
+            format!(
+                "{}:{}",
+                self.last_modified.as_deref().unwrap_or_default(),
+                self.size
+            )
+        })
+    }
+}
+
+// Condition under which a PUT may replace the file on the server.
+pub enum Precondition<'a> {
+    Any,
+    IfMatch(&'a str), // Only if the file still has this ETag
+    IfAbsent,         // Only if there is no such file yet
+}
+
+pub struct WebDavClient {
+    client: reqwest::Client,
+    base: Url,
+    username: Option<String>,
+    password: Option<String>,
+    lock_token: Option<String>,
+}
+
+impl WebDavClient {
+    pub fn new(client: reqwest::Client, server: &WebDavServer) -> Result<Self> {
+        let mut base = Url::parse(&server.url)
+            .map_err(|e| Error::InvalidUrl(format!("{}: {e}", server.url)))?;
+        if base.cannot_be_a_base() || !matches!(base.scheme(), "http" | "https") {
+            return Err(Error::InvalidUrl(server.url.clone()));
+        }
+        if !base.path().ends_with('/') {
+            let path = format!("{}/", base.path());
+            base.set_path(&path);
+        }
+        Ok(Self {
+            client,
+            base,
+            username: server.username.clone().filter(|name| !name.is_empty()),
+            password: server.password.clone(),
+            lock_token: None,
+        })
+    }
+
+    // Identifies the server and account, e.g. to keep the sync state of different servers apart.
+    pub fn id(&self) -> String {
+        match &self.username {
+            Some(username) => format!("{username}@{}", self.base),
+            None => self.base.to_string(),
+        }
+    }
+
+    fn url(&self, path: &str, collection: bool) -> Url {
+        let mut url = self.base.clone();
+        {
+            let mut segments = url
+                .path_segments_mut()
+                .expect("the base URL was checked in new()");
+            segments
+                .pop_if_empty()
+                .extend(path.split('/').filter(|segment| !segment.is_empty()));
+            if collection {
+                segments.push("");
+            }
+        }
+        url
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    // Requests that modify the server have to prove that they hold the lock, if any.
+    fn locked(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.lock_token {
+            Some(token) => request.header("If", format!("(<{token}>)")),
+            None => request,
+        }
+    }
+
+    // Returns the entry at `path` and, for a depth of 1, the entries of its direct children.
+    pub async fn propfind(&self, path: &str, depth: u32) -> Result<Vec<DavEntry>> {
+        let response = self
+            .request(method("PROPFIND"), self.url(path, depth > 0))
+            .header("Depth", depth.to_string())
+            .header(CONTENT_TYPE, "application/xml; charset=utf-8")
+            .body(PROPFIND_BODY)
+            .send()
+            .await?;
+        let response = error_for_status(response).await?;
+        if response.status() != StatusCode::MULTI_STATUS {
+            return Err(Error::InvalidResponse(format!(
+                "PROPFIND returned status code {}",
+                response.status().as_u16()
+            )));
+        }
+        parse_multistatus(&response.text().await?, self.base.path())
+    }
+
+    // Returns None if the file still has the ETag `if_none_match`.
+    pub async fn get(&self, path: &str, if_none_match: Option<&str>) -> Result<Option<Response>> {
+        let mut request = self.request(Method::GET, self.url(path, false));
+        if let Some(etag) = if_none_match {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        error_for_status(response).await.map(Some)
+    }
+
+    // Uploads a file of `len` bytes and returns its new ETag if the server reports it.
+    pub async fn put(
+        &self,
+        path: &str,
+        body: Body,
+        len: u64,
+        precondition: Precondition<'_>,
+    ) -> Result<Option<String>> {
+        let mut request = self
+            .locked(self.request(Method::PUT, self.url(path, false)))
+            .header(CONTENT_LENGTH, len)
+            .body(body);
+        request = match precondition {
+            Precondition::Any => request,
+            Precondition::IfMatch(etag) => request.header(IF_MATCH, etag),
+            Precondition::IfAbsent => request.header(IF_NONE_MATCH, "*"),
+        };
+
+        let response = request.send().await?;
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            return Err(Error::PreconditionFailed(path.to_string()));
+        }
+        let response = error_for_status(response).await?;
+        Ok(response
+            .headers()
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string))
+    }
+
+    // Deletes a file unless it no longer has the ETag `if_match`; a missing file is not an error.
+    pub async fn delete(&self, path: &str, if_match: Option<&str>) -> Result<()> {
+        let mut request = self.locked(self.request(Method::DELETE, self.url(path, false)));
+        if let Some(etag) = if_match {
+            request = request.header(IF_MATCH, etag);
+        }
+        let response = request.send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(()),
+            StatusCode::PRECONDITION_FAILED => Err(Error::PreconditionFailed(path.to_string())),
+            _ => error_for_status(response).await.map(|_| ()),
+        }
+    }
+
+    // Creates the collection `path` and all of its missing ancestors.
+    pub async fn mkcol_all(&self, path: &str) -> Result<()> {
+        let mut prefix = String::new();
+        for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(segment);
+
+            let response = self
+                .locked(self.request(method("MKCOL"), self.url(&prefix, true)))
+                .send()
+                .await?;
+            // 405 Method Not Allowed means that the collection exists already
+            if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+                error_for_status(response).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Takes an exclusive write lock on the collection `path` for `timeout_secs` seconds.
+    // Returns false if the server does not support locking.
+    pub async fn lock(&mut self, path: &str, timeout_secs: u64) -> Result<bool> {
+        let response = self
+            .request(method("LOCK"), self.url(path, true))
+            .header("Depth", "infinity")
+            .header("Timeout", format!("Second-{timeout_secs}"))
+            .header(CONTENT_TYPE, "application/xml; charset=utf-8")
+            .body(LOCK_BODY)
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::LOCKED => return Err(Error::Locked),
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => return Ok(false),
+            _ => {}
+        }
+        let response = error_for_status(response).await?;
+        let token = response
+            .headers()
+            .get("Lock-Token")
+            .and_then(|v| v.to_str().ok())
+            .map(|token| token.trim().trim_start_matches('<').trim_end_matches('>'))
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| Error::InvalidResponse("LOCK returned no lock token".into()))?;
+        self.lock_token = Some(token.to_string());
+        Ok(true)
+    }
+
+    // Restarts the timeout of the lock taken with lock(), if any.
+    pub async fn refresh_lock(&self, path: &str, timeout_secs: u64) -> Result<()> {
+        let Some(token) = &self.lock_token else {
+            return Ok(());
+        };
+        let response = self
+            .request(method("LOCK"), self.url(path, true))
+            .header("If", format!("(<{token}>)"))
+            .header("Timeout", format!("Second-{timeout_secs}"))
+            .send()
+            .await?;
+        error_for_status(response).await.map(|_| ())
+    }
+
+    pub async fn unlock(&mut self, path: &str) -> Result<()> {
+        let Some(token) = self.lock_token.take() else {
+            return Ok(());
+        };
+        let response = self
+            .request(method("UNLOCK"), self.url(path, true))
+            .header("Lock-Token", format!("<{token}>"))
+            .send()
+            .await?;
+        error_for_status(response).await.map(|_| ())
+    }
+}
+
+fn method(name: &'static str) -> Method {
+    Method::from_bytes(name.as_bytes()).expect("WebDAV method names are valid")
+}
+
+async fn error_for_status(response: Response) -> Result<Response> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(Error::HttpErrorCode(
+            response.status().as_u16(),
+            response.text().await.unwrap_or_default(),
+        ))
+    }
+}
+
+// Parses the entries of a 207 Multi-Status response to PROPFIND. Namespace prefixes differ
+// between servers, so elements are matched by their local name only.
+fn parse_multistatus(xml: &str, base_path: &str) -> Result<Vec<DavEntry>> {
+    let base_path = percent_decode_str(base_path).decode_utf8_lossy();
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut entries = Vec::new();
+    let mut entry: Option<DavEntry> = None;
+    let mut element = Vec::new();
+    loop {
+        match reader
+            .read_event()
+            .map_err(|e| Error::InvalidResponse(e.to_string()))?
+        {
+            Event::Start(start) => {
+                let name = start.local_name();
+                match name.as_ref() {
+                    b"response" => entry = Some(DavEntry::default()),
+                    b"collection" => {
+                        if let Some(entry) = &mut entry {
+                            entry.is_collection = true;
+                        }
+                    }
+                    _ => {}
+                }
+                element = name.as_ref().to_vec();
+            }
+            Event::Empty(empty) => {
+                if empty.local_name().as_ref() == b"collection" {
+                    if let Some(entry) = &mut entry {
+                        entry.is_collection = true;
+                    }
+                }
+            }
+            Event::Text(text) => {
+                let Some(entry) = &mut entry else {
+                    continue;
+                };
+                let text = text
+                    .unescape()
+                    .map_err(|e| Error::InvalidResponse(e.to_string()))?
+                    .into_owned();
+                match element.as_slice() {
+                    b"href" => entry.path = relative_path(&text, &base_path),
+                    b"getetag" => entry.etag = Some(text),
+                    b"getcontentlength" => entry.size = text.parse().unwrap_or(0),
+                    b"getlastmodified" => entry.last_modified = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(end) => {
+                if end.local_name().as_ref() == b"response" {
+                    entries.extend(entry.take());
+                }
+                element.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+// Hrefs are either absolute URLs or absolute paths, percent-encoded in both cases.
+fn relative_path(href: &str, base_path: &str) -> String {
+    let path = Url::parse(href)
+        .map(|url| url.path().to_string())
+        .unwrap_or_else(|_| href.to_string());
+    let path = percent_decode_str(&path).decode_utf8_lossy();
+    path.strip_prefix(base_path.trim_end_matches('/'))
+        .unwrap_or(path.as_ref())
+        .trim_matches('/')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTISTATUS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response>
+    <d:href>/remote.php/dav/files/me/Readest/Books/</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype><d:collection/></d:resourcetype>
+        <d:getlastmodified>Tue, 01 Apr 2025 10:00:00 GMT</d:getlastmodified>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>https://cloud.example.com/remote.php/dav/files/me/Readest/Books/abc/My%20Book.epub</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype/>
+        <d:getetag>&quot;5f3a-1&quot;</d:getetag>
+        <d:getcontentlength>1024</d:getcontentlength>
+        <d:getlastmodified>Wed, 02 Apr 2025 12:30:00 GMT</d:getlastmodified>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+
+    #[test]
+    fn parses_multistatus_entries() {
+        let entries = parse_multistatus(MULTISTATUS, "/remote.php/dav/files/me/").unwrap();
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0].path, "Readest/Books");
+        assert!(entries[0].is_collection);
+        assert_eq!(entries[0].etag, None);
+
+        assert_eq!(entries[1].path, "Readest/Books/abc/My Book.epub");
+        assert!(!entries[1].is_collection);
+        assert_eq!(entries[1].etag.as_deref(), Some("\"5f3a-1\""));
+        assert_eq!(entries[1].size, 1024);
+        assert_eq!(
+            entries[1].last_modified.as_deref(),
+            Some("Wed, 02 Apr 2025 12:30:00 GMT")
+        );
+    }
+
+    #[test]
+    fn matches_elements_by_local_name() {
+        let xml = r#"<multistatus xmlns="DAV:"><response><href>/dav/a.json</href>
+<propstat><prop><resourcetype/><getcontentlength>7</getcontentlength></prop></propstat>
+</response></multistatus>"#;
+        let entries = parse_multistatus(xml, "/dav").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "a.json");
+        assert_eq!(entries[0].size, 7);
+        assert_eq!(entries[0].version(), ":7");
+    }
+
+    #[test]
+    fn rejects_malformed_multistatus() {
+        assert!(parse_multistatus("<d:multistatus><d:response></d:href>", "/").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Sync the library with a self-hosted WebDAV server (Nextcloud, ownCloud, Apache, rclone, ...)
+//! instead of the Readest cloud.
+//!
+//! The books folder, including each book's `config.json` with its notes and reading progress,
+//! and `settings.json` are mirrored below `Readest/` on the server. Transfers go through the
+//! shared HTTP client and report progress the same way as `download_file` and `upload_file`.
+
+mod client;
+mod sync;
+
+use serde::{ser::Serializer, Deserialize, Serialize};
+use tauri::{command, ipc::Channel, AppHandle, State};
+
+use crate::http_client::HttpClient;
+use crate::transfer_file::{channel_progress, ProgressPayload};
+use crate::transfer_registry::TransferRegistry;
+
+use client::{DavEntry, WebDavClient};
+use sync::SyncReport;
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebDavServer {
+    pub url: String, // Folder on the server that holds the Readest folder
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[error(transparent)]
+    Transfer(#[from] crate::transfer_file::Error),
+    #[error("invalid WebDAV URL {0}")]
+    InvalidUrl(String),
+    #[error("WebDAV request failed with status code {0}: {1}")]
+    HttpErrorCode(u16, String),
+    #[error("invalid WebDAV response: {0}")]
+    InvalidResponse(String),
+    #[error("{0} was changed on the server")]
+    PreconditionFailed(String),
+    #[error("the Readest folder on the server is locked by another device")]
+    Locked,
+    #[error("sync was cancelled")]
+    Cancelled,
+}
+
+impl Error {
+    fn is_not_found(&self) -> bool {
+        matches!(self, Error::HttpErrorCode(404, _))
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_ref())
+    }
+}
+
+// Checks that the server is reachable and accepts the credentials.
+#[command]
+pub async fn webdav_check_connection(
+    http: State<'_, HttpClient>,
+    server: WebDavServer,
+) -> Result<()> {
+    let client = WebDavClient::new(http.client(), &server)?;
+    client.propfind("", 0).await.map(|_| ())
+}
+
+// Lists the direct children of `path`, relative to the server URL.
+#[command]
+pub async fn webdav_list(
+    http: State<'_, HttpClient>,
+    server: WebDavServer,
+    path: String,
+) -> Result<Vec<DavEntry>> {
+    let client = WebDavClient::new(http.client(), &server)?;
+    let path = path.trim_matches('/');
+    let mut entries = client.propfind(path, 1).await?;
+    entries.retain(|entry| entry.path != path);
+    Ok(entries)
+}
+
+// Runs a two-way sync; `id` can be passed to cancel_transfer, pause_transfer and resume_transfer.
+#[command]
+pub async fn webdav_sync(
+    app: AppHandle,
+    registry: State<'_, TransferRegistry>,
+    http: State<'_, HttpClient>,
+    id: u32,
+    server: WebDavServer,
+    on_progress: Channel<ProgressPayload>,
+) -> Result<SyncReport> {
+    let client = WebDavClient::new(http.client(), &server)?;
+    let transfer = registry.register(id, None);
+    sync::sync(&app, client, &transfer, channel_progress(on_progress)).await
+}
@@ -0,0 +1,827 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::stream::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+};
+use tokio_util::codec::{BytesCodec, FramedRead};
+
+use super::client::{DavEntry, Precondition, WebDavClient};
+use super::{Error, Result};
+use crate::transfer_file::{
+    commit_download, download_temp_path, ProgressCallback, TransferStats, PARTIAL_SUFFIX,
+    TEMP_SUFFIX,
+};
+use crate::transfer_registry::TransferHandle;
+
+const SYNC_STATE_FILE: &str = "webdav-sync.json";
+
+// Folder on the server that everything is synced into, locked for the duration of a sync.
+const REMOTE_ROOT: &str = "Readest";
+
+// Locks expire on their own if the app goes away in the middle of a sync, so they are refreshed
+// while the sync runs.
+const LOCK_TIMEOUT_SECS: u64 = 600;
+const LOCK_REFRESH_INTERVAL: Duration = Duration::from_secs(LOCK_TIMEOUT_SECS / 3);
+
+// Settings that only make sense on the device they were made on, such as paths and proxies.
+// They are left out of the uploaded settings and kept as they are when downloading them.
+const DEVICE_SETTINGS: &[&str] = &[
+    "localBooksDir",
+    "httpClient",
+    "keepLogin",
+    "alwaysOnTop",
+    "showAppMenu",
+    "openBookInNewWindow",
+    "screenWakeLock",
+    "lastOpenBooks",
+    "koreaderSyncDeviceId",
+    "koreaderSyncDeviceName",
+    "lastSyncedAtBooks",
+    "lastSyncedAtConfigs",
+    "lastSyncedAtNotes",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncedFile {
+    version: String,      // Version of the file on the server after the last sync
+    etag: Option<String>, // ETag of the file on the server after the last sync
+    size: u64,            // Size of the local file after the last sync
+    modified: u64,        // Modification time of the local file in milliseconds after the last sync
+}
+
+// The state of the last sync with each server, used to tell local from remote changes.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncState {
+    servers: HashMap<String, HashMap<String, SyncedFile>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncFailure {
+    pub path: String,
+    pub error: String,
+}
+
+// Paths are relative to the server URL, e.g. `Readest/Books/<hash>/config.json`.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub uploaded: Vec<String>,
+    pub downloaded: Vec<String>,
+    pub deleted_remote: Vec<String>,
+    pub deleted_local: Vec<String>,
+    pub conflicts: Vec<String>, // Changed on both sides, the more recent copy was kept
+    pub skipped: Vec<String>,   // Changed on the server while syncing, picked up by the next sync
+    pub failed: Vec<SyncFailure>,
+}
+
+#[derive(Clone)]
+struct SyncRoot {
+    remote: &'static str,
+    local: PathBuf,
+    is_dir: bool,
+    device_keys: &'static [&'static str], // Top-level keys of a JSON file that are not synced
+}
+
+struct LocalFile {
+    path: PathBuf,
+    size: u64,
+    modified: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Upload,
+    Download,
+    DeleteRemote,
+    DeleteLocal,
+    Forget, // Gone on both sides
+}
+
+struct Context<'a> {
+    client: &'a WebDavClient,
+    transfer: &'a TransferHandle,
+    stats: Arc<TransferStats>,
+    on_progress: ProgressCallback,
+    collections: HashSet<String>, // Collections known to exist on the server
+}
+
+fn sync_roots(app: &AppHandle) -> Result<Vec<SyncRoot>> {
+    Ok(vec![
+        SyncRoot {
+            remote: "Readest/Books",
+            local: app.path().app_data_dir()?.join("Readest").join("Books"),
+            is_dir: true,
+            device_keys: &[],
+        },
+        SyncRoot {
+            remote: "Readest/settings.json",
+            local: app.path().app_config_dir()?.join("settings.json"),
+            is_dir: false,
+            device_keys: DEVICE_SETTINGS,
+        },
+    ])
+}
+
+// Leftovers of interrupted downloads and system files are never synced.
+fn is_ignored(name: &str) -> bool {
+    name.starts_with('.')
+        || name.ends_with(TEMP_SUFFIX)
+        || name.ends_with(PARTIAL_SUFFIX)
+        || name.ends_with(".tmp")
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn local_file(path: PathBuf) -> Option<LocalFile> {
+    let metadata = std::fs::metadata(&path).ok().filter(|m| m.is_file())?;
+    Some(LocalFile {
+        size: metadata.len(),
+        modified: metadata.modified().map(millis).unwrap_or(0),
+        path,
+    })
+}
+
+fn list_local(roots: &[SyncRoot]) -> HashMap<String, LocalFile> {
+    fn walk(dir: &Path, remote: &str, files: &mut HashMap<String, LocalFile>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if is_ignored(&name) {
+                continue;
+            }
+            let remote = format!("{remote}/{name}");
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => walk(&entry.path(), &remote, files),
+                Ok(_) => {
+                    if let Some(file) = local_file(entry.path()) {
+                        files.insert(remote, file);
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    let mut files = HashMap::new();
+    for root in roots {
+        if root.is_dir {
+            walk(&root.local, root.remote, &mut files);
+        } else if let Some(file) = local_file(root.local.clone()) {
+            files.insert(root.remote.to_string(), file);
+        }
+    }
+    files
+}
+
+async fn list_remote(
+    client: &WebDavClient,
+    roots: &[SyncRoot],
+) -> Result<HashMap<String, DavEntry>> {
+    let mut files = HashMap::new();
+    for root in roots {
+        if !root.is_dir {
+            match client.propfind(root.remote, 0).await {
+                Ok(entries) => files.extend(
+                    entries
+                        .into_iter()
+                        .filter(|entry| !entry.is_collection)
+                        .map(|entry| (root.remote.to_string(), entry)),
+                ),
+                Err(e) if e.is_not_found() => {}
+                Err(e) => return Err(e),
+            }
+            continue;
+        }
+
+        // Many servers refuse `Depth: infinity`, so the tree is walked one level at a time
+        let mut dirs = vec![root.remote.to_string()];
+        while let Some(dir) = dirs.pop() {
+            let entries = match client.propfind(&dir, 1).await {
+                Ok(entries) => entries,
+                Err(e) if e.is_not_found() => continue,
+                Err(e) => return Err(e),
+            };
+            for entry in entries {
+                let name = entry.path.rsplit('/').next().unwrap_or_default();
+                if entry.path == dir || is_ignored(name) {
+                    continue;
+                }
+                if entry.is_collection {
+                    dirs.push(entry.path);
+                } else {
+                    files.insert(entry.path.clone(), entry);
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+// Decides what to do with a file from its state on both sides and at the end of the last sync.
+// Returns the action and whether the file was changed on both sides.
+fn plan(
+    local: Option<&LocalFile>,
+    remote: Option<&DavEntry>,
+    synced: Option<&SyncedFile>,
+) -> Option<(Action, bool)> {
+    let local_changed = match (local, synced) {
+        (Some(local), Some(synced)) => {
+            local.size != synced.size || local.modified != synced.modified
+        }
+        (None, None) => false,
+        _ => true,
+    };
+    let remote_changed = match (remote, synced) {
+        (Some(remote), Some(synced)) => remote.version() != synced.version,
+        (None, None) => false,
+        _ => true,
+    };
+
+    let action = match (local, remote) {
+        (None, None) => Action::Forget,
+        _ if !local_changed && !remote_changed => return None,
+        (Some(_), None) if local_changed => Action::Upload,
+        (Some(_), None) => Action::DeleteLocal,
+        (None, Some(_)) if remote_changed => Action::Download,
+        (None, Some(_)) => Action::DeleteRemote,
+        (Some(_), Some(_)) if !remote_changed => Action::Upload,
+        (Some(_), Some(_)) if !local_changed => Action::Download,
+        (Some(local), Some(remote)) => {
+            // Changed on both sides: the more recent change wins
+            let remote_modified = remote
+                .last_modified
+                .as_deref()
+                .and_then(|date| httpdate::parse_http_date(date).ok())
+                .map(millis)
+                .unwrap_or(0);
+            let action = if local.modified >= remote_modified {
+                Action::Upload
+            } else {
+                Action::Download
+            };
+            return Some((action, true));
+        }
+    };
+    Some((action, false))
+}
+
+pub async fn sync(
+    app: &AppHandle,
+    mut client: WebDavClient,
+    transfer: &TransferHandle,
+    on_progress: ProgressCallback,
+) -> Result<SyncReport> {
+    let roots = sync_roots(app)?;
+    let state_path = app.path().app_data_dir()?.join(SYNC_STATE_FILE);
+    let mut state: SyncState = tokio::fs::read(&state_path)
+        .await
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default();
+
+    client.mkcol_all(REMOTE_ROOT).await?;
+    let locked = client.lock(REMOTE_ROOT, LOCK_TIMEOUT_SECS).await?;
+
+    let synced = state.servers.entry(client.id()).or_default();
+    let work = async {
+        tokio::select! {
+            result = sync_files(&client, transfer, on_progress, &roots, synced) => result,
+            _ = keep_locked(&client), if locked => unreachable!("the lock is refreshed forever"),
+        }
+    };
+    let result = transfer
+        .until_cancelled(work)
+        .await
+        .unwrap_or(Err(Error::Cancelled));
+
+    if locked {
+        if let Err(e) = client.unlock(REMOTE_ROOT).await {
+            log::warn!("Failed to unlock the WebDAV folder: {e}");
+        }
+    }
+    // Whatever was transferred before a failure or cancellation need not be transferred again
+    if let Some(parent) = state_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&state_path, serde_json::to_vec(&state)?).await?;
+    result
+}
+
+async fn keep_locked(client: &WebDavClient) {
+    loop {
+        tokio::time::sleep(LOCK_REFRESH_INTERVAL).await;
+        // Requests that need the lock fail on their own once it is lost
+        if let Err(e) = client.refresh_lock(REMOTE_ROOT, LOCK_TIMEOUT_SECS).await {
+            log::warn!("Failed to refresh the lock of the WebDAV folder: {e}");
+        }
+    }
+}
+
+async fn sync_files(
+    client: &WebDavClient,
+    transfer: &TransferHandle,
+    on_progress: ProgressCallback,
+    roots: &[SyncRoot],
+    synced: &mut HashMap<String, SyncedFile>,
+) -> Result<SyncReport> {
+    let local_roots = roots.to_vec();
+    let local = tauri::async_runtime::spawn_blocking(move || list_local(&local_roots)).await?;
+    let remote = list_remote(client, roots).await?;
+
+    let mut paths = local
+        .keys()
+        .chain(remote.keys())
+        .chain(synced.keys())
+        .cloned()
+        .collect::<Vec<_>>();
+    paths.sort_unstable();
+    paths.dedup();
+
+    let actions = paths
+        .into_iter()
+        .filter_map(|path| {
+            plan(local.get(&path), remote.get(&path), synced.get(&path))
+                .map(|(action, conflict)| (path, action, conflict))
+        })
+        .collect::<Vec<_>>();
+    let total = actions
+        .iter()
+        .map(|(path, action, _)| match action {
+            Action::Upload => local.get(path).map_or(0, |file| file.size),
+            Action::Download => remote.get(path).map_or(0, |entry| entry.size),
+            _ => 0,
+        })
+        .sum();
+
+    let mut context = Context {
+        client,
+        transfer,
+        stats: Arc::new(TransferStats::new(total, 0)),
+        on_progress,
+        collections: HashSet::new(),
+    };
+    let mut report = SyncReport::default();
+    for (path, action, conflict) in actions {
+        let local = local.get(&path);
+        let remote = remote.get(&path);
+        let result = match action {
+            Action::Upload => upload(&mut context, roots, &path, local.unwrap(), remote).await,
+            Action::Download => {
+                download(&context, roots, &path, remote.unwrap(), synced.get(&path)).await
+            }
+            Action::DeleteRemote => client
+                .delete(&path, remote.and_then(|entry| entry.etag.as_deref()))
+                .await
+                .map(|_| None),
+            Action::DeleteLocal => delete_local(roots, local.unwrap()).await.map(|_| None),
+            Action::Forget => Ok(None),
+        };
+
+        match result {
+            Ok(record) => {
+                match record {
+                    Some(record) => synced.insert(path.clone(), record),
+                    None => synced.remove(&path),
+                };
+                if conflict {
+                    report.conflicts.push(path.clone());
+                }
+                match action {
+                    Action::Upload => report.uploaded.push(path),
+                    Action::Download => report.downloaded.push(path),
+                    Action::DeleteRemote => report.deleted_remote.push(path),
+                    Action::DeleteLocal => report.deleted_local.push(path),
+                    Action::Forget => {}
+                }
+            }
+            Err(Error::PreconditionFailed(_)) => report.skipped.push(path),
+            Err(e) => {
+                log::error!("Failed to sync {path}: {e}");
+                report.failed.push(SyncFailure {
+                    path,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    Ok(report)
+}
+
+async fn upload(
+    context: &mut Context<'_>,
+    roots: &[SyncRoot],
+    path: &str,
+    local: &LocalFile,
+    remote: Option<&DavEntry>,
+) -> Result<Option<SyncedFile>> {
+    if let Some((parent, _)) = path.rsplit_once('/') {
+        if !context.collections.contains(parent) {
+            context.client.mkcol_all(parent).await?;
+            context.collections.insert(parent.to_string());
+        }
+    }
+
+    // Never overwrite a file that someone else changed after it was listed
+    let precondition = match remote {
+        Some(DavEntry {
+            etag: Some(etag), ..
+        }) => Precondition::IfMatch(etag),
+        Some(_) => Precondition::Any,
+        None => Precondition::IfAbsent,
+    };
+
+    let device_keys = device_keys(roots, path);
+    let (body, len) = if device_keys.is_empty() {
+        let transfer = context.transfer.clone();
+        let stats = Arc::clone(&context.stats);
+        let on_progress = Arc::clone(&context.on_progress);
+        let file = File::open(&local.path).await?;
+        let body = FramedRead::new(file, BytesCodec::new())
+            .map_ok(|chunk| chunk.freeze())
+            .then(move |chunk| {
+                let transfer = transfer.clone();
+                let stats = Arc::clone(&stats);
+                let on_progress = Arc::clone(&on_progress);
+                async move {
+                    transfer.wait_if_paused().await;
+                    if let Ok(chunk) = &chunk {
+                        transfer.throttle(chunk.len()).await;
+                        stats.record_chunk_transfer(chunk.len());
+                        on_progress(stats.payload());
+                    }
+                    chunk
+                }
+            });
+        (reqwest::Body::wrap_stream(body), local.size)
+    } else {
+        let data = strip_keys(&tokio::fs::read(&local.path).await?, device_keys)?;
+        context.stats.record_chunk_transfer(data.len());
+        (context.on_progress)(context.stats.payload());
+        let len = data.len() as u64;
+        (reqwest::Body::from(data), len)
+    };
+    let etag = context.client.put(path, body, len, precondition).await?;
+
+    // Servers do not have to return the ETag of an upload, so ask for it if need be
+    let entry = match etag {
+        Some(etag) => DavEntry {
+            etag: Some(etag),
+            ..Default::default()
+        },
+        None => context
+            .client
+            .propfind(path, 0)
+            .await?
+            .into_iter()
+            .next()
+            .unwrap_or_default(),
+    };
+    Ok(Some(SyncedFile {
+        version: entry.version(),
+        etag: entry.etag,
+        size: local.size,
+        modified: local.modified,
+    }))
+}
+
+async fn download(
+    context: &Context<'_>,
+    roots: &[SyncRoot],
+    path: &str,
+    remote: &DavEntry,
+    synced: Option<&SyncedFile>,
+) -> Result<Option<SyncedFile>> {
+    let Some(local_path) = local_path(roots, path) else {
+        return Err(Error::InvalidResponse(format!("unexpected path {path}")));
+    };
+
+    let if_none_match = synced.and_then(|synced| synced.etag.as_deref());
+    let Some(response) = context.client.get(path, if_none_match).await? else {
+        // Same content under a new version, e.g. when only the modification date changed
+        return Ok(synced.map(|synced| SyncedFile {
+            version: remote.version(),
+            ..synced.clone()
+        }));
+    };
+    let etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| remote.etag.clone());
+
+    if let Some(parent) = local_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let file_path = local_path.to_string_lossy();
+    let temp_path = download_temp_path(&file_path);
+    let device_keys = device_keys(roots, path);
+    if device_keys.is_empty() {
+        let mut file = BufWriter::new(File::create(&temp_path).await?);
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.try_next().await? {
+            context.transfer.wait_if_paused().await;
+            context.transfer.throttle(chunk.len()).await;
+            file.write_all(&chunk).await?;
+            context.stats.record_chunk_transfer(chunk.len());
+            (context.on_progress)(context.stats.payload());
+        }
+        file.flush().await?;
+        drop(file);
+    } else {
+        let data = response.bytes().await?;
+        context.stats.record_chunk_transfer(data.len());
+        (context.on_progress)(context.stats.payload());
+        let local = tokio::fs::read(&local_path).await.ok();
+        let data = merge_keys(&data, local.as_deref(), device_keys)?;
+        tokio::fs::write(&temp_path, data).await?;
+    }
+    commit_download(&temp_path, &file_path).await?;
+
+    let local = local_file(local_path.clone())
+        .ok_or_else(|| Error::InvalidResponse(format!("{path} vanished after download")))?;
+    Ok(Some(SyncedFile {
+        version: remote.version(),
+        etag,
+        size: local.size,
+        modified: local.modified,
+    }))
+}
+
+async fn delete_local(roots: &[SyncRoot], local: &LocalFile) -> Result<()> {
+    tokio::fs::remove_file(&local.path).await?;
+    // Drop the folder of a book once its last file is gone; fails harmlessly while it is not empty
+    if let Some(parent) = local.path.parent() {
+        if roots.iter().all(|root| root.local != parent) {
+            let _ = tokio::fs::remove_dir(parent).await;
+        }
+    }
+    Ok(())
+}
+
+// Maps a path on the server to the local file, or None if it is outside of the synced folders.
+// Paths come from the server, so every segment has to be a plain file or folder name; on
+// Windows a segment such as `..\x` or `C:\x` would otherwise escape the folder.
+fn local_path(roots: &[SyncRoot], path: &str) -> Option<PathBuf> {
+    fn is_file_name(segment: &str) -> bool {
+        let mut components = Path::new(segment).components();
+        !segment.contains(['\\', ':'])
+            && matches!(components.next(), Some(Component::Normal(_)))
+            && components.next().is_none()
+    }
+
+    roots.iter().find_map(|root| {
+        if root.remote == path {
+            return Some(root.local.clone());
+        }
+        let relative = path.strip_prefix(root.remote)?.strip_prefix('/')?;
+        if !root.is_dir || !relative.split('/').all(is_file_name) {
+            return None;
+        }
+        let local = relative
+            .split('/')
+            .fold(root.local.clone(), |dir, segment| dir.join(segment));
+        local.starts_with(&root.local).then_some(local)
+    })
+}
+
+fn device_keys<'a>(roots: &'a [SyncRoot], path: &str) -> &'a [&'static str] {
+    roots
+        .iter()
+        .find(|root| !root.is_dir && root.remote == path)
+        .map_or(&[], |root| root.device_keys)
+}
+
+// Returns the JSON object in `data` without the keys of this device.
+fn strip_keys(data: &[u8], keys: &[&str]) -> Result<Vec<u8>> {
+    let mut value: serde_json::Value = serde_json::from_slice(data)?;
+    if let Some(object) = value.as_object_mut() {
+        for key in keys {
+            object.remove(*key);
+        }
+    }
+    Ok(serde_json::to_vec(&value)?)
+}
+
+// Returns the JSON object from the server in `remote` with the keys of this device taken from
+// the `local` file, if there is a readable one.
+fn merge_keys(remote: &[u8], local: Option<&[u8]>, keys: &[&str]) -> Result<Vec<u8>> {
+    let mut value: serde_json::Value = serde_json::from_slice(remote)?;
+    let local = local
+        .and_then(|data| serde_json::from_slice::<serde_json::Value>(data).ok())
+        .and_then(|local| match local {
+            serde_json::Value::Object(object) => Some(object),
+            _ => None,
+        })
+        .unwrap_or_default();
+    if let Some(object) = value.as_object_mut() {
+        for key in keys {
+            match local.get(*key) {
+                Some(local) => object.insert(key.to_string(), local.clone()),
+                None => object.remove(*key),
+            };
+        }
+    }
+    Ok(serde_json::to_vec(&value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roots() -> Vec<SyncRoot> {
+        vec![
+            SyncRoot {
+                remote: "Readest/Books",
+                local: PathBuf::from("/data/Readest/Books"),
+                is_dir: true,
+                device_keys: &[],
+            },
+            SyncRoot {
+                remote: "Readest/settings.json",
+                local: PathBuf::from("/config/settings.json"),
+                is_dir: false,
+                device_keys: DEVICE_SETTINGS,
+            },
+        ]
+    }
+
+    fn local(size: u64, modified: u64) -> LocalFile {
+        LocalFile {
+            path: PathBuf::from("/data/Readest/Books/abc/config.json"),
+            size,
+            modified,
+        }
+    }
+
+    fn remote(etag: &str, last_modified: &str) -> DavEntry {
+        DavEntry {
+            etag: Some(etag.to_string()),
+            last_modified: Some(last_modified.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn synced(etag: &str, size: u64, modified: u64) -> SyncedFile {
+        SyncedFile {
+            version: etag.to_string(),
+            etag: Some(etag.to_string()),
+            size,
+            modified,
+        }
+    }
+
+    #[test]
+    fn maps_server_paths_into_roots() {
+        let roots = roots();
+        assert_eq!(
+            local_path(&roots, "Readest/Books/abc/config.json"),
+            Some(PathBuf::from("/data/Readest/Books/abc/config.json"))
+        );
+        assert_eq!(
+            local_path(&roots, "Readest/settings.json"),
+            Some(PathBuf::from("/config/settings.json"))
+        );
+        assert_eq!(local_path(&roots, "Readest/other.json"), None);
+        assert_eq!(local_path(&roots, "Readest/settings.json/x"), None);
+        assert_eq!(local_path(&roots, "Readest/BooksX/a"), None);
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        let roots = roots();
+        for path in [
+            "Readest/Books/../settings.json",
+            "Readest/Books/abc/../../x",
+            "Readest/Books/./x",
+            "Readest/Books//x",
+            "Readest/Books/",
+            "Readest/Books/..\\..\\x",
+            "Readest/Books/abc\\..\\..\\x",
+            "Readest/Books/C:\\Windows\\x",
+            "Readest/Books/C:x",
+            "Readest/Books/\\x",
+        ] {
+            assert_eq!(local_path(&roots, path), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn plans_one_sided_changes() {
+        let file = local(10, 1000);
+        let entry = remote("\"1\"", "Wed, 02 Apr 2025 12:30:00 GMT");
+        let same = synced("\"1\"", 10, 1000);
+
+        assert_eq!(plan(Some(&file), Some(&entry), Some(&same)), None);
+        assert_eq!(plan(Some(&file), None, None), Some((Action::Upload, false)));
+        assert_eq!(
+            plan(None, Some(&entry), None),
+            Some((Action::Download, false))
+        );
+        assert_eq!(
+            plan(Some(&local(11, 2000)), Some(&entry), Some(&same)),
+            Some((Action::Upload, false))
+        );
+        assert_eq!(
+            plan(Some(&file), Some(&remote("\"2\"", "")), Some(&same)),
+            Some((Action::Download, false))
+        );
+    }
+
+    #[test]
+    fn plans_deletions() {
+        let file = local(10, 1000);
+        let entry = remote("\"1\"", "");
+        let same = synced("\"1\"", 10, 1000);
+
+        assert_eq!(
+            plan(Some(&file), None, Some(&same)),
+            Some((Action::DeleteLocal, false))
+        );
+        assert_eq!(
+            plan(None, Some(&entry), Some(&same)),
+            Some((Action::DeleteRemote, false))
+        );
+        assert_eq!(plan(None, None, Some(&same)), Some((Action::Forget, false)));
+        // Deleted on one side but changed on the other: the change wins
+        assert_eq!(
+            plan(Some(&local(11, 2000)), None, Some(&same)),
+            Some((Action::Upload, false))
+        );
+        assert_eq!(
+            plan(None, Some(&remote("\"2\"", "")), Some(&same)),
+            Some((Action::Download, false))
+        );
+    }
+
+    #[test]
+    fn resolves_conflicts_by_modification_time() {
+        let same = synced("\"1\"", 10, 1000);
+        // 2025-04-02T12:30:00Z
+        let remote_millis = 1_743_597_000_000;
+        let entry = remote("\"2\"", "Wed, 02 Apr 2025 12:30:00 GMT");
+
+        assert_eq!(
+            plan(
+                Some(&local(11, remote_millis + 1)),
+                Some(&entry),
+                Some(&same)
+            ),
+            Some((Action::Upload, true))
+        );
+        assert_eq!(
+            plan(
+                Some(&local(11, remote_millis - 1)),
+                Some(&entry),
+                Some(&same)
+            ),
+            Some((Action::Download, true))
+        );
+        // Both created since the last sync
+        assert_eq!(
+            plan(Some(&local(11, remote_millis - 1)), Some(&entry), None),
+            Some((Action::Download, true))
+        );
+    }
+
+    #[test]
+    fn keeps_device_settings_local() {
+        let keys = ["httpClient", "localBooksDir"];
+        let local = br#"{"theme":"dark","httpClient":{"proxy":"socks5://proxy:1080"},"localBooksDir":"/home/me/Books"}"#;
+        let uploaded: serde_json::Value =
+            serde_json::from_slice(&strip_keys(local, &keys).unwrap()).unwrap();
+        assert_eq!(uploaded, serde_json::json!({ "theme": "dark" }));
+
+        let remote = br#"{"theme":"light","localBooksDir":"C:\\Books"}"#;
+        let merged: serde_json::Value =
+            serde_json::from_slice(&merge_keys(remote, Some(local), &keys).unwrap()).unwrap();
+        assert_eq!(
+            merged,
+            serde_json::json!({
+                "theme": "light",
+                "httpClient": { "proxy": "socks5://proxy:1080" },
+                "localBooksDir": "/home/me/Books",
+            })
+        );
+
+        let merged: serde_json::Value =
+            serde_json::from_slice(&merge_keys(remote, None, &keys).unwrap()).unwrap();
+        assert_eq!(merged, serde_json::json!({ "theme": "light" }));
+    }
+}
@@ -11,7 +11,7 @@ import { FaGithub } from 'react-icons/fa';
 import { IoArrowBack } from 'react-icons/io5';
 
 import { useAuth } from '@/context/AuthContext';
-import { supabase } from '@/utils/supabase';
+import { exchangeCodeWithVerifier, supabase } from '@/utils/supabase';
 import { useEnv } from '@/context/EnvContext';
 import { useTheme } from '@/hooks/useTheme';
 import { useThemeStore } from '@/store/themeStore';
@@ -20,11 +20,13 @@ import { useTranslation } from '@/hooks/useTranslation';
 import { useTrafficLightStore } from '@/store/trafficLightStore';
 import { getBaseUrl, isTauriAppPlatform } from '@/services/environment';
 import { onOpenUrl } from '@tauri-apps/plugin-deep-link';
-import { start, cancel, onUrl, onInvalidUrl } from '@fabianlars/tauri-plugin-oauth';
 import { openUrl } from '@tauri-apps/plugin-opener';
 import { invoke } from '@tauri-apps/api/core';
+import { UnlistenFn } from '@tauri-apps/api/event';
+import { getCurrentWebviewWindow } from '@tauri-apps/api/webviewWindow';
 import { handleAuthCallback } from '@/helpers/auth';
 import { getUserPlan } from '@/utils/access';
+import { getRuntimeConfig } from '@/utils/runtimeConfig';
 import { getAppleIdAuth, Scope } from './utils/appleIdAuth';
 import { authWithCustomTab, authWithSafari } from './utils/nativeAuth';
 import WindowButtons from '@/components/WindowButtons';
@@ -36,6 +38,20 @@ interface SingleInstancePayload {
   cwd: string;
 }
 
+interface OAuthLoopback {
+  port: number;
+  redirectUri: string;
+  codeChallenge: string;
+  codeChallengeMethod: string;
+}
+
+interface OAuthCallback {
+  code?: string | null;
+  codeVerifier?: string | null;
+  error?: string | null;
+  errorDescription?: string | null;
+}
+
 interface ProviderLoginProp {
   provider: OAuthProvider;
   handleSignIn: (provider: OAuthProvider) => void;
@@ -70,9 +86,10 @@ export default function AuthPage() {
   const { isDarkMode } = useThemeStore();
   const { isTrafficLightVisible } = useTrafficLightStore();
   const { settings, setSettings, saveSettings } = useSettingsStore();
-  const [port, setPort] = useState<number | null>(null);
+  const [loopback, setLoopback] = useState<OAuthLoopback | null>(null);
   const [isMounted, setIsMounted] = useState(false);
   const isOAuthServerRunning = useRef(false);
+  const unlistenOAuthCallback = useRef<UnlistenFn | null>(null);
   const useCustomeOAuth = useRef(false);
 
   const headerRef = useRef<HTMLDivElement>(null);
@@ -95,7 +112,7 @@ export default function AuthPage() {
     // For development env on Desktop, use a custom OAuth callback server
     // it's possible to register a custom URL scheme for the app
     // but this is not supported by macOS, so we use a local server instead
-    return `http://localhost:${port}`;
+    return loopback?.redirectUri ?? '';
   };
 
   const getWebRedirectTo = () => {
@@ -133,11 +150,21 @@ export default function AuthPage() {
       throw new Error('No backend connected');
     }
     supabase.auth.signOut();
+    const redirectTo = getTauriRedirectTo(true);
+    // The loopback server keeps the PKCE verifier, so only its challenge goes into the URL
+    const queryParams =
+      loopback && redirectTo === loopback.redirectUri
+        ? {
+            code_challenge: loopback.codeChallenge,
+            code_challenge_method: loopback.codeChallengeMethod,
+          }
+        : undefined;
     const { data, error } = await supabase.auth.signInWithOAuth({
       provider,
       options: {
         skipBrowserRedirect: true,
-        redirectTo: getTauriRedirectTo(true),
+        redirectTo,
+        queryParams,
       },
     });
 
@@ -162,6 +189,18 @@ export default function AuthPage() {
     }
   };
 
+  const handleOAuthTokens = (
+    accessToken: string,
+    refreshToken: string | null,
+    type: string | null,
+    next = '/',
+  ) => {
+    if (getUserPlan(accessToken) === 'free') {
+      next = '/user';
+    }
+    handleAuthCallback({ accessToken, refreshToken, type, next, login, navigate: router.push });
+  };
+
   const handleOAuthUrl = async (url: string) => {
     console.log('Handle OAuth URL:', url);
     const hashMatch = url.match(/#(.*)/);
@@ -169,14 +208,22 @@ export default function AuthPage() {
       const hash = hashMatch[1];
       const params = new URLSearchParams(hash);
       const accessToken = params.get('access_token');
-      const refreshToken = params.get('refresh_token');
-      const type = params.get('type');
       if (accessToken) {
-        let next = params.get('next') ?? '/';
-        if (getUserPlan(accessToken) === 'free') {
-          next = '/user';
-        }
-        handleAuthCallback({ accessToken, refreshToken, type, next, login, navigate: router.push });
+        const next = params.get('next') ?? '/';
+        handleOAuthTokens(accessToken, params.get('refresh_token'), params.get('type'), next);
+      }
+    }
+  };
+
+  const handleOAuthCallback = async ({ code, codeVerifier, error }: OAuthCallback) => {
+    if (error) {
+      console.error('OAuth sign-in failed:', error);
+    } else if (code && codeVerifier) {
+      try {
+        const { access_token, refresh_token } = await exchangeCodeWithVerifier(code, codeVerifier);
+        handleOAuthTokens(access_token, refresh_token, null);
+      } catch (error) {
+        console.error('OAuth code exchange failed:', error);
       }
     }
   };
@@ -202,14 +249,14 @@ export default function AuthPage() {
           });
         });
       } else {
-        const port = await start();
-        setPort(port);
-        console.log(`OAuth server started on port ${port}`);
-
-        await onUrl(handleOAuthUrl);
-        await onInvalidUrl((url) => {
-          console.log('Received invalid OAuth URL:', url);
-        });
+        // The callback is only emitted to the window that started the sign-in
+        unlistenOAuthCallback.current = await getCurrentWebviewWindow().listen<OAuthCallback>(
+          'oauth-callback',
+          (event) => handleOAuthCallback(event.payload),
+        );
+        const loopback = await invoke<OAuthLoopback>('start_oauth_loopback');
+        setLoopback(loopback);
+        console.log(`OAuth server started on port ${loopback.port}`);
       }
     } catch (error) {
       console.error('Error starting OAuth server:', error);
@@ -218,8 +265,10 @@ export default function AuthPage() {
 
   const stopTauriOAuth = async () => {
     try {
-      if (port) {
-        await cancel(port);
+      if (unlistenOAuthCallback.current) {
+        unlistenOAuthCallback.current();
+        unlistenOAuthCallback.current = null;
+        await invoke('cancel_oauth_loopback');
         console.log('OAuth server stopped');
       }
     } catch (error) {
@@ -295,8 +344,8 @@ export default function AuthPage() {
     if (isOAuthServerRunning.current) return;
     isOAuthServerRunning.current = true;
 
-    invoke('get_environment_variable', { name: 'USE_CUSTOM_OAUTH' }).then((value) => {
-      if (value === 'true') {
+    getRuntimeConfig().then((config) => {
+      if (config.useCustomOauth.value) {
         useCustomeOAuth.current = true;
       }
     });
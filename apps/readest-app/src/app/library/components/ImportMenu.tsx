@@ -2,6 +2,7 @@ import clsx from 'clsx';
 import { useEnv } from '@/context/EnvContext';
 import { useTranslation } from '@/hooks/useTranslation';
 import MenuItem from '@/components/MenuItem';
+import { setLanUploadWindowVisible } from './LanUploadWindow';
 
 interface ImportMenuProps {
   setIsDropdownOpen?: (open: boolean) => void;
@@ -17,6 +18,11 @@ const ImportMenu: React.FC<ImportMenuProps> = ({ setIsDropdownOpen, onImportBook
     setIsDropdownOpen?.(false);
   };
 
+  const handleLanUpload = () => {
+    setLanUploadWindowVisible(true);
+    setIsDropdownOpen?.(false);
+  };
+
   return (
     <ul
       tabIndex={-1}
@@ -26,6 +32,9 @@ const ImportMenu: React.FC<ImportMenuProps> = ({ setIsDropdownOpen, onImportBook
       )}
     >
       <MenuItem label={_('From Local File')} onClick={handleImportBooks} />
+      {appService?.hasWindow && (
+        <MenuItem label={_('From Another Device')} onClick={handleLanUpload} />
+      )}
     </ul>
   );
 };
@@ -0,0 +1,163 @@
+import React, { useEffect, useRef, useState } from 'react';
+import { getCurrentWindow } from '@tauri-apps/api/window';
+import Dialog from '@/components/Dialog';
+import { useTranslation } from '@/hooks/useTranslation';
+import { eventDispatcher } from '@/utils/event';
+import {
+  LanUploadFile,
+  LanUploadProgress,
+  UploadServerInfo,
+  getUploadServerInfo,
+  startUploadServer,
+  stopUploadServer,
+} from '@/helpers/lanUpload';
+
+export const setLanUploadWindowVisible = (visible: boolean) => {
+  const dialog = document.getElementById('lan_upload_window');
+  if (dialog) {
+    const event = new CustomEvent('setLanUploadVisibility', {
+      detail: { visible },
+    });
+    dialog.dispatchEvent(event);
+  }
+};
+
+interface LanUploadWindowProps {
+  onImportBooks: (files: string[]) => Promise<void>;
+}
+
+export const LanUploadWindow: React.FC<LanUploadWindowProps> = ({ onImportBooks }) => {
+  const _ = useTranslation();
+  const [isOpen, setIsOpen] = useState(false);
+  const [isStarting, setIsStarting] = useState(false);
+  const [info, setInfo] = useState<UploadServerInfo | null>(null);
+  const [progress, setProgress] = useState<LanUploadProgress | null>(null);
+  const importBooksRef = useRef(onImportBooks);
+  importBooksRef.current = onImportBooks;
+
+  useEffect(() => {
+    const handleCustomEvent = (event: CustomEvent) => {
+      setIsOpen(event.detail.visible);
+      if (event.detail.visible) {
+        getUploadServerInfo().then(setInfo);
+      }
+    };
+    const el = document.getElementById('lan_upload_window');
+    el?.addEventListener('setLanUploadVisibility', handleCustomEvent as EventListener);
+    return () => {
+      el?.removeEventListener('setLanUploadVisibility', handleCustomEvent as EventListener);
+    };
+  }, []);
+
+  // Received books are imported whether or not the dialog is open
+  useEffect(() => {
+    const currentWindow = getCurrentWindow();
+    if (currentWindow.label !== 'main') return;
+    const unlisteners = [
+      currentWindow.listen<LanUploadProgress>('lan-upload-progress', ({ payload }) => {
+        setProgress(payload);
+      }),
+      currentWindow.listen<LanUploadFile>('lan-upload-file', async ({ payload }) => {
+        setProgress(null);
+        await importBooksRef.current([payload.path]);
+        eventDispatcher.dispatch('toast', {
+          type: 'info',
+          timeout: 2000,
+          message: _('Book received: {{filename}}', { filename: payload.fileName }),
+        });
+      }),
+    ];
+    return () => {
+      unlisteners.forEach((unlisten) => unlisten.then((f) => f()));
+    };
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, []);
+
+  const handleStart = async () => {
+    setIsStarting(true);
+    try {
+      setInfo(await startUploadServer());
+    } catch (error) {
+      eventDispatcher.dispatch('toast', {
+        message: `${_('Failed to start the upload server')}: ${error}`,
+        type: 'error',
+      });
+    }
+    setIsStarting(false);
+  };
+
+  const handleStop = async () => {
+    await stopUploadServer();
+    setInfo(null);
+    setProgress(null);
+  };
+
+  const handleCopyUrl = () => {
+    if (!info) return;
+    navigator.clipboard?.writeText(info.url);
+    eventDispatcher.dispatch('toast', { message: _('Copied to clipboard'), type: 'info' });
+  };
+
+  return (
+    <Dialog
+      id='lan_upload_window'
+      isOpen={isOpen}
+      onClose={() => setIsOpen(false)}
+      title={_('Send Books from Another Device')}
+      boxClassName='sm:!min-w-[520px] sm:h-auto'
+      bgClassName='!bg-black/60'
+    >
+      <div className='mb-4 mt-0 flex flex-col items-center gap-4 p-2 sm:p-4'>
+        {info ? (
+          <>
+            <p className='text-base-content/70 text-center text-sm'>
+              {_('Scan the code or open the address in a browser on the same network.')}
+            </p>
+            <img
+              className='h-48 w-48 rounded-lg bg-white p-2'
+              src={`data:image/svg+xml;utf8,${encodeURIComponent(info.qrCode)}`}
+              alt={info.url}
+            />
+            <button
+              className='text-base-content/80 w-full break-all text-center font-mono text-sm'
+              title={_('Copy')}
+              onClick={handleCopyUrl}
+            >
+              {info.url}
+            </button>
+            {progress && (
+              <div className='w-full'>
+                <div className='text-base-content/70 mb-1 truncate text-xs'>
+                  {_('Receiving {{filename}}', { filename: progress.fileName })}
+                </div>
+                <progress
+                  className='progress progress-primary w-full'
+                  value={progress.total > 0 ? progress.received : undefined}
+                  max={progress.total > 0 ? progress.total : undefined}
+                />
+              </div>
+            )}
+            <button className='btn btn-outline mt-2 h-12 min-h-12 w-full' onClick={handleStop}>
+              {_('Stop Server')}
+            </button>
+          </>
+        ) : (
+          <>
+            <p className='text-base-content/70 text-center text-sm'>
+              {_(
+                'Start a web server to send books from a phone or another computer on the same network.',
+              )}
+            </p>
+            <button
+              className='btn btn-primary mt-2 h-12 min-h-12 w-full'
+              onClick={handleStart}
+              disabled={isStarting}
+            >
+              {isStarting ? <span className='loading loading-spinner'></span> : _('Start Server')}
+            </button>
+          </>
+        )}
+      </div>
+    </Dialog>
+  );
+};
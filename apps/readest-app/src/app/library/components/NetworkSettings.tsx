@@ -0,0 +1,190 @@
+import React, { useState, useEffect } from 'react';
+import Dialog from '@/components/Dialog';
+import { useTranslation } from '@/hooks/useTranslation';
+import { useSettingsStore } from '@/store/settingsStore';
+import { useEnv } from '@/context/EnvContext';
+import { eventDispatcher } from '@/utils/event';
+import { HttpClientConfig } from '@/types/settings';
+import { tauriGetHttpClientConfig, tauriSetHttpClientConfig } from '@/utils/httpClient';
+
+export const setNetworkSettingsWindowVisible = (visible: boolean) => {
+  const dialog = document.getElementById('network_settings_window');
+  if (dialog) {
+    const event = new CustomEvent('setNetworkSettingsVisibility', {
+      detail: { visible },
+    });
+    dialog.dispatchEvent(event);
+  }
+};
+
+const parseTimeout = (value: string) => {
+  const secs = parseInt(value, 10);
+  return secs > 0 ? secs : null;
+};
+
+export const NetworkSettingsWindow: React.FC = () => {
+  const _ = useTranslation();
+  const { settings, setSettings, saveSettings } = useSettingsStore();
+  const { envConfig } = useEnv();
+
+  const [isOpen, setIsOpen] = useState(false);
+  const [isSaving, setIsSaving] = useState(false);
+  const [proxy, setProxy] = useState('');
+  const [noProxy, setNoProxy] = useState('');
+  const [certificates, setCertificates] = useState('');
+  const [connectTimeout, setConnectTimeout] = useState('');
+  const [readTimeout, setReadTimeout] = useState('');
+  const [userAgent, setUserAgent] = useState('');
+
+  const loadConfig = (config: HttpClientConfig) => {
+    setProxy(config.proxy || '');
+    setNoProxy(config.noProxy || '');
+    setCertificates((config.extraRootCertificates || []).join('\n'));
+    setConnectTimeout(config.connectTimeoutSecs ? String(config.connectTimeoutSecs) : '');
+    setReadTimeout(config.readTimeoutSecs ? String(config.readTimeoutSecs) : '');
+    setUserAgent(config.userAgent || '');
+  };
+
+  useEffect(() => {
+    const handleCustomEvent = (event: CustomEvent) => {
+      setIsOpen(event.detail.visible);
+      if (event.detail.visible) {
+        // The config in use may differ from the settings if the saved one was invalid
+        tauriGetHttpClientConfig()
+          .then(loadConfig)
+          .catch(() => loadConfig(settings.httpClient || {}));
+      }
+    };
+    const el = document.getElementById('network_settings_window');
+    el?.addEventListener('setNetworkSettingsVisibility', handleCustomEvent as EventListener);
+    return () => {
+      el?.removeEventListener('setNetworkSettingsVisibility', handleCustomEvent as EventListener);
+    };
+  }, [settings.httpClient]);
+
+  const handleSave = async () => {
+    const config: HttpClientConfig = {
+      proxy: proxy.trim() || null,
+      noProxy: noProxy.trim() || null,
+      extraRootCertificates: certificates
+        .split('\n')
+        .map((path) => path.trim())
+        .filter(Boolean),
+      connectTimeoutSecs: parseTimeout(connectTimeout),
+      readTimeoutSecs: parseTimeout(readTimeout),
+      userAgent: userAgent.trim() || null,
+    };
+    setIsSaving(true);
+    try {
+      await tauriSetHttpClientConfig(config);
+      const newSettings = { ...settings, httpClient: config };
+      setSettings(newSettings);
+      await saveSettings(envConfig, newSettings);
+      eventDispatcher.dispatch('toast', { message: _('Network settings saved'), type: 'info' });
+      setIsOpen(false);
+    } catch (error) {
+      eventDispatcher.dispatch('toast', {
+        message: `${_('Failed to apply network settings')}: ${error}`,
+        type: 'error',
+      });
+    }
+    setIsSaving(false);
+  };
+
+  return (
+    <Dialog
+      id='network_settings_window'
+      isOpen={isOpen}
+      onClose={() => setIsOpen(false)}
+      title={_('Network Settings')}
+      boxClassName='sm:!min-w-[520px] sm:h-auto'
+      bgClassName='!bg-black/60'
+    >
+      <div className='mb-4 mt-0 flex flex-col gap-4 p-2 sm:p-4'>
+        <div className='form-control w-full'>
+          <label className='label py-1'>
+            <span className='label-text font-medium'>{_('Proxy URL')}</span>
+          </label>
+          <input
+            type='text'
+            placeholder='socks5://127.0.0.1:1080'
+            className='input input-bordered h-12 w-full focus:outline-none focus:ring-0'
+            value={proxy}
+            onChange={(e) => setProxy(e.target.value)}
+          />
+        </div>
+        <div className='form-control w-full'>
+          <label className='label py-1'>
+            <span className='label-text font-medium'>{_('Bypass Proxy For')}</span>
+          </label>
+          <input
+            type='text'
+            placeholder='localhost, .example.com'
+            className='input input-bordered h-12 w-full focus:outline-none focus:ring-0'
+            value={noProxy}
+            onChange={(e) => setNoProxy(e.target.value)}
+          />
+        </div>
+        <div className='form-control w-full'>
+          <label className='label py-1'>
+            <span className='label-text font-medium'>{_('Extra CA Certificates')}</span>
+          </label>
+          <textarea
+            placeholder={_('One PEM file path per line')}
+            className='textarea textarea-bordered w-full focus:outline-none focus:ring-0'
+            rows={2}
+            value={certificates}
+            onChange={(e) => setCertificates(e.target.value)}
+          />
+        </div>
+        <div className='flex gap-4'>
+          <div className='form-control w-full'>
+            <label className='label py-1'>
+              <span className='label-text font-medium'>{_('Connect Timeout (s)')}</span>
+            </label>
+            <input
+              type='number'
+              min='1'
+              placeholder='30'
+              className='input input-bordered h-12 w-full focus:outline-none focus:ring-0'
+              value={connectTimeout}
+              onChange={(e) => setConnectTimeout(e.target.value)}
+            />
+          </div>
+          <div className='form-control w-full'>
+            <label className='label py-1'>
+              <span className='label-text font-medium'>{_('Read Timeout (s)')}</span>
+            </label>
+            <input
+              type='number'
+              min='1'
+              placeholder='60'
+              className='input input-bordered h-12 w-full focus:outline-none focus:ring-0'
+              value={readTimeout}
+              onChange={(e) => setReadTimeout(e.target.value)}
+            />
+          </div>
+        </div>
+        <div className='form-control w-full'>
+          <label className='label py-1'>
+            <span className='label-text font-medium'>{_('User Agent')}</span>
+          </label>
+          <input
+            type='text'
+            placeholder='Readest'
+            className='input input-bordered h-12 w-full focus:outline-none focus:ring-0'
+            value={userAgent}
+            onChange={(e) => setUserAgent(e.target.value)}
+          />
+        </div>
+        <button
+          className='btn btn-primary mt-2 h-12 min-h-12 w-full'
+          onClick={handleSave}
+          disabled={isSaving}
+        >
+          {isSaving ? <span className='loading loading-spinner'></span> : _('Save')}
+        </button>
+      </div>
+    </Dialog>
+  );
+};
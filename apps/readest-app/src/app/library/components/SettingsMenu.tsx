@@ -9,6 +9,7 @@ import { BiMoon, BiSun } from 'react-icons/bi';
 
 import { setAboutDialogVisible } from '@/components/AboutWindow';
 import { setKOSyncSettingsWindowVisible } from './KOSyncSettings';
+import { setNetworkSettingsWindowVisible } from './NetworkSettings';
 import { isTauriAppPlatform, isWebAppPlatform } from '@/services/environment';
 import { DOWNLOAD_READEST_URL } from '@/services/constants';
 import { useAuth } from '@/context/AuthContext';
@@ -19,7 +20,11 @@ import { useSettingsStore } from '@/store/settingsStore';
 import { useTranslation } from '@/hooks/useTranslation';
 import { useResponsiveSize } from '@/hooks/useResponsiveSize';
 import { navigateToLogin, navigateToProfile } from '@/utils/nav';
-import { tauriHandleSetAlwaysOnTop, tauriHandleToggleFullScreen } from '@/utils/window';
+import {
+  tauriHandleSetAlwaysOnTop,
+  tauriHandleToggleFullScreen,
+  tauriSetAppMenuVisible,
+} from '@/utils/window';
 import { optInTelemetry, optOutTelemetry } from '@/utils/telemetry';
 import UserAvatar from '@/components/UserAvatar';
 import MenuItem from '@/components/MenuItem';
@@ -100,6 +105,14 @@ const SettingsMenu: React.FC<SettingsMenuProps> = ({ setIsDropdownOpen }) => {
     setIsDropdownOpen?.(false);
   };
 
+  const toggleShowAppMenu = () => {
+    settings.showAppMenu = !settings.showAppMenu;
+    setSettings(settings);
+    saveSettings(envConfig, settings);
+    tauriSetAppMenuVisible(settings.showAppMenu);
+    setIsDropdownOpen?.(false);
+  };
+
   const toggleAlwaysShowStatusBar = () => {
     settings.alwaysShowStatusBar = !settings.alwaysShowStatusBar;
     setSettings(settings);
@@ -164,6 +177,11 @@ const SettingsMenu: React.FC<SettingsMenuProps> = ({ setIsDropdownOpen }) => {
     setIsDropdownOpen?.(false);
   };
 
+  const showNetworkSettingsWindow = () => {
+    setNetworkSettingsWindowVisible(true);
+    setIsDropdownOpen?.(false);
+  };
+
   const handleUpgrade = () => {
     navigateToProfile(router);
     setIsDropdownOpen?.(false);
@@ -249,6 +267,13 @@ const SettingsMenu: React.FC<SettingsMenuProps> = ({ setIsDropdownOpen }) => {
           onClick={toggleAlwaysOnTop}
         />
       )}
+      {appService?.hasWindow && !appService?.isMacOSApp && (
+        <MenuItem
+          label={_('Show Menu Bar')}
+          Icon={settings.showAppMenu ? MdCheck : undefined}
+          onClick={toggleShowAppMenu}
+        />
+      )}
       {appService?.isMobileApp && (
         <MenuItem
           label={_('Always Show Status Bar')}
@@ -275,6 +300,9 @@ const SettingsMenu: React.FC<SettingsMenuProps> = ({ setIsDropdownOpen }) => {
       />
       <hr className='border-base-200 my-1' />
       <MenuItem label={_('KOReader Sync')} onClick={showKoSyncSettingsWindow} />
+      {isTauriAppPlatform() && (
+        <MenuItem label={_('Network Settings')} onClick={showNetworkSettingsWindow} />
+      )}
       <hr className='border-base-200 my-1' />
       {user && userPlan === 'free' && !appService?.isIOSApp && (
         <MenuItem label={_('Upgrade to Readest Premium')} onClick={handleUpgrade} />
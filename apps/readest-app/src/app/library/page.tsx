@@ -9,7 +9,7 @@ import 'overlayscrollbars/overlayscrollbars.css';
 
 import { Book } from '@/types/book';
 import { AppService, DeleteAction } from '@/types/system';
-import { navigateToLogin, navigateToReader } from '@/utils/nav';
+import { navigateToLogin, navigateToReader, showReaderWindow } from '@/utils/nav';
 import {
   formatAuthors,
   formatTitle,
@@ -20,7 +20,7 @@ import {
 import { eventDispatcher } from '@/utils/event';
 import { ProgressPayload } from '@/utils/transfer';
 import { throttle } from '@/utils/throttle';
-import { parseOpenWithFiles } from '@/helpers/openWith';
+import { getOpenWithLocationParams, parseOpenWithFiles } from '@/helpers/openWith';
 import { isTauriAppPlatform, isWebAppPlatform } from '@/services/environment';
 import { checkForAppUpdates, checkAppReleaseNotes } from '@/helpers/updater';
 import { FILE_ACCEPT_FORMATS, SUPPORTED_FILE_EXTS } from '@/services/constants';
@@ -40,15 +40,21 @@ import { useBooksSync } from './hooks/useBooksSync';
 import { useSafeAreaInsets } from '@/hooks/useSafeAreaInsets';
 import { useScreenWakeLock } from '@/hooks/useScreenWakeLock';
 import { useOpenWithBooks } from '@/hooks/useOpenWithBooks';
+import { useDeepLinks } from '@/hooks/useDeepLinks';
+import { useAppMenu } from '@/hooks/useAppMenu';
 import { lockScreenOrientation } from '@/utils/bridge';
 import {
   tauriHandleSetAlwaysOnTop,
   tauriHandleToggleFullScreen,
   tauriQuitApp,
+  tauriSetAppMenuVisible,
+  tauriSetRecentBooks,
 } from '@/utils/window';
 
 import { AboutWindow } from '@/components/AboutWindow';
 import { KOSyncSettingsWindow } from './components/KOSyncSettings';
+import { NetworkSettingsWindow } from './components/NetworkSettings';
+import { LanUploadWindow } from './components/LanUploadWindow';
 import { UpdaterWindow } from '@/components/UpdaterWindow';
 import { BookMetadata } from '@/libs/document';
 import { BookDetailModal } from '@/components/metadata';
@@ -91,6 +97,7 @@ const LibraryPageContent = ({ searchParams }: { searchParams: ReadonlyURLSearchP
     [key: string]: number | null;
   }>({});
   const [pendingNavigationBookIds, setPendingNavigationBookIds] = useState<string[] | null>(null);
+  const pendingNavigationParams = useRef('');
   const [isDragging, setIsDragging] = useState(false);
   const demoBooks = useDemoBooks();
   const osRef = useRef<OverlayScrollbarsComponentRef>(null);
@@ -101,6 +108,7 @@ const LibraryPageContent = ({ searchParams }: { searchParams: ReadonlyURLSearchP
   useUICSS();
 
   useOpenWithBooks();
+  useDeepLinks();
 
   const { pullLibrary, pushLibrary } = useBooksSync({
     onSyncStart: () => setLoading(true),
@@ -123,6 +131,18 @@ const LibraryPageContent = ({ searchParams }: { searchParams: ReadonlyURLSearchP
     },
   });
 
+  useAppMenu({
+    importBooks: () => handleImportBooks(),
+    openRecent: ({ bookId }) => {
+      if (appService?.hasWindow && settings.openBookInNewWindow) {
+        showReaderWindow(appService, [bookId]);
+      } else {
+        navigateToReader(router, [bookId]);
+      }
+    },
+    toggleFullscreen: () => tauriHandleToggleFullScreen(),
+  });
+
   useEffect(() => {
     const doCheckAppUpdates = async () => {
       if (appService?.hasUpdater && settings.autoCheckUpdates) {
@@ -134,6 +154,9 @@ const LibraryPageContent = ({ searchParams }: { searchParams: ReadonlyURLSearchP
     if (settings.alwaysOnTop) {
       tauriHandleSetAlwaysOnTop(settings.alwaysOnTop);
     }
+    if (appService?.hasWindow) {
+      tauriSetAppMenuVisible(!!settings.showAppMenu);
+    }
     doCheckAppUpdates();
     // eslint-disable-next-line react-hooks/exhaustive-deps
   }, [appService?.hasUpdater, settings]);
@@ -251,6 +274,16 @@ const LibraryPageContent = ({ searchParams }: { searchParams: ReadonlyURLSearchP
     // eslint-disable-next-line react-hooks/exhaustive-deps
   }, [pageRef.current]);
 
+  useEffect(() => {
+    if (!appService?.hasWindow || !libraryLoaded) return;
+    const recentBooks = libraryBooks
+      .filter((book) => !book.deletedAt)
+      .sort((a, b) => b.updatedAt - a.updatedAt)
+      .slice(0, 10)
+      .map((book) => ({ id: book.hash, title: book.title }));
+    tauriSetRecentBooks(recentBooks);
+  }, [appService, libraryBooks, libraryLoaded]);
+
   useEffect(() => {
     if (!libraryBooks.some((book) => !book.deletedAt)) {
       handleSetSelectMode(false);
@@ -279,6 +312,7 @@ const LibraryPageContent = ({ searchParams }: { searchParams: ReadonlyURLSearchP
 
       console.log('Opening books:', bookIds);
       if (bookIds.length > 0) {
+        pendingNavigationParams.current = getOpenWithLocationParams();
         setPendingNavigationBookIds(bookIds);
         return true;
       }
@@ -303,6 +337,7 @@ const LibraryPageContent = ({ searchParams }: { searchParams: ReadonlyURLSearchP
     }
     console.log('Opening last books:', bookIds);
     if (bookIds.length > 0) {
+      pendingNavigationParams.current = '';
       setPendingNavigationBookIds(bookIds);
       return true;
     }
@@ -314,7 +349,7 @@ const LibraryPageContent = ({ searchParams }: { searchParams: ReadonlyURLSearchP
       const bookIds = pendingNavigationBookIds;
       setPendingNavigationBookIds(null);
       if (bookIds.length > 0) {
-        navigateToReader(router, bookIds);
+        navigateToReader(router, bookIds, pendingNavigationParams.current);
       }
     }
   }, [pendingNavigationBookIds, appService, router]);
@@ -755,6 +790,8 @@ const LibraryPageContent = ({ searchParams }: { searchParams: ReadonlyURLSearchP
       )}
       <AboutWindow />
       <KOSyncSettingsWindow />
+      {isTauriAppPlatform() && <NetworkSettingsWindow />}
+      {appService?.hasWindow && <LanUploadWindow onImportBooks={importBooks} />}
       <UpdaterWindow />
       <Toast />
     </div>
@@ -1,4 +1,5 @@
 import React, { useEffect, useRef, useState } from 'react';
+import { useSearchParams } from 'next/navigation';
 import { BookDoc, getDirection } from '@/libs/document';
 import { BookConfig } from '@/types/book';
 import { FoliateView, wrappedFoliateView } from '@/types/view';
@@ -57,6 +58,7 @@ const FoliateViewer: React.FC<{
   config: BookConfig;
   contentInsets: Insets;
 }> = ({ bookKey, bookDoc, config, contentInsets: insets }) => {
+  const searchParams = useSearchParams();
   const { getView, setView: setFoliateView, setProgress } = useReaderStore();
   const { getViewSettings, setViewSettings } = useReaderStore();
   const { getParallels } = useParallelViewStore();
@@ -284,8 +286,15 @@ const FoliateViewer: React.FC<{
       view.renderer.setAttribute('max-block-size', `${maxBlockSize}px`);
       applyMarginAndGap();
 
-      const lastLocation = config.location;
-      if (lastLocation) {
+      // A location requested on the command line applies when a single book is opened
+      const isRequested = searchParams?.get('ids') === bookKey.split('-')[0];
+      const requestedCfi = isRequested ? searchParams?.get('cfi') : null;
+      const requestedPage = isRequested ? Number(searchParams?.get('page')) : 0;
+      const lastLocation = requestedCfi || config.location;
+      if (!requestedCfi && requestedPage > 0) {
+        // Pages of fixed layout books are their sections
+        await view.goTo(requestedPage - 1);
+      } else if (lastLocation) {
         await view.init({ lastLocation });
       } else {
         await view.goToFraction(0);
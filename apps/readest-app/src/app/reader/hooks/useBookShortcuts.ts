@@ -9,6 +9,7 @@ import { eventDispatcher } from '@/utils/event';
 import { MAX_ZOOM_LEVEL, MIN_ZOOM_LEVEL, ZOOM_STEP } from '@/services/constants';
 import { viewPagination } from './usePagination';
 import useShortcuts from '@/hooks/useShortcuts';
+import { useAppMenu } from '@/hooks/useAppMenu';
 import useBooksManager from './useBooksManager';
 
 interface UseBookShortcutsProps {
@@ -169,6 +170,13 @@ const useBookShortcuts = ({ sideBarBookKey, bookKeys }: UseBookShortcutsProps) =
     },
     [sideBarBookKey, bookKeys],
   );
+
+  useAppMenu({
+    toggleFullscreen,
+    zoomIn,
+    zoomOut,
+    resetZoom,
+  });
 };
 
 export default useBookShortcuts;
@@ -4,6 +4,7 @@ import { useEffect } from 'react';
 import { useEnv } from '@/context/EnvContext';
 import { useTranslation } from '@/hooks/useTranslation';
 import { useOpenWithBooks } from '@/hooks/useOpenWithBooks';
+import { useDeepLinks } from '@/hooks/useDeepLinks';
 import { useSettingsStore } from '@/store/settingsStore';
 import { checkForAppUpdates, checkAppReleaseNotes } from '@/helpers/updater';
 import Reader from './components/Reader';
@@ -15,6 +16,7 @@ export default function Page() {
   const { settings } = useSettingsStore();
 
   useOpenWithBooks();
+  useDeepLinks();
 
   useEffect(() => {
     const doCheckAppUpdates = async () => {
@@ -0,0 +1,31 @@
+import { invoke } from '@tauri-apps/api/core';
+import { Book } from '@/types/book';
+import { AppService } from '@/types/system';
+
+// An import or open request from the command line, see `readest help`.
+export interface CliRequest {
+  command: 'import' | 'open';
+  files: string[];
+  bookId?: string | null;
+  cfi?: string | null;
+  page?: number | null;
+}
+
+// Requests that arrived before the app was ready to handle them.
+export const takeCliRequests = async () => {
+  return await invoke<CliRequest[]>('take_cli_requests');
+};
+
+export const importCliFiles = async (appService: AppService, files: string[], library: Book[]) => {
+  let imported = 0;
+  for (const file of files) {
+    try {
+      const book = await appService.importBook(file, library);
+      if (book) imported++;
+    } catch (error) {
+      console.error('Failed to import book:', file, error);
+    }
+  }
+  await appService.saveLibraryBooks(library);
+  return imported;
+};
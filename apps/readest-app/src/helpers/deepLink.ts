@@ -0,0 +1,31 @@
+import { invoke } from '@tauri-apps/api/core';
+import { Book, BookNote } from '@/types/book';
+import { AppService } from '@/types/system';
+
+// A `readest://` link, validated by the app, see deep_link.rs.
+export type DeepLink =
+  | { action: 'open'; bookId: string; cfi?: string | null }
+  | { action: 'import'; url: string }
+  | { action: 'annotation'; id: string; bookId?: string | null };
+
+// Links that arrived before the app was ready to handle them.
+export const takeDeepLinks = async () => {
+  return await invoke<DeepLink[]>('take_deep_links');
+};
+
+// Finds a highlight or note by id, in the given book or else in the whole library.
+export const findAnnotation = async (
+  appService: AppService,
+  library: Book[],
+  id: string,
+  bookId?: string | null,
+) => {
+  const settings = await appService.loadSettings();
+  const books = library.filter((book) => !book.deletedAt && (!bookId || book.hash === bookId));
+  for (const book of books) {
+    const config = await appService.loadBookConfig(book, settings);
+    const note = config.booknotes?.find((note: BookNote) => note.id === id && !note.deletedAt);
+    if (note) return { book, note };
+  }
+  return null;
+};
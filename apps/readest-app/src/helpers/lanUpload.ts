@@ -0,0 +1,33 @@
+import { invoke } from '@tauri-apps/api/core';
+
+// The web server for sending books from other devices, see upload_server.rs.
+export interface UploadServerInfo {
+  url: string;
+  port: number;
+  token: string;
+  importDir: string;
+  qrCode: string; // SVG image of the URL
+}
+
+export interface LanUploadProgress {
+  fileName: string;
+  received: number;
+  total: number;
+}
+
+export interface LanUploadFile {
+  fileName: string;
+  path: string;
+}
+
+export const startUploadServer = async () => {
+  return await invoke<UploadServerInfo>('start_upload_server');
+};
+
+export const stopUploadServer = async () => {
+  await invoke('stop_upload_server');
+};
+
+export const getUploadServerInfo = async () => {
+  return await invoke<UploadServerInfo | null>('get_upload_server_info');
+};
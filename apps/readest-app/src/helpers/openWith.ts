@@ -1,38 +1,62 @@
-import { isWebAppPlatform, hasCli } from '@/services/environment';
+import { invoke } from '@tauri-apps/api/core';
 import { getCurrent } from '@tauri-apps/plugin-deep-link';
+import { isWebAppPlatform } from '@/services/environment';
+import { BookFormat } from '@/types/book';
 
-declare global {
-  interface Window {
-    OPEN_WITH_FILES?: string[] | null;
-  }
+// A file to open, optionally at a location.
+export interface OpenWithFile {
+  path: string;
+  cfi?: string | null;
+  page?: number | null; // 1-based, used when there is no CFI
 }
 
-interface CliArgument {
-  value: string;
-  occurrences: number;
+// A file handed to the desktop app, after its format has been detected from its content.
+export interface OpenFile extends OpenWithFile {
+  format: BookFormat | 'TXT';
 }
 
-const parseWindowOpenWithFiles = () => {
-  const params = new URLSearchParams(window.location.search);
-  const files = params.getAll('file');
-  return files.length > 0 ? files : window.OPEN_WITH_FILES;
+// A file that was not opened because its content is not a supported book.
+export interface RejectedFile {
+  path: string;
+  reason: string;
+}
+
+interface PendingOpenFiles {
+  files: OpenFile[];
+  rejected: RejectedFile[];
+}
+
+// The files that the app was last asked to open, for the library page to pick up.
+let openWithFiles: OpenWithFile[] = [];
+
+export const setOpenWithFiles = (files: OpenWithFile[]) => {
+  openWithFiles = files;
 };
 
-const parseCLIOpenWithFiles = async () => {
-  const { getMatches } = await import('@tauri-apps/plugin-cli');
-  const matches = await getMatches();
-  const args = matches?.args;
-  const files: string[] = [];
-  if (args) {
-    for (const name of ['file1', 'file2', 'file3', 'file4']) {
-      const arg = args[name] as CliArgument;
-      if (arg && arg.occurrences > 0) {
-        files.push(arg.value);
-      }
-    }
+// Files that arrived on desktop before the app was listening for `open-files` events.
+export const takePendingOpens = async () => {
+  return await invoke<PendingOpenFiles>('take_pending_opens');
+};
+
+// The query parameters to open a single book at its requested location.
+export const getOpenWithLocationParams = (files: OpenWithFile[] = parseWindowOpenWithFiles()) => {
+  const params = new URLSearchParams();
+  const [file] = files;
+  if (files.length === 1 && file?.cfi) {
+    params.set('cfi', file.cfi);
+  } else if (files.length === 1 && file?.page) {
+    params.set('page', String(file.page));
   }
+  return params.toString();
+};
 
-  return files;
+// Files passed to a new library window in its URL, or handed to this one.
+const parseWindowOpenWithFiles = (): OpenWithFile[] => {
+  const params = new URLSearchParams(window.location.search);
+  const paths = params.getAll('file');
+  if (paths.length === 0) return openWithFiles;
+  const page = Number(params.get('page')) || null;
+  return paths.map((path) => ({ path, cfi: params.get('cfi'), page }));
 };
 
 const parseIntentOpenWithFiles = async () => {
@@ -58,10 +82,7 @@ const parseIntentOpenWithFiles = async () => {
 export const parseOpenWithFiles = async () => {
   if (isWebAppPlatform()) return [];
 
-  let files = parseWindowOpenWithFiles();
-  if ((!files || files.length === 0) && hasCli()) {
-    files = await parseCLIOpenWithFiles();
-  }
+  let files: string[] | null = parseWindowOpenWithFiles().map((file) => file.path);
   if (!files || files.length === 0) {
     files = await parseIntentOpenWithFiles();
   }
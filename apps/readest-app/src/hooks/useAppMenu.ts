@@ -0,0 +1,36 @@
+import { useEffect, useRef } from 'react';
+import { getCurrentWindow } from '@tauri-apps/api/window';
+import { useEnv } from '@/context/EnvContext';
+
+// An item of the app menu that was chosen, see menu.rs.
+export type MenuAction =
+  | { action: 'importBooks' }
+  | { action: 'openRecent'; bookId: string }
+  | { action: 'toggleFullscreen' }
+  | { action: 'zoomIn' }
+  | { action: 'zoomOut' }
+  | { action: 'resetZoom' };
+
+export type MenuActionHandlers = {
+  [A in MenuAction as A['action']]?: (action: A) => void;
+};
+
+// Handles the app menu actions forwarded to the current window.
+export const useAppMenu = (handlers: MenuActionHandlers) => {
+  const { appService } = useEnv();
+  const handlersRef = useRef(handlers);
+  handlersRef.current = handlers;
+
+  useEffect(() => {
+    if (!appService?.hasWindow) return;
+    const unlisten = getCurrentWindow().listen<MenuAction>('menu-action', ({ payload }) => {
+      const handler = handlersRef.current[payload.action] as
+        | ((action: MenuAction) => void)
+        | undefined;
+      handler?.(payload);
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [appService]);
+};
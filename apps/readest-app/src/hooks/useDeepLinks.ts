@@ -0,0 +1,104 @@
+import { useEffect, useRef } from 'react';
+import { useRouter } from 'next/navigation';
+import { getCurrentWindow } from '@tauri-apps/api/window';
+import { ask } from '@tauri-apps/plugin-dialog';
+import { useEnv } from '@/context/EnvContext';
+import { useLibraryStore } from '@/store/libraryStore';
+import { isTauriAppPlatform } from '@/services/environment';
+import { useTranslation } from '@/hooks/useTranslation';
+import { eventDispatcher } from '@/utils/event';
+import { navigateToReader } from '@/utils/nav';
+import { DeepLink, findAnnotation, takeDeepLinks } from '@/helpers/deepLink';
+
+export function useDeepLinks() {
+  const _ = useTranslation();
+  const router = useRouter();
+  const { appService } = useEnv();
+  const { setLibrary, setCheckLastOpenBooks } = useLibraryStore();
+  const listenedDeepLinks = useRef(false);
+
+  const loadLibrary = async () => {
+    const { library } = useLibraryStore.getState();
+    return library.length > 0 ? library : await appService!.loadLibraryBooks();
+  };
+
+  const openBook = (bookId: string, cfi?: string | null) => {
+    setCheckLastOpenBooks(false);
+    const params = cfi ? new URLSearchParams({ cfi }).toString() : '';
+    navigateToReader(router, [bookId], params);
+  };
+
+  const showError = (message: string) => {
+    eventDispatcher.dispatch('toast', { type: 'error', message, timeout: 5000 });
+  };
+
+  // Any web page can open a link, so nothing is downloaded before the user agreed to it
+  const confirmImport = async (url: string) => {
+    const host = new URL(url).host;
+    return await ask(_('Import the book at {{url}}?', { url }), {
+      title: _('Import from {{host}}', { host }),
+      kind: 'warning',
+      okLabel: _('Import'),
+      cancelLabel: _('Cancel'),
+    });
+  };
+
+  const handleDeepLink = async (link: DeepLink) => {
+    console.log('Handle deep link:', link);
+    if (!appService) return;
+    const library = await loadLibrary();
+    switch (link.action) {
+      case 'open':
+        if (!library.some((book) => book.hash === link.bookId && !book.deletedAt)) {
+          showError(_('The book is not in your library'));
+          return;
+        }
+        openBook(link.bookId, link.cfi);
+        break;
+      case 'import':
+        if (!(await confirmImport(link.url))) return;
+        try {
+          const book = await appService.importBook(link.url, library);
+          await appService.saveLibraryBooks(library);
+          setLibrary([...library]);
+          if (book) openBook(book.hash);
+        } catch (error) {
+          console.error('Failed to import book:', link.url, error);
+          showError(_('Failed to import book from {{url}}', { url: link.url }));
+        }
+        break;
+      case 'annotation': {
+        const found = await findAnnotation(appService, library, link.id, link.bookId);
+        if (!found) {
+          showError(_('The annotation is not in your library'));
+          return;
+        }
+        openBook(found.book.hash, found.note.cfi);
+        break;
+      }
+    }
+  };
+
+  useEffect(() => {
+    if (!isTauriAppPlatform() || !appService) return;
+    if (listenedDeepLinks.current) return;
+    // Links are only handled by the main window
+    if (getCurrentWindow().label !== 'main') return;
+    listenedDeepLinks.current = true;
+
+    const unlistenDeepLinks = getCurrentWindow().listen<DeepLink[]>(
+      'deep-links',
+      ({ payload }) => {
+        payload.forEach(handleDeepLink);
+      },
+    );
+    // Only take the pending links once later ones are sure to arrive as events
+    unlistenDeepLinks.then(() => {
+      takeDeepLinks().then((links) => links.forEach(handleDeepLink));
+    });
+    return () => {
+      unlistenDeepLinks.then((f) => f());
+    };
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [appService]);
+}
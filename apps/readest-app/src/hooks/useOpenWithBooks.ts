@@ -6,34 +6,78 @@ import { useSettingsStore } from '@/store/settingsStore';
 import { onOpenUrl } from '@tauri-apps/plugin-deep-link';
 import { getCurrentWindow } from '@tauri-apps/api/window';
 import { isTauriAppPlatform } from '@/services/environment';
-import { navigateToLibrary, showLibraryWindow } from '@/utils/nav';
-
-interface SingleInstancePayload {
-  args: string[];
-  cwd: string;
-}
+import { useTranslation } from '@/hooks/useTranslation';
+import { eventDispatcher } from '@/utils/event';
+import { getFilename } from '@/utils/book';
+import { CliRequest, importCliFiles, takeCliRequests } from '@/helpers/cli';
+import {
+  OpenFile,
+  OpenWithFile,
+  RejectedFile,
+  getOpenWithLocationParams,
+  setOpenWithFiles,
+  takePendingOpens,
+} from '@/helpers/openWith';
+import { navigateToLibrary, navigateToReader, showLibraryWindow } from '@/utils/nav';
 
 export function useOpenWithBooks() {
+  const _ = useTranslation();
   const router = useRouter();
   const { appService } = useEnv();
-  const { setCheckOpenWithBooks } = useLibraryStore();
+  const { setLibrary, setCheckOpenWithBooks, setCheckLastOpenBooks } = useLibraryStore();
   const listenedOpenWithBooks = useRef(false);
 
-  const handleOpenWithFileUrl = (url: string) => {
-    console.log('Handle Open with URL:', url);
-    let filePath = url;
-    if (filePath.startsWith('file://')) {
-      filePath = decodeURI(filePath.replace('file://', ''));
+  const handleOpenWithFiles = (files: OpenWithFile[]) => {
+    console.log('Handle Open with files:', files);
+    if (files.length === 0) return;
+    const settings = useSettingsStore.getState().settings;
+    if (appService?.hasWindow && settings.openBookInNewWindow) {
+      showLibraryWindow(
+        appService,
+        files.map((file) => file.path),
+        getOpenWithLocationParams(files),
+      );
+    } else {
+      setOpenWithFiles(files);
+      setCheckOpenWithBooks(true);
+      navigateToLibrary(router, `reload=${Date.now()}`);
+    }
+  };
+
+  const handleOpenWithFileUrls = (urls: string[]) => {
+    console.log('Handle Open with URLs:', urls);
+    const filePaths = urls
+      .map((url) => (url.startsWith('file://') ? decodeURI(url.replace('file://', '')) : url))
+      .filter((filePath) => !/^(https?:|data:|blob:|readest:)/i.test(filePath));
+    handleOpenWithFiles(filePaths.map((path) => ({ path })));
+  };
+
+  const handleRejectedFiles = (rejected: RejectedFile[]) => {
+    for (const { path, reason } of rejected) {
+      eventDispatcher.dispatch('toast', {
+        type: 'error',
+        message: _('Cannot open {{filename}}: {{reason}}', {
+          filename: getFilename(path),
+          reason,
+        }),
+        timeout: 5000,
+      });
     }
-    if (!/^(https?:|data:|blob:)/i.test(filePath)) {
-      const settings = useSettingsStore.getState().settings;
-      if (appService?.hasWindow && settings.openBookInNewWindow) {
-        showLibraryWindow(appService, [filePath]);
-      } else {
-        window.OPEN_WITH_FILES = [filePath];
-        setCheckOpenWithBooks(true);
-        navigateToLibrary(router, `reload=${Date.now()}`);
-      }
+  };
+
+  const handleCliRequest = async (request: CliRequest) => {
+    console.log('Handle CLI request:', request);
+    if (!appService) return;
+    if (request.command === 'import') {
+      if (request.files.length === 0) return;
+      const { library } = useLibraryStore.getState();
+      const books = library.length > 0 ? library : await appService.loadLibraryBooks();
+      await importCliFiles(appService, request.files, books);
+      setLibrary([...books]);
+    } else if (request.bookId) {
+      setCheckLastOpenBooks(false);
+      const location = { path: '', cfi: request.cfi, page: request.page };
+      navigateToReader(router, [request.bookId], getOpenWithLocationParams([location]));
     }
   };
 
@@ -42,24 +86,44 @@ export function useOpenWithBooks() {
     if (listenedOpenWithBooks.current) return;
     listenedOpenWithBooks.current = true;
 
-    const unlistenDeeplink = getCurrentWindow().listen('single-instance', ({ event, payload }) => {
-      console.log('Received deep link:', event, payload);
-      const { args } = payload as SingleInstancePayload;
-      if (args?.[1]) {
-        handleOpenWithFileUrl(args[1]);
-      }
-    });
     const listenOpenWithFiles = async () => {
       return await onOpenUrl((urls) => {
-        urls.forEach((url) => {
-          handleOpenWithFileUrl(url);
-        });
+        // Files opened on desktop arrive as open-files events, already checked by the app
+        if (appService.hasWindow) {
+          urls = urls.filter((url) => !url.startsWith('file://'));
+        }
+        handleOpenWithFileUrls(urls);
       });
     };
     const unlistenOpenUrl = listenOpenWithFiles();
+    // Files and command line requests on desktop are only handled by the main window
+    const isMainWindow = appService.hasWindow && getCurrentWindow().label === 'main';
+    const unlisteners = isMainWindow
+      ? [
+          getCurrentWindow().listen<OpenFile[]>('open-files', ({ payload }) => {
+            handleOpenWithFiles(payload);
+          }),
+          getCurrentWindow().listen<RejectedFile[]>('open-files-rejected', ({ payload }) => {
+            handleRejectedFiles(payload);
+          }),
+          getCurrentWindow().listen<CliRequest[]>('cli-requests', ({ payload }) => {
+            payload.forEach(handleCliRequest);
+          }),
+        ]
+      : [];
+    // Only take the pending items once later ones are sure to arrive as events
+    if (isMainWindow) {
+      Promise.all(unlisteners).then(async () => {
+        const { files, rejected } = await takePendingOpens();
+        handleOpenWithFiles(files);
+        handleRejectedFiles(rejected);
+        const requests = await takeCliRequests();
+        requests.forEach(handleCliRequest);
+      });
+    }
     return () => {
-      unlistenDeeplink.then((f) => f());
       unlistenOpenUrl.then((f) => f());
+      unlisteners.forEach((unlisten) => unlisten.then((f) => f()));
     };
     // eslint-disable-next-line react-hooks/exhaustive-deps
   }, [appService]);
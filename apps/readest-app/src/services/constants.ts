@@ -40,6 +40,7 @@ export const DEFAULT_SYSTEM_SETTINGS: Partial<SystemSettings> = {
   keepLogin: false,
   autoUpload: true,
   alwaysOnTop: false,
+  showAppMenu: true,
   openBookInNewWindow: true,
   alwaysShowStatusBar: false,
   autoCheckUpdates: true,
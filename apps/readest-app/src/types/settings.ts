@@ -9,6 +9,16 @@ export type LibraryCoverFitType = 'crop' | 'fit';
 export type KoreaderSyncChecksumMethod = 'binary' | 'filename';
 export type KoreaderSyncStrategy = 'prompt' | 'silent' | 'send' | 'receive' | 'disabled';
 
+// Configures the HTTP client used by transfers in the native app, see http_client.rs
+export interface HttpClientConfig {
+  proxy?: string | null;
+  noProxy?: string | null;
+  extraRootCertificates?: string[];
+  connectTimeoutSecs?: number | null;
+  readTimeoutSecs?: number | null;
+  userAgent?: string | null;
+}
+
 export interface ReadSettings {
   sideBarWidth: string;
   isSideBarPinned: boolean;
@@ -30,6 +40,7 @@ export interface SystemSettings {
   keepLogin: boolean;
   autoUpload: boolean;
   alwaysOnTop: boolean;
+  showAppMenu: boolean;
   openBookInNewWindow: boolean;
   autoCheckUpdates: boolean;
   screenWakeLock: boolean;
@@ -52,6 +63,8 @@ export interface SystemSettings {
   koreaderSyncStrategy: KoreaderSyncStrategy;
   koreaderSyncPercentageTolerance: number;
 
+  httpClient?: HttpClientConfig;
+
   lastSyncedAtBooks: number;
   lastSyncedAtConfigs: number;
   lastSyncedAtNotes: number;
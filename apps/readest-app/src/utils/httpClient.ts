@@ -0,0 +1,11 @@
+import { invoke } from '@tauri-apps/api/core';
+import { HttpClientConfig } from '@/types/settings';
+
+export const tauriGetHttpClientConfig = async () => {
+  return invoke<HttpClientConfig>('get_http_client_config');
+};
+
+// Rejects with a message if the proxy or a certificate is invalid, keeping the current config
+export const tauriSetHttpClientConfig = async (config: HttpClientConfig) => {
+  return invoke<void>('set_http_client_config', { config });
+};
@@ -3,10 +3,13 @@ import { WebviewWindow } from '@tauri-apps/api/webviewWindow';
 import { isPWA, isWebAppPlatform } from '@/services/environment';
 import { BOOK_IDS_SEPARATOR } from '@/services/constants';
 import { AppService } from '@/types/system';
+import { tauriOpenReaderWindow } from '@/utils/window';
 
-let readerWindowsCount = 0;
-const createReaderWindow = (appService: AppService, url: string) => {
-  const win = new WebviewWindow(`reader-${readerWindowsCount}`, {
+let windowsCount = 0;
+// Windows of books opened from Rust are labelled `reader-<hash>`, so the labels here must not
+// look like those.
+const createWindow = (appService: AppService, prefix: 'reader' | 'library', url: string) => {
+  const win = new WebviewWindow(`${prefix}-${windowsCount}`, {
     url,
     width: 800,
     height: 600,
@@ -20,29 +23,39 @@ const createReaderWindow = (appService: AppService, url: string) => {
   });
   win.once('tauri://created', () => {
     console.log('new window created');
-    readerWindowsCount += 1;
+    windowsCount += 1;
   });
   win.once('tauri://error', (e) => {
     console.error('error creating window', e);
   });
   win.once('tauri://destroyed', () => {
-    readerWindowsCount -= 1;
+    windowsCount -= 1;
   });
 };
 
+// A single book gets its own window, or the one it is already open in, while several books
+// are opened together in a new window, as with the reader's parallel view.
 export const showReaderWindow = (appService: AppService, bookIds: string[]) => {
-  const ids = bookIds.join(BOOK_IDS_SEPARATOR);
-  const params = new URLSearchParams('');
-  params.set('ids', ids);
-  const url = `/reader?${params.toString()}`;
-  createReaderWindow(appService, url);
+  if (bookIds.length === 1) {
+    tauriOpenReaderWindow(bookIds[0]!).catch((error) => {
+      console.error('Failed to open reader window:', bookIds[0], error);
+    });
+    return;
+  }
+  const params = new URLSearchParams();
+  params.set('ids', bookIds.join(BOOK_IDS_SEPARATOR));
+  createWindow(appService, 'reader', `/reader?${params.toString()}`);
 };
 
-export const showLibraryWindow = (appService: AppService, filenames: string[]) => {
-  const params = new URLSearchParams();
+export const showLibraryWindow = (
+  appService: AppService,
+  filenames: string[],
+  queryParams?: string,
+) => {
+  const params = new URLSearchParams(queryParams || '');
   filenames.forEach((filename) => params.append('file', filename));
   const url = `/library?${params.toString()}`;
-  createReaderWindow(appService, url);
+  createWindow(appService, 'library', url);
 };
 
 export const navigateToReader = (
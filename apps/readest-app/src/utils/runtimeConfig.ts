@@ -0,0 +1,17 @@
+import { invoke } from '@tauri-apps/api/core';
+
+export type ConfigSource = 'default' | 'configFile' | 'environment' | 'commandLine';
+
+export interface ConfigValue<T> {
+  value: T;
+  source: ConfigSource;
+  name?: string | null;
+}
+
+export interface RuntimeConfig {
+  useCustomOauth: ConfigValue<boolean>;
+}
+
+export const getRuntimeConfig = async (): Promise<RuntimeConfig> => {
+  return await invoke<RuntimeConfig>('get_runtime_config');
+};
@@ -21,6 +21,21 @@ export const createSupabaseClient = (accessToken?: string) => {
   });
 };
 
+// Exchanges an authorization code for a session with a PKCE verifier that was not created by
+// the client, e.g. the one of the desktop OAuth loopback server.
+export const exchangeCodeWithVerifier = async (authCode: string, codeVerifier: string) => {
+  const response = await fetch(`${supabaseUrl}/auth/v1/token?grant_type=pkce`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json', apikey: supabaseAnonKey },
+    body: JSON.stringify({ auth_code: authCode, code_verifier: codeVerifier }),
+  });
+  const data = await response.json();
+  if (!response.ok) {
+    throw new Error(data.error_description || data.msg || `HTTP ${response.status}`);
+  }
+  return data as { access_token: string; refresh_token: string };
+};
+
 export const createSupabaseAdminClient = () => {
   const supabaseAdminKey = process.env['SUPABASE_ADMIN_KEY'] || '';
   return createClient(supabaseUrl, supabaseAdminKey, {
@@ -6,6 +6,8 @@ export interface ProgressPayload {
   progress: number;
   total: number;
   transferSpeed: number;
+  etaSecs?: number | null;
+  elapsedSecs?: number;
 }
 
 export type ProgressHandler = (progress: ProgressPayload) => void;
@@ -1,3 +1,4 @@
+import { invoke } from '@tauri-apps/api/core';
 import { getCurrentWindow } from '@tauri-apps/api/window';
 import { emitTo, TauriEvent } from '@tauri-apps/api/event';
 import { exit } from '@tauri-apps/plugin-process';
@@ -74,3 +75,33 @@ export const tauriQuitApp = async () => {
   await eventDispatcher.dispatch('quit-app');
   await exit(0);
 };
+
+export interface ReaderWindow {
+  label: string;
+  bookId: string;
+  title: string;
+  focused: boolean;
+}
+
+// Opens a book in its own `reader-<hash>` window, or focuses the window if it is already open.
+export const tauriOpenReaderWindow = async (bookId: string, location?: string | null) => {
+  return await invoke<ReaderWindow>('open_reader_window', { bookId, location });
+};
+
+export const tauriCloseReaderWindow = async (bookId: string) => {
+  return await invoke<boolean>('close_reader_window', { bookId });
+};
+
+export const tauriListReaderWindows = async () => {
+  return await invoke<ReaderWindow[]>('list_reader_windows');
+};
+
+// The books listed in File > Open Recent of the app menu.
+export const tauriSetRecentBooks = async (books: { id: string; title: string }[]) => {
+  await invoke('set_recent_books', { books });
+};
+
+// Shows or hides the menu bar of the windows on Linux and Windows.
+export const tauriSetAppMenuVisible = async (visible: boolean) => {
+  await invoke('set_app_menu_visible', { visible });
+};